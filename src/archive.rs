@@ -0,0 +1,1467 @@
+#[cfg(feature = "encryption")]
+use crate::EncryptionKey;
+use crate::{
+    cache::{CacheStats, DecodeCache},
+    format::{BlobChecksum, Footer, Header, FOOTER_LEN, HEADER_LEN},
+    looks_like_file, lowercase_paths, normalize_path, query,
+    read_at::ReadAt,
+    Asset, AssetIndexer, Encoding, MonolithicaError, Result,
+};
+#[cfg(feature = "std-fs")]
+use crate::{
+    error::IoContext,
+    read_at::{read_region_at, share_file, SharedFile},
+    read_region, shard_path,
+};
+use std::{
+    collections::HashMap,
+    io::{self, Read},
+    ops::Range,
+    path::PathBuf,
+    sync::OnceLock,
+};
+#[cfg(feature = "std-fs")]
+use std::{
+    fs::{self, File},
+    io::{Seek, SeekFrom},
+    path::Path,
+};
+
+pub(crate) enum Blob {
+    /// Read with positioned reads, so concurrent readers don't share a
+    /// cursor, or locked for each read on platforms without them.
+    #[cfg(feature = "std-fs")]
+    File(SharedFile),
+    #[cfg(feature = "mmap")]
+    Mmap(memmap2::Mmap),
+    /// A decoded compressed blob, header included so asset offsets apply as
+    /// is, or one built in memory.
+    Memory(Vec<u8>),
+    /// A blob embedded in the program, see [`Archive::from_bytes`].
+    Static(&'static [u8]),
+}
+
+impl Blob {
+    /// Loads the blob in `file`, decoding it into memory if it was created with
+    /// [`crate::ArchiveOptions::blob_compression`]. Also returns the range of
+    /// the blob that holds asset data.
+    #[cfg(feature = "std-fs")]
+    fn load(mut file: File, mmap: bool, keyed: bool) -> Result<(Self, Range<u64>)> {
+        let header = Header::read_for(&mut file, keyed)?;
+        if let Some(compression) = header.compression {
+            let mut encoded = Vec::new();
+            file.read_to_end(&mut encoded)?;
+            return Self::decode(compression, header.data_len, &encoded);
+        }
+        let data_range = HEADER_LEN..file.metadata()?.len();
+
+        #[cfg(feature = "mmap")]
+        if mmap {
+            // SAFETY: the archive only ever reads the mapping, and the caller is
+            // required not to modify the blob file while it is mapped.
+            let map = unsafe { memmap2::Mmap::map(&file)? };
+            return Ok((Blob::Mmap(map), data_range));
+        }
+        #[cfg(not(feature = "mmap"))]
+        let _ = mmap;
+
+        Ok((Blob::File(share_file(file)), data_range))
+    }
+
+    /// Same as [`Blob::load`] for a blob held in `bytes`.
+    fn from_bytes(bytes: &'static [u8]) -> Result<(Self, Range<u64>)> {
+        let data_range = HEADER_LEN..bytes.len() as u64;
+        let header = Header::read(&mut &bytes[..])?;
+        if let Some(compression) = header.compression {
+            return Self::decode(compression, header.data_len, &bytes[HEADER_LEN as usize..]);
+        }
+        Ok((Blob::Static(bytes), data_range))
+    }
+
+    /// Same as [`Blob::from_bytes`] for a blob owned by the archive, to be
+    /// read with a key if `keyed`.
+    fn from_vec(bytes: Vec<u8>, keyed: bool) -> Result<(Self, Range<u64>)> {
+        let data_range = HEADER_LEN..bytes.len() as u64;
+        let header = Header::read_for(&mut &bytes[..], keyed)?;
+        if let Some(compression) = header.compression {
+            return Self::decode(compression, header.data_len, &bytes[HEADER_LEN as usize..]);
+        }
+        Ok((Blob::Memory(bytes), data_range))
+    }
+
+    /// Decodes `encoded`, the data of a compressed blob, expected to be
+    /// `data_len` bytes once decoded. Stops decoding past them, so a corrupt
+    /// or hostile blob can't take up more memory than its header claims.
+    fn decode(compression: Encoding, data_len: u64, encoded: &[u8]) -> Result<(Self, Range<u64>)> {
+        let mut data = vec![0u8; HEADER_LEN as usize];
+        data.extend(compression.decode_limited(encoded, data_len)?);
+        if data.len() as u64 - HEADER_LEN > data_len {
+            tracing::error!("decoded blob longer than its header says");
+            return Err(MonolithicaError::CorruptArchive(format!(
+                "decoded blob is more than the {data_len} bytes the header says"
+            )));
+        }
+        if data.len() as u64 - HEADER_LEN != data_len {
+            tracing::error!("decoded blob length mismatch");
+            return Err(MonolithicaError::CorruptArchive(format!(
+                "decoded blob is {} bytes, header says {data_len}",
+                data.len() as u64 - HEADER_LEN,
+            )));
+        }
+        let data_range = HEADER_LEN..data.len() as u64;
+        Ok((Blob::Memory(data), data_range))
+    }
+
+    pub(crate) fn as_slice(&self) -> Option<&[u8]> {
+        match self {
+            #[cfg(feature = "std-fs")]
+            Blob::File(_) => None,
+            #[cfg(feature = "mmap")]
+            Blob::Mmap(map) => Some(map),
+            Blob::Memory(data) => Some(data),
+            Blob::Static(data) => Some(data),
+        }
+    }
+}
+
+impl ReadAt for Blob {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            #[cfg(feature = "std-fs")]
+            Blob::File(file) => file.read_at(offset, buf),
+            blob => blob.as_slice().unwrap().read_at(offset, buf),
+        }
+    }
+}
+
+/// An opened blob together with its index.
+///
+/// Archives are `Send + Sync` and every read names its offset, so one archive
+/// can be shared by all the handlers of a server behind an
+/// [`Arc`](std::sync::Arc) rather than opened per request:
+///
+/// ```no_run
+/// # #[cfg(not(feature = "std-fs"))]
+/// # fn main() {}
+/// # #[cfg(feature = "std-fs")]
+/// # fn main() -> monolithica::Result<()> {
+/// use monolithica::Archive;
+/// use std::{sync::Arc, thread};
+///
+/// let archive = Arc::new(Archive::open("assets.blob", "assets.blob.idx")?);
+/// let handles: Vec<_> = (0..4)
+///     .map(|_| {
+///         let archive = Arc::clone(&archive);
+///         thread::spawn(move || archive.get("index.html"))
+///     })
+///     .collect();
+/// for handle in handles {
+///     handle.join().unwrap()?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct Archive {
+    pub(crate) blob: Blob,
+    /// The shards after the first of a sharded blob, see [`Asset::shard`],
+    /// with the part of each that holds asset data.
+    pub(crate) shards: Vec<(Blob, Range<u64>)>,
+    /// Where the blob was opened from, to open it again for async reads.
+    #[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+    pub(crate) blob_path: Option<PathBuf>,
+    /// The part of the blob holding asset data.
+    pub(crate) data_range: Range<u64>,
+    pub(crate) assets: HashMap<String, Asset>,
+    /// See [`AssetIndexer::locate_asset_ci`].
+    lowercase: OnceLock<HashMap<String, String>>,
+    /// See [`Archive::with_decode_cache`].
+    cache: Option<DecodeCache>,
+    /// See [`Archive::nth`] and [`Archive::list_prefix`].
+    sorted: OnceLock<Vec<String>>,
+    /// The checksum of a single-file archive and the length of the part of
+    /// the file it covers, see [`Archive::verify_checksum`].
+    pub(crate) checksum: Option<(BlobChecksum, u64)>,
+    /// See [`Archive::open_with_key`].
+    #[cfg(feature = "encryption")]
+    key: Option<EncryptionKey>,
+}
+
+impl Archive {
+    #[cfg(feature = "std-fs")]
+    pub fn open(blob_path: impl AsRef<Path>, blob_index_path: impl AsRef<Path>) -> Result<Self> {
+        Self::open_with(blob_path.as_ref(), blob_index_path.as_ref(), false, false)
+    }
+
+    /// Opens an archive created with [`crate::ArchiveOptions::encryption_key`],
+    /// decrypting assets with `key` as they're read. Reads fail with
+    /// [`MonolithicaError::DecryptionFailed`] if the key is wrong or the
+    /// bytes of an asset were tampered with. Encrypted assets can't be
+    /// streamed or borrowed, only read whole or in ranges.
+    #[cfg(all(feature = "encryption", feature = "std-fs"))]
+    pub fn open_with_key(
+        blob_path: impl AsRef<Path>,
+        blob_index_path: impl AsRef<Path>,
+        key: EncryptionKey,
+    ) -> Result<Self> {
+        let mut archive =
+            Self::open_with(blob_path.as_ref(), blob_index_path.as_ref(), false, true)?;
+        archive.key = Some(key);
+        Ok(archive)
+    }
+
+    /// Same as [`Archive::open`], then [`Archive::check_bounds`], so an index
+    /// that doesn't match its blob fails at startup rather than on the first
+    /// read of a bad entry.
+    #[cfg(feature = "std-fs")]
+    pub fn open_checked(
+        blob_path: impl AsRef<Path>,
+        blob_index_path: impl AsRef<Path>,
+    ) -> Result<Self> {
+        let archive = Self::open(blob_path, blob_index_path)?;
+        archive.check_bounds()?;
+        Ok(archive)
+    }
+
+    /// Opens the blob at `blob_path`, and its other shards if the index
+    /// refers to any, to be read with a key if `keyed`.
+    #[cfg(feature = "std-fs")]
+    fn open_with(
+        blob_path: &Path,
+        blob_index_path: &Path,
+        mmap: bool,
+        keyed: bool,
+    ) -> Result<Self> {
+        let (blob, data_range) = Blob::load(File::open(blob_path).at(blob_path)?, mmap, keyed)?;
+        let assets = Self::load_index(blob_index_path)?;
+        let shard_count = assets.values().map(|asset| asset.shard).max().unwrap_or(0);
+        let shards = (1..=shard_count)
+            .map(|shard| {
+                let path = shard_path(blob_path, shard);
+                Blob::load(File::open(&path).at(&path)?, mmap, keyed)
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            blob,
+            shards,
+            blob_path: Some(blob_path.to_owned()),
+            data_range,
+            assets,
+            lowercase: OnceLock::new(),
+            cache: None,
+            sorted: OnceLock::new(),
+            checksum: None,
+            #[cfg(feature = "encryption")]
+            key: None,
+        })
+    }
+
+    /// Opens an archive created by [`crate::AssetArchiver::create_single_archive`].
+    #[cfg(feature = "std-fs")]
+    pub fn open_single(archive_path: impl AsRef<Path>) -> Result<Self> {
+        let archive_path = archive_path.as_ref();
+        let mut file = File::open(archive_path).at(archive_path)?;
+        if Header::read(&mut file)?.compression.is_some() {
+            return Err(MonolithicaError::Unsupported(
+                "compressed single-file archives are not supported".to_owned(),
+            ));
+        }
+
+        let file_len = file.metadata()?.len();
+        if file_len < FOOTER_LEN {
+            tracing::error!("archive too short");
+            return Err(MonolithicaError::CorruptArchive(format!(
+                "archive too short, {file_len} bytes"
+            )));
+        }
+        file.seek(SeekFrom::Start(file_len - FOOTER_LEN))?;
+        let footer = Footer::read(&mut file)?;
+
+        let checksummed = checksummed_len(&footer, file_len - FOOTER_LEN)?;
+        let index = read_region(&mut file, footer.index_offset, footer.index_len)?;
+        let checksum = read_region(&mut file, checksummed, file_len - FOOTER_LEN - checksummed)?;
+        Ok(Self {
+            blob: Blob::File(share_file(file)),
+            shards: Vec::new(),
+            blob_path: Some(archive_path.to_owned()),
+            data_range: HEADER_LEN..footer.index_offset,
+            assets: Self::parse_index(&index)?,
+            lowercase: OnceLock::new(),
+            cache: None,
+            sorted: OnceLock::new(),
+            checksum: BlobChecksum::parse(&checksum)?.map(|checksum| (checksum, checksummed)),
+            #[cfg(feature = "encryption")]
+            key: None,
+        })
+    }
+
+    /// Opens the archive with the blob memory-mapped, so assets can be borrowed
+    /// with [`Archive::get_slice`] instead of being copied out.
+    ///
+    /// The blob must not be modified or truncated while the archive is open.
+    #[cfg(feature = "mmap")]
+    pub fn open_mmap(
+        blob_path: impl AsRef<Path>,
+        blob_index_path: impl AsRef<Path>,
+    ) -> Result<Self> {
+        Self::open_with(blob_path.as_ref(), blob_index_path.as_ref(), true, false)
+    }
+
+    /// Opens an archive whose blob and index are embedded in the program, e.g.
+    /// with [`include_bytes!`], without touching the filesystem. Assets are
+    /// read straight out of `blob`, so [`Archive::get_slice`] works too.
+    pub fn from_bytes(blob: &'static [u8], index: impl AsRef<[u8]>) -> Result<Self> {
+        let (blob, data_range) = Blob::from_bytes(blob)?;
+        Ok(Self {
+            blob,
+            shards: Vec::new(),
+            blob_path: None,
+            data_range,
+            assets: Self::parse_index(index.as_ref())?,
+            lowercase: OnceLock::new(),
+            cache: None,
+            sorted: OnceLock::new(),
+            checksum: None,
+            #[cfg(feature = "encryption")]
+            key: None,
+        })
+    }
+
+    /// Same as [`Archive::from_bytes`] for a blob held in memory, e.g. one
+    /// built by [`crate::AssetArchiver::build_in_memory`].
+    pub fn from_vec(blob: Vec<u8>, index: impl AsRef<[u8]>) -> Result<Self> {
+        Self::from_vec_keyed(blob, index.as_ref(), false)
+    }
+
+    /// Same as [`Archive::open_with_key`] for a blob held in memory.
+    #[cfg(feature = "encryption")]
+    pub fn from_vec_with_key(
+        blob: Vec<u8>,
+        index: impl AsRef<[u8]>,
+        key: EncryptionKey,
+    ) -> Result<Self> {
+        let mut archive = Self::from_vec_keyed(blob, index.as_ref(), true)?;
+        archive.key = Some(key);
+        Ok(archive)
+    }
+
+    fn from_vec_keyed(blob: Vec<u8>, index: &[u8], keyed: bool) -> Result<Self> {
+        let (blob, data_range) = Blob::from_vec(blob, keyed)?;
+        Ok(Self {
+            blob,
+            shards: Vec::new(),
+            blob_path: None,
+            data_range,
+            assets: Self::parse_index(index)?,
+            lowercase: OnceLock::new(),
+            cache: None,
+            sorted: OnceLock::new(),
+            checksum: None,
+            #[cfg(feature = "encryption")]
+            key: None,
+        })
+    }
+
+    /// Same as [`Archive::from_bytes`] for an archive created by
+    /// [`crate::AssetArchiver::create_single_archive`].
+    pub fn from_single_bytes(archive: &'static [u8]) -> Result<Self> {
+        if Header::read(&mut &archive[..])?.compression.is_some() {
+            return Err(MonolithicaError::Unsupported(
+                "compressed single-file archives are not supported".to_owned(),
+            ));
+        }
+
+        let Some(footer_offset) = archive.len().checked_sub(FOOTER_LEN as usize) else {
+            tracing::error!("archive too short");
+            return Err(MonolithicaError::CorruptArchive(format!(
+                "archive too short, {} bytes",
+                archive.len()
+            )));
+        };
+        let footer = Footer::read(&mut &archive[footer_offset..])?;
+
+        let checksummed = checksummed_len(&footer, footer_offset as u64)?;
+        let index = slice_region(archive, footer.index_offset, footer.index_len)?;
+        let checksum = &archive[checksummed as usize..footer_offset];
+        Ok(Self {
+            blob: Blob::Static(archive),
+            shards: Vec::new(),
+            blob_path: None,
+            data_range: HEADER_LEN..footer.index_offset,
+            assets: Self::parse_index(index)?,
+            lowercase: OnceLock::new(),
+            cache: None,
+            sorted: OnceLock::new(),
+            checksum: BlobChecksum::parse(checksum)?.map(|checksum| (checksum, checksummed)),
+            #[cfg(feature = "encryption")]
+            key: None,
+        })
+    }
+
+    #[cfg(feature = "std-fs")]
+    fn load_index(blob_index_path: &Path) -> Result<HashMap<String, Asset>> {
+        Self::parse_index(&fs::read(blob_index_path).at(blob_index_path)?)
+    }
+
+    /// Parses either index format, telling them apart by the binary magic.
+    fn parse_index(content: &[u8]) -> Result<HashMap<String, Asset>> {
+        Ok(AssetIndexer::from_bytes(content)?
+            .asset_map
+            .into_iter()
+            .map(|(path, asset)| (path.into_owned(), asset))
+            .collect())
+    }
+
+    /// Keeps the decoded bytes of the compressed assets read last, up to
+    /// `capacity` bytes, so serving a hot asset doesn't decode it on every
+    /// read. Assets stored raw aren't cached, nor are those larger than
+    /// `capacity`.
+    pub fn with_decode_cache(mut self, capacity: u64) -> Self {
+        self.cache = Some(DecodeCache::new(capacity));
+        self
+    }
+
+    /// Hits and misses of the cache set up with
+    /// [`Archive::with_decode_cache`], `None` without one.
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.cache.as_ref().map(DecodeCache::stats)
+    }
+
+    pub fn locate_asset(&self, path: &str) -> Option<&Asset> {
+        self.assets.get(path)
+    }
+
+    /// See [`AssetIndexer::locate_normalized`].
+    pub fn locate_normalized(&self, path: &str) -> Option<&Asset> {
+        self.assets.get(normalize_path(path)?.as_ref())
+    }
+
+    /// See [`AssetIndexer::resolve`].
+    pub fn resolve(&self, request_path: &str) -> Option<(&str, &Asset)> {
+        let (path, asset) = self
+            .assets
+            .get_key_value(normalize_path(request_path)?.as_ref())?;
+        Some((path.as_str(), asset))
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.assets.contains_key(path)
+    }
+
+    /// Case-insensitive [`Archive::locate_asset`], see
+    /// [`AssetIndexer::locate_asset_ci`] for how ambiguous paths resolve.
+    pub fn locate_asset_ci(&self, path: &str) -> Option<&Asset> {
+        let lowercase = self.lowercase.get_or_init(|| lowercase_paths(self.iter()));
+        self.assets.get(lowercase.get(&path.to_lowercase())?)
+    }
+
+    /// See [`AssetIndexer::nth`].
+    pub fn nth(&self, i: usize) -> Option<(&str, &Asset)> {
+        let (path, asset) = self.assets.get_key_value(self.sorted().get(i)?)?;
+        Some((path.as_str(), asset))
+    }
+
+    /// See [`AssetIndexer::list_prefix`].
+    fn sorted(&self) -> &[String] {
+        self.sorted
+            .get_or_init(|| query::sorted_paths(self.paths()))
+    }
+
+    /// Iterates over all entries in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Asset)> {
+        self.assets
+            .iter()
+            .map(|(path, asset)| (path.as_str(), asset))
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.assets.keys().map(String::as_str)
+    }
+
+    /// Iterates over all assets in path order along with their decoded bytes,
+    /// e.g. to re-hash or copy every asset. Each asset is only read when the
+    /// iterator gets to it, and the failure to read one doesn't end the
+    /// iteration.
+    pub fn contents(&self) -> impl Iterator<Item = (&str, Result<Vec<u8>>)> {
+        (0..self.len())
+            .filter_map(|i| self.nth(i))
+            .map(|(path, asset)| (path, self.read_decoded(path, asset)))
+    }
+
+    /// See [`crate::AssetIndexer::list_prefix`].
+    pub fn list_prefix(&self, prefix: &str) -> Vec<&str> {
+        query::list_prefix(self.sorted(), prefix)
+    }
+
+    /// See [`crate::AssetIndexer::list_dir`].
+    pub fn list_dir(&self, prefix: &str) -> Vec<&str> {
+        query::list_sorted_dir(self.sorted(), prefix)
+    }
+
+    /// See [`crate::AssetIndexer::find`].
+    pub fn find(&self, pattern: &str) -> Result<Vec<(&str, &Asset)>> {
+        query::find(self.iter(), pattern)
+    }
+
+    /// See [`crate::AssetIndexer::total_size`].
+    pub fn total_size(&self) -> u64 {
+        query::total_size(self.iter())
+    }
+
+    /// See [`crate::AssetIndexer::size_by_mime`].
+    pub fn size_by_mime(&self) -> HashMap<String, (u64, usize)> {
+        query::size_by_mime(self.iter())
+    }
+
+    /// The paths and [`Asset::content_hash`]es of the assets that have one,
+    /// sorted by path, e.g. to write a manifest of fingerprinted URLs.
+    pub fn content_hashes(&self) -> Vec<(&str, &str)> {
+        let mut hashes: Vec<_> = self
+            .iter()
+            .filter_map(|(path, asset)| Some((path, asset.content_hash.as_deref()?)))
+            .collect();
+        hashes.sort_unstable();
+        hashes
+    }
+
+    pub fn len(&self) -> usize {
+        self.assets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.assets.is_empty()
+    }
+
+    /// Returns the bytes of the asset at `path`, `Ok(None)` if there is no such
+    /// asset.
+    pub fn get(&self, path: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.get_with_mime(path)?.map(|(bytes, _)| bytes))
+    }
+
+    /// Like [`Archive::get`], decoding the asset as UTF-8, for text assets
+    /// such as HTML, CSS or JSON. Fails rather than replacing invalid bytes.
+    pub fn get_string(&self, path: &str) -> Result<Option<String>> {
+        let Some(bytes) = self.get(path)? else {
+            return Ok(None);
+        };
+        String::from_utf8(bytes).map(Some).map_err(|source| {
+            tracing::error!("asset is not valid UTF-8: {path}");
+            MonolithicaError::NotUtf8 {
+                path: path.to_owned(),
+                source,
+            }
+        })
+    }
+
+    /// The path and asset to serve for `path` in a single-page app: the asset
+    /// at `path`, or the one at `fallback`, typically `index.html`, if there is
+    /// none and `path` doesn't [look like a file](crate::looks_like_file).
+    pub fn locate_or_fallback<'p>(
+        &self,
+        path: &'p str,
+        fallback: &'p str,
+    ) -> Option<(&'p str, &Asset)> {
+        if let Some(asset) = self.locate_asset(path) {
+            return Some((path, asset));
+        }
+        if looks_like_file(path) {
+            return None;
+        }
+        Some((fallback, self.locate_asset(fallback)?))
+    }
+
+    /// Returns the bytes of the asset [`Archive::locate_or_fallback`] picks.
+    pub fn get_or_fallback(&self, path: &str, fallback: &str) -> Result<Option<Vec<u8>>> {
+        match self.locate_or_fallback(path, fallback) {
+            Some((path, _)) => self.get(path),
+            None => Ok(None),
+        }
+    }
+
+    /// Same as [`Archive::get`], also returning the mime recorded in the index.
+    pub fn get_with_mime(&self, path: &str) -> Result<Option<(Vec<u8>, Option<String>)>> {
+        let Some(asset) = self.assets.get(path) else {
+            return Ok(None);
+        };
+
+        Ok(Some((self.read_decoded(path, asset)?, asset.mime.clone())))
+    }
+
+    /// The decoded bytes of `asset`, at `path`, through the decode cache.
+    fn read_decoded(&self, path: &str, asset: &Asset) -> Result<Vec<u8>> {
+        let decode = || asset.decode(self.read_stored(path, asset)?);
+        match &self.cache {
+            Some(cache) if asset.is_compressed() => cache.get_or_decode(path, decode),
+            _ => decode(),
+        }
+    }
+
+    /// Returns the bytes of the asset at `path` as stored, without decoding
+    /// them, along with their encoding. Useful to serve compressed assets as
+    /// they are with a matching `Content-Encoding`.
+    pub fn get_raw(&self, path: &str) -> Result<Option<(Vec<u8>, Option<Encoding>)>> {
+        let Some(asset) = self.assets.get(path) else {
+            return Ok(None);
+        };
+
+        Ok(Some((self.read_stored(path, asset)?, asset.encoding)))
+    }
+
+    /// Returns the bytes of the asset at `path` in the first of the `accepted`
+    /// encodings that it's stored in, either itself or as a precompressed
+    /// variant, otherwise decoded. The encoding of the returned bytes is
+    /// returned along with them, `None` if they are decoded.
+    pub fn get_encoded(
+        &self,
+        path: &str,
+        accepted: &[Encoding],
+    ) -> Result<Option<(Vec<u8>, Option<Encoding>)>> {
+        let Some(asset) = self.assets.get(path) else {
+            return Ok(None);
+        };
+
+        for &encoding in accepted {
+            if asset.encoding == Some(encoding) {
+                return Ok(Some((self.read_stored(path, asset)?, Some(encoding))));
+            }
+            if let Some(variant) = asset.variant(encoding) {
+                let bytes =
+                    self.read_asset_region(path, asset.shard, variant.offset, variant.len)?;
+                return Ok(Some((bytes, Some(encoding))));
+            }
+        }
+
+        Ok(self.get(path)?.map(|bytes| (bytes, None)))
+    }
+
+    /// Returns `len` bytes of the asset at `path` from `start` on, counting in
+    /// its decoded bytes, e.g. to answer a range request. The range is clamped
+    /// to the asset, so it's empty past its end. Only that part of the blob is
+    /// read unless the asset has to be decoded. `Ok(None)` if there is no such
+    /// asset.
+    pub fn read_range(&self, path: &str, start: u64, len: u64) -> Result<Option<Vec<u8>>> {
+        let Some(asset) = self.assets.get(path) else {
+            return Ok(None);
+        };
+
+        let start = start.min(asset.original_len);
+        let end = start.saturating_add(len).min(asset.original_len);
+        if !asset.is_compressed() && !self.is_encrypted() {
+            return self
+                .read_asset_region(path, asset.shard, asset.offset + start, end - start)
+                .map(Some);
+        }
+        let mut bytes = self.get(path)?.unwrap_or_default();
+        bytes.truncate(end as usize);
+        bytes.drain(..start as usize);
+        Ok(Some(bytes))
+    }
+
+    /// The bytes of `asset` at `path` as stored, decrypted if the archive is
+    /// encrypted.
+    fn read_stored(&self, path: &str, asset: &Asset) -> Result<Vec<u8>> {
+        let stored = self.read_asset_region(path, asset.shard, asset.offset, asset.len)?;
+        self.decrypt(path, asset, stored)
+    }
+
+    /// Decrypts `stored`, the bytes of `asset` at `path` as read from the
+    /// blob, if the archive was opened with a key.
+    #[cfg_attr(not(feature = "encryption"), allow(unused_variables))]
+    pub(crate) fn decrypt(&self, path: &str, asset: &Asset, stored: Vec<u8>) -> Result<Vec<u8>> {
+        #[cfg(feature = "encryption")]
+        if let Some(key) = &self.key {
+            return key.decrypt(path, asset, stored);
+        }
+        Ok(stored)
+    }
+
+    /// Whether the assets are encrypted, see [`Archive::open_with_key`].
+    pub(crate) fn is_encrypted(&self) -> bool {
+        #[cfg(feature = "encryption")]
+        if self.key.is_some() {
+            return true;
+        }
+        false
+    }
+
+    /// Fails if the assets are encrypted, so can't be read straight from the
+    /// blob as `what` does.
+    pub(crate) fn check_plain(&self, what: &str) -> Result<()> {
+        if self.is_encrypted() {
+            tracing::error!("{what} of an encrypted archive");
+            return Err(MonolithicaError::Unsupported(format!(
+                "encrypted assets can't be {what}, read them whole instead"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Checks that every asset and variant lies within the asset data of its
+    /// blob, failing with [`MonolithicaError::CorruptArchive`] naming the first
+    /// bad entry in path order. Costs a pass over the index, which
+    /// [`Archive::open`] leaves out; it can be run after any way of opening
+    /// the archive.
+    pub fn check_bounds(&self) -> Result<()> {
+        let mut paths: Vec<_> = self.paths().collect();
+        paths.sort_unstable();
+        for path in paths {
+            let asset = &self.assets[path];
+            let (_, data) = self.shard_data(asset.shard)?;
+            let regions = std::iter::once((asset.offset, asset.len))
+                .chain(asset.variants.iter().map(|v| (v.offset, v.len)));
+            for (offset, len) in regions {
+                let end = offset.checked_add(len);
+                if offset < data.start || end.is_none_or(|end| end > data.end) {
+                    tracing::error!("asset out of bounds: {path}");
+                    return Err(MonolithicaError::CorruptArchive(format!(
+                        "{path}: {len} bytes at offset {offset} lie outside the blob data, \
+                         bytes {}..{}",
+                        data.start, data.end
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The blob holding the assets of `shard`, along with the part of it that
+    /// holds asset data.
+    fn shard_data(&self, shard: u32) -> Result<(&Blob, &Range<u64>)> {
+        let blob = match shard {
+            0 => Some((&self.blob, &self.data_range)),
+            _ => self
+                .shards
+                .get(shard as usize - 1)
+                .map(|(blob, data)| (blob, data)),
+        };
+        blob.ok_or_else(|| {
+            tracing::error!("missing shard {shard}");
+            MonolithicaError::Unsupported(format!(
+                "shard {shard} isn't open, sharded archives have to be opened from files"
+            ))
+        })
+    }
+
+    /// The blob holding `len` bytes at `offset` of `shard`, failing with
+    /// [`MonolithicaError::BlobTooShort`] if its asset data ends before them,
+    /// rather than finding out partway through reading them, and with
+    /// [`MonolithicaError::CorruptArchive`] if they start before it, such as in
+    /// the header.
+    fn region_blob(&self, shard: u32, offset: u64, len: u64) -> Result<&Blob> {
+        let (blob, data) = self.shard_data(shard)?;
+        if offset < data.start {
+            tracing::error!("region at offset {offset} is before the asset data");
+            return Err(MonolithicaError::CorruptArchive(format!(
+                "region at offset {offset} starts before the asset data at {}",
+                data.start
+            )));
+        }
+        if offset.saturating_add(len) > data.end {
+            tracing::error!("blob too short, expected {len} bytes at offset {offset}");
+            return Err(MonolithicaError::BlobTooShort {
+                offset,
+                len,
+                available: data.end.saturating_sub(offset),
+            });
+        }
+        Ok(blob)
+    }
+
+    /// Same as [`Archive::region_blob`] for the bytes of `asset` at `path`,
+    /// failing with [`MonolithicaError::AssetTruncated`] naming it if the blob
+    /// is too short.
+    pub(crate) fn asset_blob(&self, path: &str, asset: &Asset) -> Result<&Blob> {
+        self.region_blob(asset.shard, asset.offset, asset.len)
+            .map_err(|e| truncated(path, e))
+    }
+
+    pub(crate) fn read_region(&self, shard: u32, offset: u64, len: u64) -> Result<Vec<u8>> {
+        match self.region_blob(shard, offset, len)? {
+            #[cfg(feature = "std-fs")]
+            Blob::File(file) => read_region_at(file, offset, len),
+            blob => Ok(slice_region(blob.as_slice().unwrap(), offset, len)?.to_vec()),
+        }
+    }
+
+    /// Same as [`Archive::read_region`] for a region of the asset at `path`,
+    /// failing with [`MonolithicaError::AssetTruncated`] naming it if the blob
+    /// is too short.
+    pub(crate) fn read_asset_region(
+        &self,
+        path: &str,
+        shard: u32,
+        offset: u64,
+        len: u64,
+    ) -> Result<Vec<u8>> {
+        self.read_region(shard, offset, len)
+            .map_err(|e| truncated(path, e))
+    }
+
+    /// Opens a reader over the bytes of the asset at `path` as stored, see
+    /// [`Archive::get_raw`], `Ok(None)` if there is no such asset.
+    pub fn open_asset(&self, path: &str) -> Result<Option<AssetStream<'_>>> {
+        let Some(asset) = self.assets.get(path) else {
+            return Ok(None);
+        };
+        self.check_plain("streamed")?;
+        Ok(Some(AssetStream {
+            blob: self.asset_blob(path, asset)?,
+            pos: asset.offset,
+            end: asset.offset + asset.len,
+        }))
+    }
+
+    /// Borrows the bytes of the asset at `path` as stored straight from the
+    /// blob, only available for archives opened with [`Archive::open_mmap`] or
+    /// [`Archive::from_bytes`], or whose blob is compressed and thus held in
+    /// memory.
+    pub fn get_slice(&self, path: &str) -> Result<Option<&[u8]>> {
+        let Some(asset) = self.assets.get(path) else {
+            return Ok(None);
+        };
+        self.check_plain("borrowed")?;
+
+        match self.asset_blob(path, asset)?.as_slice() {
+            Some(blob) => Ok(Some(
+                slice_region(blob, asset.offset, asset.len).map_err(|e| truncated(path, e))?,
+            )),
+            None => Err(MonolithicaError::Unsupported(
+                "blob is not memory-mapped".to_owned(),
+            )),
+        }
+    }
+}
+
+/// A [`Read`] over exactly the bytes of one asset, see [`Archive::open_asset`].
+///
+/// Every read is positioned explicitly, so other reads from the same archive
+/// in between don't disturb the stream.
+pub struct AssetStream<'a> {
+    blob: &'a Blob,
+    pos: u64,
+    end: u64,
+}
+
+impl AssetStream<'_> {
+    /// Number of bytes left before the end of the asset.
+    pub fn remaining(&self) -> u64 {
+        self.end - self.pos
+    }
+}
+
+impl Read for AssetStream<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let max = buf
+            .len()
+            .min(usize::try_from(self.remaining()).unwrap_or(usize::MAX));
+        if max == 0 {
+            return Ok(0);
+        }
+
+        let bytes_read = self.blob.read_at(self.pos, &mut buf[..max])?;
+        if bytes_read == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!(
+                    "blob too short, {} bytes of asset missing",
+                    self.remaining()
+                ),
+            ));
+        }
+
+        self.pos += bytes_read as u64;
+        Ok(bytes_read)
+    }
+}
+
+/// Where the index of a single-file archive whose footer is at `footer_offset`
+/// ends, and so its checksum starts. Fails unless the index lies between the
+/// header and the footer.
+fn checksummed_len(footer: &Footer, footer_offset: u64) -> Result<u64> {
+    if footer.index_offset < HEADER_LEN {
+        tracing::error!("index overlaps the header");
+        return Err(MonolithicaError::CorruptArchive(format!(
+            "archive index at offset {} overlaps the header",
+            footer.index_offset
+        )));
+    }
+    match footer.index_offset.checked_add(footer.index_len) {
+        Some(index_end) if index_end <= footer_offset => Ok(index_end),
+        _ => {
+            tracing::error!("index overlaps the footer");
+            Err(MonolithicaError::CorruptArchive(
+                "archive index overlaps the footer".to_owned(),
+            ))
+        }
+    }
+}
+
+/// Names the asset at `path` in a [`MonolithicaError::BlobTooShort`].
+fn truncated(path: &str, error: MonolithicaError) -> MonolithicaError {
+    match error {
+        MonolithicaError::BlobTooShort {
+            offset,
+            len,
+            available,
+        } => {
+            tracing::error!("asset truncated: {path}");
+            MonolithicaError::AssetTruncated {
+                path: path.to_owned(),
+                offset,
+                len,
+                available,
+            }
+        }
+        error => error,
+    }
+}
+
+fn slice_region(blob: &[u8], offset: u64, len: u64) -> Result<&[u8]> {
+    let end = offset
+        .checked_add(len)
+        .and_then(|end| usize::try_from(end).ok())
+        .filter(|end| *end <= blob.len());
+    let Some(end) = end else {
+        tracing::error!("blob too short, expected {len} bytes at offset {offset}");
+        return Err(MonolithicaError::BlobTooShort {
+            offset,
+            len,
+            available: (blob.len() as u64).saturating_sub(offset),
+        });
+    };
+
+    // `offset` fits in a `usize`, being at most `end`.
+    Ok(&blob[offset as usize..end])
+}
+
+#[cfg(all(test, feature = "std-fs"))]
+mod tests {
+    use super::*;
+    use crate::{
+        test_util::{build_fixture, fixture_dir, write_file},
+        ArchiveOptions, AssetArchiver, IndexFormat,
+    };
+
+    #[test]
+    fn get_asset() {
+        let (blob, index) = build_fixture("archive_get_asset");
+        let archive = Archive::open(&blob, &index).unwrap();
+
+        assert_eq!(
+            archive.get("index.html").unwrap().unwrap(),
+            b"<html></html>"
+        );
+        let (bytes, mime) = archive.get_with_mime("js/app.js").unwrap().unwrap();
+        assert_eq!(bytes, b"console.log(1);");
+        assert_eq!(mime.as_deref(), Some("application/javascript"));
+        assert!(archive.get("missing.html").unwrap().is_none());
+
+        let (path, asset) = archive.resolve("/js\\app.js").unwrap();
+        assert_eq!((path, asset.original_len), ("js/app.js", 15));
+        assert!(archive.resolve("/css/../index.html").is_none());
+    }
+
+    #[test]
+    fn contents() {
+        let (blob, index) = build_fixture("archive_contents");
+        let archive = Archive::open(&blob, &index).unwrap();
+        let contents: Vec<_> = archive
+            .contents()
+            .map(|(path, bytes)| (path, bytes.unwrap()))
+            .collect();
+        assert_eq!(
+            contents,
+            [
+                (".rustc_info.json", br#"{"rustc":"1.0"}"#.to_vec()),
+                ("index.html", b"<html></html>".to_vec()),
+                ("js/app.js", b"console.log(1);".to_vec()),
+            ]
+        );
+
+        // An asset that can't be read is reported without ending the
+        // iteration.
+        let offset = archive.locate_asset("index.html").unwrap().offset;
+        fs::write(
+            &index,
+            format!("a.txt//0//1000//-\nb.txt//{offset}//13//-\n"),
+        )
+        .unwrap();
+        let archive = Archive::open(&blob, &index).unwrap();
+        let contents: Vec<_> = archive.contents().collect();
+        assert!(contents[0].1.is_err());
+        assert_eq!(contents[1].1.as_deref().unwrap(), b"<html></html>");
+    }
+
+    #[test]
+    fn truncated_blob() {
+        let (blob, index) = build_fixture("archive_truncated_blob");
+        let archive = Archive::open(&blob, &index).unwrap();
+        let asset = archive.locate_asset("js/app.js").unwrap().clone();
+        drop(archive);
+        // Cut the blob 5 bytes into the asset.
+        let file = File::options().write(true).open(&blob).unwrap();
+        file.set_len(asset.offset + 5).unwrap();
+        drop(file);
+
+        let archive = Archive::open(&blob, &index).unwrap();
+        let err = archive.get("js/app.js").unwrap_err();
+        assert!(
+            matches!(
+                &err,
+                MonolithicaError::AssetTruncated { path, offset, len: 15, available: 5 }
+                    if path == "js/app.js" && *offset == asset.offset
+            ),
+            "{err}"
+        );
+        assert!(err.to_string().contains("js/app.js"), "{err}");
+        assert_eq!(
+            archive.read_range("js/app.js", 0, 5).unwrap().unwrap(),
+            b"conso"
+        );
+        assert!(matches!(
+            archive.read_range("js/app.js", 0, 6),
+            Err(MonolithicaError::AssetTruncated { .. })
+        ));
+        assert!(matches!(
+            archive.open_asset("js/app.js"),
+            Err(MonolithicaError::AssetTruncated { .. })
+        ));
+    }
+
+    #[test]
+    fn read_range() {
+        let (blob, index) = build_fixture("archive_read_range");
+        let archive = Archive::open(&blob, &index).unwrap();
+        let range = |start, len| {
+            archive
+                .read_range("js/app.js", start, len)
+                .unwrap()
+                .unwrap()
+        };
+
+        assert_eq!(range(0, 7), b"console");
+        assert_eq!(range(8, 3), b"log");
+        // Clamped to the 15 bytes of the asset.
+        assert_eq!(range(12, 100), b"1);");
+        assert_eq!(range(12, u64::MAX), b"1);");
+        assert_eq!(range(15, 1), b"");
+        assert_eq!(range(100, 1), b"");
+        assert!(archive.read_range("missing.js", 0, 1).unwrap().is_none());
+    }
+
+    #[test]
+    fn check_bounds_on_open() {
+        let (blob, index) = build_fixture("archive_check_bounds_on_open");
+        Archive::open_checked(&blob, &index).unwrap();
+
+        let content = fs::read_to_string(&index).unwrap();
+        let blob_len = fs::metadata(&blob).unwrap().len();
+        let bad = format!("{content}late.js//{}//10//-\n", blob_len - 5);
+        fs::write(&index, bad).unwrap();
+        let archive = Archive::open(&blob, &index).unwrap();
+        assert!(archive.get("late.js").is_err());
+        let err = Archive::open_checked(&blob, &index).err().unwrap();
+        assert!(
+            matches!(&err, MonolithicaError::CorruptArchive(message) if message.starts_with("late.js:")),
+            "{err}"
+        );
+        // Inside the header.
+        fs::write(&index, "a.txt//2//4//-\n").unwrap();
+        assert!(Archive::open_checked(&blob, &index).is_err());
+    }
+
+    #[test]
+    fn region_before_data() {
+        let (blob, index) = build_fixture("archive_region_before_data");
+        fs::write(
+            &index,
+            format!("a.txt//2//4//-\nb.txt//{HEADER_LEN}//0//-\n"),
+        )
+        .unwrap();
+        let archive = Archive::open(&blob, &index).unwrap();
+        let corrupt = |err: Option<MonolithicaError>| {
+            let err = err.unwrap();
+            assert!(matches!(err, MonolithicaError::CorruptArchive(_)), "{err}");
+        };
+        corrupt(archive.get("a.txt").err());
+        corrupt(archive.open_asset("a.txt").err());
+        corrupt(archive.read_range("a.txt", 1, 2).err());
+        assert_eq!(archive.get("b.txt").unwrap().unwrap(), b"");
+
+        let archive =
+            Archive::from_vec(fs::read(&blob).unwrap(), fs::read(&index).unwrap()).unwrap();
+        corrupt(archive.get("a.txt").err());
+        corrupt(archive.get_slice("a.txt").err());
+    }
+
+    #[test]
+    fn size_stats() {
+        let (blob, index) = build_fixture("archive_size_stats");
+        let archive = Archive::open(&blob, &index).unwrap();
+        assert_eq!(archive.total_size(), 15 + 13 + 15);
+
+        let sizes = archive.size_by_mime();
+        assert_eq!(sizes.len(), 3);
+        assert_eq!(sizes["text/html"], (13, 1));
+        assert_eq!(sizes["application/javascript"], (15, 1));
+        assert_eq!(sizes["application/json"], (15, 1));
+
+        let content = fs::read_to_string(&index).unwrap();
+        let indexer = AssetIndexer::parse(&content).unwrap();
+        assert_eq!(indexer.total_size(), archive.total_size());
+        assert_eq!(indexer.size_by_mime(), sizes);
+        let unknown = AssetIndexer::parse("a.bin//16//4//-\nb.bin//20//6//-").unwrap();
+        assert_eq!(unknown.size_by_mime()[crate::DEFAULT_MIME], (10, 2));
+    }
+
+    #[test]
+    fn get_string() {
+        let root = fixture_dir("archive_get_string");
+        write_file(&root.join("src/index.html"), "<p>héllo</p>");
+        write_file(&root.join("src/image.png"), [0x89, b'P', b'N', b'G', 0xff]);
+        let (blob, index) = (root.join("test.blob"), root.join("test.blob.idx"));
+        AssetArchiver::create_archive(root.join("src"), &blob, &index, false).unwrap();
+        let archive = Archive::open(&blob, &index).unwrap();
+
+        assert_eq!(
+            archive.get_string("index.html").unwrap().as_deref(),
+            Some("<p>héllo</p>")
+        );
+        assert_eq!(archive.get_string("missing.html").unwrap(), None);
+        let err = archive.get_string("image.png").unwrap_err();
+        assert!(
+            matches!(&err, MonolithicaError::NotUtf8 { path, .. } if path == "image.png"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn spa_fallback() {
+        let (blob, index) = build_fixture("archive_spa_fallback");
+        let archive = Archive::open(&blob, &index).unwrap();
+        let get = |path| archive.get_or_fallback(path, "index.html").unwrap();
+
+        assert_eq!(get("js/app.js").unwrap(), b"console.log(1);");
+        assert_eq!(get("users/42").unwrap(), b"<html></html>");
+        assert_eq!(get("").unwrap(), b"<html></html>");
+        assert_eq!(get("js/missing.js"), None);
+        assert_eq!(
+            archive.get_or_fallback("users", "missing.html").unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn open_with_binary_index() {
+        let root = fixture_dir("archive_open_with_binary_index");
+        write_file(&root.join("src/index.html"), "<html></html>");
+        let (blob, index) = (root.join("test.blob"), root.join("test.blob.idx"));
+        let options = ArchiveOptions {
+            index_format: IndexFormat::Binary,
+            ..Default::default()
+        };
+        AssetArchiver::create_archive_with_options(
+            root.join("src"),
+            &blob,
+            &index,
+            false,
+            &options,
+        )
+        .unwrap();
+
+        let archive = Archive::open(&blob, &index).unwrap();
+        assert_eq!(
+            archive.get("index.html").unwrap().unwrap(),
+            b"<html></html>"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn open_with_json_index() {
+        let root = fixture_dir("archive_open_with_json_index");
+        write_file(&root.join("src/index.html"), "<html></html>");
+        let (blob, index) = (root.join("test.blob"), root.join("test.blob.idx"));
+        let options = ArchiveOptions {
+            index_format: IndexFormat::Json,
+            ..Default::default()
+        };
+        AssetArchiver::create_archive_with_options(
+            root.join("src"),
+            &blob,
+            &index,
+            false,
+            &options,
+        )
+        .unwrap();
+
+        let archive = Archive::open(&blob, &index).unwrap();
+        assert_eq!(
+            archive.get("index.html").unwrap().unwrap(),
+            b"<html></html>"
+        );
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd_compressed_blob() {
+        let root = fixture_dir("archive_zstd_compressed_blob");
+        let html = "<p>compressible</p>".repeat(100);
+        write_file(&root.join("src/index.html"), &html);
+        write_file(&root.join("src/about.html"), &html);
+        let (blob, index) = (root.join("test.blob"), root.join("test.blob.idx"));
+        let options = ArchiveOptions {
+            blob_compression: Some(Encoding::Zstd),
+            ..Default::default()
+        };
+        AssetArchiver::create_archive_with_options(
+            root.join("src"),
+            &blob,
+            &index,
+            false,
+            &options,
+        )
+        .unwrap();
+        assert!(fs::metadata(&blob).unwrap().len() < html.len() as u64);
+
+        let archive = Archive::open(&blob, &index).unwrap();
+        assert_eq!(archive.get("index.html").unwrap().unwrap(), html.as_bytes());
+        assert_eq!(
+            archive.get_slice("about.html").unwrap().unwrap(),
+            html.as_bytes()
+        );
+        let mut streamed = Vec::new();
+        let mut stream = archive.open_asset("about.html").unwrap().unwrap();
+        stream.read_to_end(&mut streamed).unwrap();
+        assert_eq!(streamed, html.as_bytes());
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn compressed_blob_length() {
+        let options = ArchiveOptions {
+            blob_compression: Some(Encoding::Gzip),
+            ..Default::default()
+        };
+        let entries = [("a.txt".to_owned(), vec![b'a'; 1000])];
+        let (blob, index) = AssetArchiver::build_in_memory(entries, &options).unwrap();
+        let data_len = u64::from_le_bytes(blob[8..16].try_into().unwrap());
+        for claimed in [data_len - 1, data_len + 1] {
+            let mut tampered = blob.clone();
+            tampered[8..16].copy_from_slice(&claimed.to_le_bytes());
+            let err = Archive::from_vec(tampered, &index).err().unwrap();
+            assert!(matches!(err, MonolithicaError::CorruptArchive(_)), "{err}");
+        }
+        assert!(Archive::from_vec(blob, index).is_ok());
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn decode_cache() {
+        let root = fixture_dir("archive_decode_cache");
+        let html = "<p>compressible</p>".repeat(100);
+        write_file(&root.join("src/index.html"), &html);
+        write_file(&root.join("src/image.png"), "not compressed");
+        let (blob, index) = (root.join("test.blob"), root.join("test.blob.idx"));
+        let options = ArchiveOptions {
+            compression: Some(Encoding::Gzip),
+            ..Default::default()
+        };
+        AssetArchiver::create_archive_with_options(
+            root.join("src"),
+            &blob,
+            &index,
+            false,
+            &options,
+        )
+        .unwrap();
+
+        let archive = Archive::open(&blob, &index).unwrap();
+        assert_eq!(archive.cache_stats(), None);
+        let archive = archive.with_decode_cache(1 << 20);
+        for _ in 0..3 {
+            assert_eq!(archive.get("index.html").unwrap().unwrap(), html.as_bytes());
+            assert_eq!(
+                archive.get("image.png").unwrap().unwrap(),
+                b"not compressed"
+            );
+        }
+        let stats = archive.cache_stats().unwrap();
+        assert_eq!((stats.hits, stats.misses), (2, 1));
+        assert_eq!((stats.entries, stats.bytes), (1, html.len() as u64));
+        assert_eq!(
+            archive.read_range("index.html", 3, 12).unwrap().unwrap(),
+            b"compressible"
+        );
+        assert_eq!(archive.cache_stats().unwrap().hits, 3);
+    }
+
+    #[cfg(all(feature = "brotli", feature = "gzip"))]
+    #[test]
+    fn precompressed_variants() {
+        let root = fixture_dir("archive_precompressed_variants");
+        let html = "<p>compressible</p>".repeat(100);
+        write_file(&root.join("src/index.html"), &html);
+        write_file(&root.join("src/image.png"), &html);
+        let (blob, index) = (root.join("test.blob"), root.join("test.blob.idx"));
+        for index_format in [IndexFormat::Text, IndexFormat::Binary] {
+            let options = ArchiveOptions {
+                index_format,
+                precompressed: vec![Encoding::Brotli, Encoding::Gzip],
+                ..Default::default()
+            };
+            AssetArchiver::create_archive_with_options(
+                root.join("src"),
+                &blob,
+                &index,
+                true,
+                &options,
+            )
+            .unwrap();
+
+            let archive = Archive::open(&blob, &index).unwrap();
+            let asset = archive.locate_asset("index.html").unwrap();
+            assert!(!asset.is_compressed());
+            assert_eq!(asset.variants.len(), 2);
+            assert!(archive
+                .locate_asset("image.png")
+                .unwrap()
+                .variants
+                .is_empty());
+
+            let get = |accepted| {
+                archive
+                    .get_encoded("index.html", accepted)
+                    .unwrap()
+                    .unwrap()
+            };
+            let (bytes, encoding) = get(&[Encoding::Brotli, Encoding::Gzip]);
+            assert_eq!(encoding, Some(Encoding::Brotli));
+            assert_eq!(Encoding::Brotli.decode(&bytes).unwrap(), html.as_bytes());
+            let (bytes, encoding) = get(&[Encoding::Zstd, Encoding::Gzip]);
+            assert_eq!(encoding, Some(Encoding::Gzip));
+            assert_eq!(Encoding::Gzip.decode(&bytes).unwrap(), html.as_bytes());
+            assert_eq!(get(&[]), (html.clone().into_bytes(), None));
+            assert_eq!(archive.get("index.html").unwrap().unwrap(), html.as_bytes());
+        }
+    }
+
+    #[test]
+    fn stream_asset() {
+        let (blob, index) = build_fixture("archive_stream_asset");
+        let archive = Archive::open(&blob, &index).unwrap();
+
+        let mut stream = archive.open_asset("js/app.js").unwrap().unwrap();
+        let mut head = [0u8; 7];
+        stream.read_exact(&mut head).unwrap();
+        assert_eq!(&head, b"console");
+        assert_eq!(stream.remaining(), 8);
+
+        // an unrelated read must not move the stream
+        archive.get("index.html").unwrap();
+
+        let mut rest = Vec::new();
+        stream.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b".log(1);");
+        assert_eq!(stream.read(&mut head).unwrap(), 0);
+        assert!(archive.open_asset("missing.js").unwrap().is_none());
+    }
+
+    #[test]
+    fn single_file_archive() {
+        let root = fixture_dir("archive_single_file_archive");
+        write_file(&root.join("src/index.html"), "<html></html>");
+        write_file(&root.join("src/css/app.css"), "body {}");
+        let path = root.join("assets.mono");
+        AssetArchiver::create_single_archive(root.join("src"), &path, false).unwrap();
+
+        let archive = Archive::open_single(&path).unwrap();
+        assert_eq!(
+            archive.get("index.html").unwrap().unwrap(),
+            b"<html></html>"
+        );
+        assert_eq!(archive.get("css/app.css").unwrap().unwrap(), b"body {}");
+
+        let bytes = fs::read(&path).unwrap();
+        let footer = bytes.len() - FOOTER_LEN as usize;
+        let index_offset = u64::from_le_bytes(bytes[footer..footer + 8].try_into().unwrap());
+        for (at, value) in [
+            (0, 2),
+            (0, HEADER_LEN - 1),
+            (8, u64::MAX - index_offset),
+            (8, 1 << 20),
+        ] {
+            let mut tampered = bytes.clone();
+            tampered[footer + at..footer + at + 8].copy_from_slice(&value.to_le_bytes());
+            fs::write(&path, &tampered).unwrap();
+            let err = Archive::open_single(&path).err().unwrap();
+            assert!(matches!(err, MonolithicaError::CorruptArchive(_)), "{err}");
+            let err = Archive::from_single_bytes(tampered.leak()).err().unwrap();
+            assert!(matches!(err, MonolithicaError::CorruptArchive(_)), "{err}");
+        }
+
+        let mut bytes = bytes;
+        bytes[0] = b'X';
+        fs::write(&path, &bytes).unwrap();
+        assert!(Archive::open_single(&path).is_err());
+    }
+
+    #[test]
+    fn from_bytes() {
+        let (blob, index) = build_fixture("archive_from_bytes");
+        let blob = fs::read(blob).unwrap().leak();
+        let archive = Archive::from_bytes(blob, fs::read_to_string(index).unwrap()).unwrap();
+        assert_eq!(
+            archive.get_slice("index.html").unwrap().unwrap(),
+            b"<html></html>"
+        );
+        assert_eq!(
+            archive.get("js/app.js").unwrap().unwrap(),
+            b"console.log(1);"
+        );
+        assert!(Archive::from_bytes(&blob[..4], "").is_err());
+
+        let root = fixture_dir("archive_from_single_bytes");
+        write_file(&root.join("src/index.html"), "<html></html>");
+        let path = root.join("assets.mono");
+        AssetArchiver::create_single_archive(root.join("src"), &path, false).unwrap();
+        let archive = Archive::from_single_bytes(fs::read(path).unwrap().leak()).unwrap();
+        assert_eq!(
+            archive.get("index.html").unwrap().unwrap(),
+            b"<html></html>"
+        );
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn get_slice_from_mmap() {
+        let (blob, index) = build_fixture("archive_get_slice_from_mmap");
+        let archive = Archive::open_mmap(&blob, &index).unwrap();
+
+        assert_eq!(
+            archive.get_slice("js/app.js").unwrap().unwrap(),
+            b"console.log(1);"
+        );
+        assert_eq!(
+            archive.get("index.html").unwrap().unwrap(),
+            b"<html></html>"
+        );
+        assert!(archive.get_slice("missing.js").unwrap().is_none());
+
+        let mut streamed = Vec::new();
+        let mut stream = archive.open_asset("index.html").unwrap().unwrap();
+        stream.read_to_end(&mut streamed).unwrap();
+        assert_eq!(streamed, b"<html></html>");
+
+        let file_backed = Archive::open(&blob, &index).unwrap();
+        assert!(file_backed.get_slice("js/app.js").is_err());
+    }
+
+    #[test]
+    fn slice_region_out_of_bounds() {
+        let blob = [0u8; 8];
+        assert_eq!(slice_region(&blob, 2, 6).unwrap().len(), 6);
+        assert!(slice_region(&blob, 2, 7).is_err());
+        assert!(slice_region(&blob, 9, 0).is_err());
+        assert!(slice_region(&blob, u64::MAX, 2).is_err());
+    }
+}
@@ -0,0 +1,520 @@
+use crate::{
+    archive::Blob,
+    atomic::{Outputs, TempPath},
+    error::IoContext,
+    filter::IGNORE_FILES,
+    format::{self, Header, HEADER_LEN},
+    packer::{
+        Checksummer, ContentHasher, EntryKind, FileMeta, Packer, Visit, DEFAULT_BUFFER_SIZE,
+        MAX_BUFFERED_LEN,
+    },
+    progress::{reborrow, Progress},
+    shard_path, Archive, ArchiveOptions, ArchiveSummary, AssetArchiver, MonolithicaError,
+    ProgressEvent, Result,
+};
+use std::{
+    ffi::OsString,
+    io::{self, Cursor, SeekFrom},
+    path::{Path, PathBuf},
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+use tokio::{
+    fs::{self, File},
+    io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt, ReadBuf},
+};
+
+impl AssetArchiver {
+    /// Same as [`AssetArchiver::create_archive_with_options`], but walks the
+    /// source directory and does file I/O with [`tokio::fs`], producing the
+    /// same blob and index. [`ArchiveOptions::parallel`] is ignored; encoding
+    /// still runs on the calling task.
+    pub async fn create_archive_async(
+        src_dir: impl AsRef<Path>,
+        blob_path: impl AsRef<Path>,
+        blob_index_path: impl AsRef<Path>,
+        overwrite_existing: bool,
+        options: &ArchiveOptions,
+    ) -> Result<ArchiveSummary> {
+        Self::create_archive_async_reporting(
+            src_dir.as_ref(),
+            blob_path.as_ref(),
+            blob_index_path.as_ref(),
+            overwrite_existing,
+            options,
+            None,
+        )
+        .await
+    }
+
+    /// [`AssetArchiver::create_archive_async`], telling `progress` about each
+    /// file.
+    pub(crate) async fn create_archive_async_reporting(
+        src_dir: &Path,
+        blob_path: &Path,
+        blob_index_path: &Path,
+        overwrite_existing: bool,
+        options: &ArchiveOptions,
+        mut progress: Option<Progress<'_>>,
+    ) -> Result<ArchiveSummary> {
+        if options.shard_size.is_some() {
+            return Err(MonolithicaError::Unsupported(
+                "sharded blobs can't be written asynchronously".to_owned(),
+            ));
+        }
+        check_path(blob_path, overwrite_existing).await?;
+        check_path(blob_index_path, overwrite_existing).await?;
+
+        let (blob_tmp, blob_index_tmp) = (TempPath::new(blob_path), TempPath::new(blob_index_path));
+        let mut blob_file = File::create(blob_tmp.tmp()).await.at(blob_tmp.tmp())?;
+        let mut header = Vec::new();
+        let outputs = AssetArchiver::outputs(blob_path, blob_index_path, options);
+        let (entries, mut summary) = if let Some(compression) = options.blob_compression {
+            let mut data = Vec::new();
+            let packer = pack(
+                src_dir,
+                options,
+                &mut data,
+                reborrow(&mut progress),
+                outputs,
+            )
+            .await?;
+
+            Header {
+                flags: options.header_flags(),
+                compression: Some(compression),
+                data_len: data.len() as u64,
+            }
+            .write(&mut header)?;
+            blob_file.write_all(&header).await?;
+            blob_file.write_all(&compression.encode(&data)?).await?;
+            packer.finish()
+        } else {
+            blob_file.seek(SeekFrom::Start(HEADER_LEN)).await?;
+            let packer = pack(
+                src_dir,
+                options,
+                &mut blob_file,
+                reborrow(&mut progress),
+                outputs,
+            )
+            .await?;
+            let data_len = packer.offset - HEADER_LEN;
+
+            Header {
+                flags: options.header_flags(),
+                compression: None,
+                data_len,
+            }
+            .write(&mut header)?;
+            blob_file.seek(SeekFrom::Start(0)).await?;
+            blob_file.write_all(&header).await?;
+            packer.finish()
+        };
+        blob_file.flush().await?;
+        summary.blob_size = blob_file.metadata().await?.len();
+
+        let mut index = Vec::new();
+        format::write_index(&mut index, &entries, options.index_format)?;
+        fs::write(blob_index_tmp.tmp(), index)
+            .await
+            .at(blob_index_tmp.tmp())?;
+        #[cfg(feature = "serde")]
+        if let Some(path) = &options.manifest {
+            let entries = entries.iter().map(|(path, asset)| (path.as_str(), asset));
+            let manifest = crate::Manifest::from_entries(entries);
+            fs::write(path, manifest.to_json()).await.at(path)?;
+        }
+        blob_file.sync_all().await?;
+        drop(blob_file);
+
+        for tmp in [blob_tmp, blob_index_tmp] {
+            fs::rename(tmp.tmp(), tmp.path()).await.at(tmp.path())?;
+            tmp.persisted();
+        }
+
+        if let Some(progress) = progress {
+            progress(&ProgressEvent::Done(&summary));
+        }
+        Ok(summary)
+    }
+}
+
+impl Archive {
+    /// Async counterpart of [`Archive::open_asset`], reading the blob with a
+    /// [`tokio::fs::File`] of its own. The stream ends exactly at the end of
+    /// the asset. Memory-mapped and in-memory blobs are read from a copy of
+    /// the asset.
+    pub async fn open_asset_async(&self, path: &str) -> Result<Option<AsyncAssetStream>> {
+        let Some(asset) = self.assets.get(path) else {
+            return Ok(None);
+        };
+        self.check_plain("streamed")?;
+
+        let inner = match (self.asset_blob(path, asset)?, &self.blob_path) {
+            (Blob::File(_), Some(blob_path)) => {
+                let path = shard_path(blob_path, asset.shard);
+                let mut file = File::open(&path).await.at(&path)?;
+                file.seek(SeekFrom::Start(asset.offset)).await?;
+                Inner::File {
+                    file,
+                    remaining: asset.len,
+                }
+            }
+            _ => Inner::Memory(Cursor::new(self.read_asset_region(
+                path,
+                asset.shard,
+                asset.offset,
+                asset.len,
+            )?)),
+        };
+        Ok(Some(AsyncAssetStream { inner }))
+    }
+}
+
+/// An [`AsyncRead`] over exactly the bytes of one asset as stored, see
+/// [`Archive::open_asset_async`].
+pub struct AsyncAssetStream {
+    inner: Inner,
+}
+
+enum Inner {
+    File { file: File, remaining: u64 },
+    Memory(Cursor<Vec<u8>>),
+}
+
+impl AsyncRead for AsyncAssetStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let (file, remaining) = match &mut self.get_mut().inner {
+            Inner::Memory(cursor) => return Pin::new(cursor).poll_read(cx, buf),
+            Inner::File { file, remaining } => (file, remaining),
+        };
+        let max = buf
+            .remaining()
+            .min(usize::try_from(*remaining).unwrap_or(usize::MAX));
+        if max == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        let mut limited = ReadBuf::new(buf.initialize_unfilled_to(max));
+        ready!(Pin::new(file).poll_read(cx, &mut limited))?;
+        let bytes_read = limited.filled().len();
+        if bytes_read == 0 {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("blob too short, {remaining} bytes of asset missing"),
+            )));
+        }
+
+        buf.advance(bytes_read);
+        *remaining -= bytes_read as u64;
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Walks `src_dir` depth first like [`Packer::pack`], but with an explicit
+/// stack of directories, writing the assets to `out`. Encoded files are staged
+/// in [`Packer::blob`] and copied to `out` one at a time.
+async fn pack<'o, W: AsyncWrite + Unpin>(
+    src_dir: &'o Path,
+    options: &'o ArchiveOptions,
+    out: &mut W,
+    progress: Option<Progress<'o>>,
+    outputs: Outputs,
+) -> Result<Packer<'o, Vec<u8>>> {
+    let mut packer = Packer::new(Vec::new(), src_dir, options)?;
+    packer.progress = progress;
+    packer.outputs = outputs;
+    let mut stack = Vec::new();
+    if let Some(entries) = enter_dir(&mut packer, src_dir).await? {
+        stack.push(entries);
+    }
+    while let Some(entries) = stack.last_mut() {
+        let Some((path, name, kind)) = entries.next() else {
+            stack.pop();
+            packer.leave_dir();
+            continue;
+        };
+        match packer.visit(&path, &name, kind)? {
+            Visit::File(rel_path) => add_file(&mut packer, &path, rel_path, out).await?,
+            Visit::Symlink(rel_path) => {
+                packer.add_symlink(rel_path, &fs::read_link(&path).await.at(&path)?)?
+            }
+            Visit::Dir => {
+                if let Some(entries) = enter_dir(&mut packer, &path).await? {
+                    stack.push(entries);
+                }
+            }
+            Visit::Skip => {}
+        }
+    }
+    Ok(packer)
+}
+
+type DirEntries = std::vec::IntoIter<(PathBuf, OsString, EntryKind)>;
+
+/// Enters `dir` and lists its entries, or returns `None` if it is skipped.
+async fn enter_dir(packer: &mut Packer<'_, Vec<u8>>, dir: &Path) -> Result<Option<DirEntries>> {
+    let options = packer.options;
+    let canonical = match options.follow_symlinks {
+        true => Some(fs::canonicalize(dir).await.at(dir)?),
+        false => None,
+    };
+    if !packer.enter_dir(dir, canonical)? {
+        return Ok(None);
+    }
+    let mut ignore_files = Vec::new();
+    if options.ignore_files {
+        for name in IGNORE_FILES {
+            let path = dir.join(name);
+            if fs::metadata(&path).await.is_ok_and(|m| m.is_file()) {
+                let content = fs::read_to_string(&path).await.at(&path)?;
+                ignore_files.push((path, content));
+            }
+        }
+    }
+    packer.ignores.enter_with(dir, &ignore_files)?;
+
+    let mut entries = Vec::new();
+    let mut read_dir = fs::read_dir(dir).await.at(dir)?;
+    while let Some(entry) = read_dir.next_entry().await.at(dir)? {
+        let path = entry.path();
+        let kind = if entry.file_type().await.at(&path)?.is_symlink() && !options.follow_symlinks {
+            EntryKind::Symlink
+        } else {
+            match fs::metadata(&path).await {
+                Ok(metadata) if metadata.is_file() => EntryKind::File(metadata.len()),
+                Ok(metadata) if metadata.is_dir() => EntryKind::Dir,
+                _ => continue,
+            }
+        };
+        entries.push((path, entry.file_name(), kind));
+    }
+    if options.deterministic {
+        entries.sort_by(|a, b| a.1.cmp(&b.1));
+    }
+    Ok(Some(entries.into_iter()))
+}
+
+/// Archives the file at `path`, streaming it to `out` unless it is encoded or
+/// deduplicated, reading it once either way unless it's too large to hold in
+/// memory while deduplicating.
+async fn add_file<W: AsyncWrite + Unpin>(
+    packer: &mut Packer<'_, Vec<u8>>,
+    path: &Path,
+    rel_path: String,
+    out: &mut W,
+) -> Result<()> {
+    let mime = packer.mimes.resolve(&rel_path);
+    let mut file = File::open(path).await.at(path)?;
+    let metadata = file.metadata().await.at(path)?;
+    let meta = FileMeta::of(packer.options, &metadata);
+    if packer.encodes(mime.as_deref()) {
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).await.at(path)?;
+        packer.add_data(rel_path, data, mime, meta)?;
+        out.write_all(&packer.blob).await?;
+        packer.blob.clear();
+        return Ok(());
+    }
+
+    let mut buf = vec![0; packer.options.buffer_size.unwrap_or(DEFAULT_BUFFER_SIZE)];
+    let file_len = metadata.len();
+    // Too large to hold in memory while finding out whether it's a
+    // duplicate, so it's hashed in a pass of its own, as in the sync packer.
+    let key = match packer.options.dedup && file_len > MAX_BUFFERED_LEN {
+        true => {
+            let mut file = File::open(path).await.at(path)?;
+            let mut hasher = ContentHasher::default();
+            loop {
+                let n = file.read(&mut buf).await.at(path)?;
+                if n == 0 {
+                    break;
+                }
+                hasher.update(&buf[..n]);
+            }
+            Some(hasher.finish())
+        }
+        false => None,
+    };
+    if key.is_some_and(|key| packer.add_duplicate(&rel_path, &mime, meta, key)) {
+        return Ok(());
+    }
+
+    let mut hasher = Checksummer::new(packer.options);
+    // Held in memory while deduplicating, hashed on the way, and only written
+    // once known not to be a duplicate.
+    let mut buffered = (packer.options.dedup && key.is_none()).then(|| {
+        (
+            Vec::with_capacity(file_len as usize),
+            ContentHasher::default(),
+        )
+    });
+    let mut copied = 0;
+    loop {
+        let n = file.read(&mut buf).await.at(path)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        match &mut buffered {
+            Some((data, content_hasher)) => {
+                data.extend_from_slice(&buf[..n]);
+                content_hasher.update(&buf[..n]);
+            }
+            None => out.write_all(&buf[..n]).await?,
+        }
+        copied += n as u64;
+    }
+    if copied != file_len {
+        tracing::error!("file changed while archiving: {rel_path}");
+        return Err(MonolithicaError::FileChanged {
+            path: rel_path.to_owned(),
+            read: copied,
+            expected: file_len,
+        });
+    }
+    let key = match buffered {
+        Some((data, content_hasher)) => {
+            let key = content_hasher.finish();
+            if packer.add_duplicate(&rel_path, &mime, meta, key) {
+                return Ok(());
+            }
+            out.write_all(&data).await?;
+            Some(key)
+        }
+        None => key,
+    };
+    packer.push_raw(rel_path, mime, meta, file_len, hasher.finish());
+    if let Some(key) = key {
+        packer.remember(key);
+    }
+    Ok(())
+}
+
+/// Async counterpart of [`AssetArchiver::check_path`].
+async fn check_path(path: &Path, overwrite_existing: bool) -> Result<()> {
+    if let Ok(metadata) = fs::symlink_metadata(path).await {
+        if metadata.is_file() || metadata.is_symlink() {
+            if !overwrite_existing {
+                tracing::error!("file already exists");
+                return Err(MonolithicaError::FileExists(path.to_owned()));
+            }
+        } else {
+            tracing::error!("path exists but not a file");
+            return Err(MonolithicaError::NotAFile(path.to_owned()));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        test_util::{build_fixture, fixture_dir, write_file},
+        Archive, ArchiveOptions, AssetArchiver, MonolithicaError,
+    };
+    use std::{fs, path::Path};
+    use tokio::io::AsyncReadExt;
+
+    fn build_sync(src: &Path, root: &Path, options: &ArchiveOptions) -> (Vec<u8>, Vec<u8>) {
+        let (blob, index) = (root.join("sync.blob"), root.join("sync.blob.idx"));
+        AssetArchiver::create_archive_with_options(src, &blob, &index, true, options).unwrap();
+        (fs::read(blob).unwrap(), fs::read(index).unwrap())
+    }
+
+    #[tokio::test]
+    async fn matches_sync() {
+        let root = fixture_dir("async_matches_sync");
+        let src = root.join("src");
+        write_file(&src.join(".ignore"), "*.log\n");
+        write_file(&src.join("index.html"), "<html></html>".repeat(64));
+        write_file(&src.join("debug.log"), "log");
+        write_file(&src.join("js/app.js"), "console.log(1);");
+        write_file(&src.join("js/lib/util.js"), "export {};");
+        write_file(&src.join("css/site.css"), "body {}");
+        write_file(&src.join("css/copy.css"), "body {}");
+
+        let options = ArchiveOptions {
+            ignore_files: true,
+            deterministic: true,
+            dedup: true,
+            mtime: true,
+            content_hash: true,
+            buffer_size: Some(7),
+            #[cfg(feature = "gzip")]
+            precompressed: vec![crate::Encoding::Gzip],
+            #[cfg(feature = "zstd")]
+            blob_compression: Some(crate::Encoding::Zstd),
+            ..Default::default()
+        };
+
+        let (blob, index) = (root.join("async.blob"), root.join("async.blob.idx"));
+        let summary = AssetArchiver::create_archive_async(&src, &blob, &index, false, &options)
+            .await
+            .unwrap();
+        assert_eq!(summary.file_count, 6);
+        assert_eq!(summary.duplicates, 1);
+        assert_eq!(summary.skipped, 1);
+        assert_eq!(summary.blob_size, fs::metadata(&blob).unwrap().len());
+
+        let (sync_blob, sync_index) = build_sync(&src, &root, &options);
+        assert_eq!(fs::read(&blob).unwrap(), sync_blob);
+        assert_eq!(fs::read(&index).unwrap(), sync_index);
+
+        let err = AssetArchiver::create_archive_async(&src, &blob, &index, false, &options)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, MonolithicaError::FileExists(path) if path == blob));
+    }
+
+    #[tokio::test]
+    async fn open_asset_async() {
+        let (blob, index) = build_fixture("async_open_asset");
+        let archive = Archive::open(&blob, &index).unwrap();
+
+        let mut stream = archive
+            .open_asset_async("index.html")
+            .await
+            .unwrap()
+            .unwrap();
+        let mut bytes = Vec::new();
+        stream.read_to_end(&mut bytes).await.unwrap();
+        assert_eq!(bytes, b"<html></html>");
+
+        let mut stream = archive
+            .open_asset_async("js/app.js")
+            .await
+            .unwrap()
+            .unwrap();
+        let mut buf = [0; 4];
+        let mut bytes = Vec::new();
+        loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            bytes.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(bytes, b"console.log(1);");
+        assert!(archive
+            .open_asset_async("missing.js")
+            .await
+            .unwrap()
+            .is_none());
+
+        fs::write(&blob, &fs::read(&blob).unwrap()[..20]).unwrap();
+        let mut stream = archive
+            .open_asset_async("js/app.js")
+            .await
+            .unwrap()
+            .unwrap();
+        let err = stream.read_to_end(&mut Vec::new()).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,168 @@
+use crate::{error::IoContext, Result};
+use std::{
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A temporary sibling of `path` to write a new version of it to, renamed
+/// over it by [`TempPath::persist`] once complete, so `path` never holds a
+/// partial file. The temporary file is removed if the guard is dropped
+/// before.
+pub(crate) struct TempPath {
+    path: PathBuf,
+    tmp: PathBuf,
+    persisted: bool,
+}
+
+impl TempPath {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_owned(),
+            tmp: tmp_path(path),
+            persisted: false,
+        }
+    }
+
+    pub fn tmp(&self) -> &Path {
+        &self.tmp
+    }
+
+    #[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Renames the temporary file over `path`.
+    pub fn persist(self) -> Result<()> {
+        fs::rename(&self.tmp, &self.path).at(&self.path)?;
+        self.persisted();
+        Ok(())
+    }
+
+    /// Marks the temporary file as renamed into place by the caller.
+    #[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+    pub fn persisted(mut self) {
+        self.persisted = true;
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        if !self.persisted {
+            let _ = fs::remove_file(&self.tmp);
+        }
+    }
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_owned();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+/// The files an archive is being written to, so that walking a source
+/// directory they live in doesn't archive them while they grow.
+#[derive(Debug, Default)]
+pub(crate) struct Outputs {
+    /// Canonical paths of the outputs and of their temporary files.
+    paths: Vec<PathBuf>,
+    /// Canonical path of a sharded blob, whose numbered shards are outputs
+    /// too, see [`crate::shard_path`].
+    sharded_blob: Option<PathBuf>,
+}
+
+impl Outputs {
+    /// Outputs in directories that don't exist can't be in a source and are
+    /// left out.
+    pub fn new(paths: &[&Path]) -> Self {
+        let paths = paths
+            .iter()
+            .flat_map(|path| [path.to_path_buf(), tmp_path(path)])
+            .filter_map(|path| canonical_output(&path))
+            .collect();
+        Self {
+            paths,
+            sharded_blob: None,
+        }
+    }
+
+    pub fn sharded(mut self, blob_path: &Path) -> Self {
+        self.sharded_blob = canonical_output(blob_path);
+        self
+    }
+
+    /// Whether the file at `path` is one of the outputs. Only files named
+    /// like one are canonicalized.
+    pub fn contains(&self, path: &Path) -> bool {
+        let Some(name) = path.file_name() else {
+            return false;
+        };
+        let is_shard = |blob: &PathBuf| is_shard_name(blob, name);
+        let named_like_output = self.paths.iter().any(|p| p.file_name() == Some(name))
+            || self.sharded_blob.as_ref().is_some_and(is_shard);
+        if !named_like_output {
+            return false;
+        }
+        let Ok(path) = fs::canonicalize(path) else {
+            return false;
+        };
+        self.paths.contains(&path)
+            || self
+                .sharded_blob
+                .as_ref()
+                .is_some_and(|blob| blob.parent() == path.parent() && is_shard(blob))
+    }
+}
+
+/// `path` with its directory canonicalized, as it may not exist yet.
+fn canonical_output(path: &Path) -> Option<PathBuf> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    Some(fs::canonicalize(dir).ok()?.join(path.file_name()?))
+}
+
+/// Whether `name` is that of a shard after the first of `blob`, or of its
+/// temporary file.
+fn is_shard_name(blob: &Path, name: &OsStr) -> bool {
+    let (Some(blob_name), Some(name)) = (blob.file_name().and_then(OsStr::to_str), name.to_str())
+    else {
+        return false;
+    };
+    let Some(suffix) = name
+        .strip_prefix(blob_name)
+        .and_then(|s| s.strip_prefix('.'))
+    else {
+        return false;
+    };
+    let shard = suffix.strip_suffix(".tmp").unwrap_or(suffix);
+    !shard.is_empty() && shard.bytes().all(|b| b.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::fixture_dir;
+
+    #[test]
+    fn persist_or_remove() {
+        let root = fixture_dir("atomic_persist_or_remove");
+        let path = root.join("test.blob");
+        fs::write(&path, "old").unwrap();
+
+        let tmp = TempPath::new(&path);
+        assert_eq!(tmp.tmp(), root.join("test.blob.tmp"));
+        fs::write(tmp.tmp(), "partial").unwrap();
+        drop(tmp);
+        assert!(!root.join("test.blob.tmp").exists());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+
+        let tmp = TempPath::new(&path);
+        fs::write(tmp.tmp(), "new").unwrap();
+        tmp.persist().unwrap();
+        assert!(!root.join("test.blob.tmp").exists());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+    }
+}
@@ -0,0 +1,187 @@
+//! Command line driver for the library, built with the `cli` feature:
+//!
+//! ```text
+//! monolithica create <dir> <blob> <index> [--force]
+//! monolithica extract <blob> <index> <dir> [--force]
+//! monolithica verify <blob> <index>
+//! monolithica list <blob> <index>
+//! ```
+//!
+//! `monolithica help <command>` describes the arguments of each.
+
+use clap::{Parser, Subcommand};
+use monolithica::{Archive, AssetArchiver, MonolithicaError, VerifyProblem};
+use std::{path::PathBuf, process::ExitCode};
+
+/// Packs the files of a directory into a blob and an index, and reads them
+/// back.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, PartialEq, Eq, Subcommand)]
+enum Command {
+    /// Archive the files under a directory.
+    Create {
+        /// The directory to archive.
+        dir: PathBuf,
+        /// Where to write the blob.
+        blob: PathBuf,
+        /// Where to write the index.
+        index: PathBuf,
+        /// Overwrite the blob and the index if they exist.
+        #[arg(short, long)]
+        force: bool,
+    },
+    /// Write the assets of an archive out as files under a directory.
+    Extract {
+        blob: PathBuf,
+        index: PathBuf,
+        /// The directory to write the files under.
+        dir: PathBuf,
+        /// Overwrite files that exist.
+        #[arg(short, long)]
+        force: bool,
+    },
+    /// Check that the index matches the blob and the assets their checksums.
+    Verify { blob: PathBuf, index: PathBuf },
+    /// Print the path, size and mime of each asset, in path order.
+    List { blob: PathBuf, index: PathBuf },
+}
+
+fn run(command: Command) -> Result<bool, MonolithicaError> {
+    match command {
+        Command::Create {
+            dir,
+            blob,
+            index,
+            force,
+        } => {
+            let summary = AssetArchiver::create_archive(dir, blob, index, force)?;
+            println!(
+                "archived {} files, {} bytes, blob of {} bytes, {} skipped",
+                summary.file_count, summary.total_bytes, summary.blob_size, summary.skipped
+            );
+        }
+        Command::Extract {
+            blob,
+            index,
+            dir,
+            force,
+        } => AssetArchiver::extract_archive(blob, index, dir, force)?,
+        Command::Verify { blob, index } => {
+            let report = AssetArchiver::verify_archive(blob, index)?;
+            for problem in &report.problems {
+                println!("{}", describe(problem));
+            }
+            println!(
+                "checked {} entries and {} checksums, {} problems",
+                report.entries_checked,
+                report.checksums_checked,
+                report.problems.len()
+            );
+            return Ok(report.is_ok());
+        }
+        Command::List { blob, index } => {
+            let archive = Archive::open(blob, index)?;
+            for (path, asset) in (0..archive.len()).filter_map(|i| archive.nth(i)) {
+                let mime = asset.mime.as_deref().unwrap_or("-");
+                println!("{path}\t{}\t{mime}", asset.original_len);
+            }
+        }
+    }
+    Ok(true)
+}
+
+fn describe(problem: &VerifyProblem) -> String {
+    match problem {
+        VerifyProblem::OutOfBounds { path, offset, len } => {
+            format!("{path}: {len} bytes at offset {offset} out of bounds")
+        }
+        VerifyProblem::Overlap { path, other } => format!("{path}: overlaps {other}"),
+        VerifyProblem::Gap { offset, len } => format!("unused {len} bytes at offset {offset}"),
+        VerifyProblem::ChecksumMismatch { path } => format!("{path}: checksum mismatch"),
+        VerifyProblem::Unreadable { path, error } => format!("{path}: {error}"),
+        VerifyProblem::ArchiveChecksumMismatch => "archive checksum mismatch".to_owned(),
+    }
+}
+
+fn main() -> ExitCode {
+    // Exits with usage and status 2 on bad arguments, or with the help.
+    let Cli { command } = Cli::parse();
+    match run(command) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{error::ErrorKind, CommandFactory};
+
+    fn parse(args: &str) -> Result<Command, ErrorKind> {
+        let args = ["monolithica"].into_iter().chain(args.split_whitespace());
+        Cli::try_parse_from(args)
+            .map(|cli| cli.command)
+            .map_err(|e| e.kind())
+    }
+
+    #[test]
+    fn parse_commands() {
+        Cli::command().debug_assert();
+        assert_eq!(
+            parse("create dist a.blob a.idx --force"),
+            Ok(Command::Create {
+                dir: "dist".into(),
+                blob: "a.blob".into(),
+                index: "a.idx".into(),
+                force: true,
+            })
+        );
+        assert_eq!(
+            parse("list a.blob a.idx"),
+            Ok(Command::List {
+                blob: "a.blob".into(),
+                index: "a.idx".into(),
+            })
+        );
+        assert_eq!(
+            parse("extract -f -- -old.blob a.idx out"),
+            Ok(Command::Extract {
+                blob: "-old.blob".into(),
+                index: "a.idx".into(),
+                dir: "out".into(),
+                force: true,
+            })
+        );
+        assert_eq!(
+            parse("verify a.blob"),
+            Err(ErrorKind::MissingRequiredArgument)
+        );
+        assert_eq!(
+            parse("list a.blob a.idx extra"),
+            Err(ErrorKind::UnknownArgument)
+        );
+        assert_eq!(
+            parse("list a.blob a.idx --force"),
+            Err(ErrorKind::UnknownArgument)
+        );
+        assert_eq!(
+            parse("create dist a.blob a.idx --unknown"),
+            Err(ErrorKind::UnknownArgument)
+        );
+        assert_eq!(parse("create --help"), Err(ErrorKind::DisplayHelp));
+        assert_eq!(
+            parse(""),
+            Err(ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand)
+        );
+    }
+}
@@ -0,0 +1,100 @@
+//! Helpers for `build.rs` scripts, to bundle assets into the binary.
+//!
+//! Archive the assets into `OUT_DIR` from the build script, with
+//! `monolithica` listed under `[build-dependencies]` as well:
+//!
+//! ```no_run
+//! // build.rs
+//! fn main() -> anyhow::Result<()> {
+//!     monolithica::build::embed("assets", "assets")?;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! Then include the blob and its index in the program and serve assets out of
+//! them, e.g. with the `axum` feature:
+//!
+//! ```ignore
+//! use monolithica::{Archive, ArchiveService};
+//!
+//! static BLOB: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/assets.blob"));
+//! static INDEX: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/assets.blob.idx"));
+//!
+//! let archive = Archive::from_bytes(BLOB, INDEX)?;
+//! let app = ArchiveService::new(archive).into_router();
+//! ```
+
+use crate::{ArchiveOptions, ArchiveSummary, AssetArchiver, MonolithicaError, Result};
+use std::path::Path;
+
+/// Archives `src_dir` into `$OUT_DIR/<name>.blob` and `$OUT_DIR/<name>.blob.idx`,
+/// and tells cargo to run the build script again when anything under
+/// `src_dir` changes.
+pub fn embed(src_dir: impl AsRef<Path>, name: &str) -> Result<ArchiveSummary> {
+    embed_with_options(src_dir, name, &ArchiveOptions::default())
+}
+
+/// Same as [`embed`], with the given options.
+pub fn embed_with_options(
+    src_dir: impl AsRef<Path>,
+    name: &str,
+    options: &ArchiveOptions,
+) -> Result<ArchiveSummary> {
+    let out_dir = std::env::var_os("OUT_DIR").ok_or_else(|| {
+        MonolithicaError::InvalidInput("OUT_DIR not set, not in a build script".to_owned())
+    })?;
+    embed_into(src_dir.as_ref(), Path::new(&out_dir), name, options)
+}
+
+fn embed_into(
+    src_dir: &Path,
+    out_dir: &Path,
+    name: &str,
+    options: &ArchiveOptions,
+) -> Result<ArchiveSummary> {
+    println!("cargo:rerun-if-changed={}", src_dir.display());
+    AssetArchiver::create_archive_with_options(
+        src_dir,
+        out_dir.join(format!("{name}.blob")),
+        out_dir.join(format!("{name}.blob.idx")),
+        true,
+        options,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_util::{fixture_dir, write_file},
+        Archive,
+    };
+    use std::fs;
+
+    #[test]
+    fn embed_into_out_dir() {
+        let root = fixture_dir("build_embed_into_out_dir");
+        write_file(&root.join("assets/index.html"), "<html></html>");
+        let out_dir = root.join("out");
+        fs::create_dir(&out_dir).unwrap();
+
+        for _ in 0..2 {
+            let summary = embed_into(
+                &root.join("assets"),
+                &out_dir,
+                "assets",
+                &Default::default(),
+            )
+            .unwrap();
+            assert_eq!(summary.file_count, 1);
+        }
+
+        let blob = fs::read(out_dir.join("assets.blob")).unwrap().leak();
+        let index = fs::read(out_dir.join("assets.blob.idx")).unwrap();
+        let archive = Archive::from_bytes(blob, index).unwrap();
+        assert_eq!(
+            archive.get("index.html").unwrap().unwrap(),
+            b"<html></html>"
+        );
+    }
+}
@@ -0,0 +1,706 @@
+#[cfg(feature = "encryption")]
+use crate::EncryptionKey;
+use crate::{
+    atomic::Outputs, packer::Source, progress::ProgressCallback, ArchiveOptions, ArchiveSummary,
+    AssetArchiver, DuplicatePaths, Encoding, IndexFormat, MimeGuesser, MonolithicaError,
+    OversizedFiles, ProgressEvent, Result,
+};
+use std::path::{Path, PathBuf};
+
+/// Named, defaulted options for creating an archive, so new options don't
+/// change the signature of [`AssetArchiver::create_archive`].
+///
+/// ```no_run
+/// # fn main() -> anyhow::Result<()> {
+/// monolithica::ArchiveBuilder::new()
+///     .source("dist")
+///     .blob("assets.blob")
+///     .index("assets.blob.idx")
+///     .overwrite(true)
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ArchiveBuilder {
+    source: Option<PathBuf>,
+    blob: Option<PathBuf>,
+    index: Option<PathBuf>,
+    overwrite: bool,
+    options: ArchiveOptions,
+    progress: Option<ProgressCallback>,
+}
+
+impl ArchiveBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The directory to archive.
+    pub fn source(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.source = Some(dir.into());
+        self
+    }
+
+    pub fn blob(mut self, path: impl Into<PathBuf>) -> Self {
+        self.blob = Some(path.into());
+        self
+    }
+
+    pub fn index(mut self, path: impl Into<PathBuf>) -> Self {
+        self.index = Some(path.into());
+        self
+    }
+
+    /// Whether to replace existing blob and index files, `false` by default.
+    pub fn overwrite(mut self, overwrite: bool) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    pub fn index_format(mut self, index_format: IndexFormat) -> Self {
+        self.options.index_format = index_format;
+        self
+    }
+
+    /// See [`ArchiveOptions::compression`].
+    pub fn compression(mut self, encoding: Encoding) -> Self {
+        self.options.compression = Some(encoding);
+        self
+    }
+
+    /// See [`ArchiveOptions::blob_compression`].
+    pub fn blob_compression(mut self, encoding: Encoding) -> Self {
+        self.options.blob_compression = Some(encoding);
+        self
+    }
+
+    /// See [`ArchiveOptions::precompressed`].
+    pub fn precompressed(mut self, encodings: impl IntoIterator<Item = Encoding>) -> Self {
+        self.options.precompressed = encodings.into_iter().collect();
+        self
+    }
+
+    /// Sets [`ArchiveOptions::compressible_mimes`].
+    pub fn compressible_mimes<S: Into<String>>(
+        mut self,
+        mimes: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.options.compressible_mimes = Some(mimes.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Adds a glob pattern to [`ArchiveOptions::include`].
+    pub fn include(mut self, pattern: impl Into<String>) -> Self {
+        self.options.include.push(pattern.into());
+        self
+    }
+
+    /// Adds a glob pattern to [`ArchiveOptions::exclude`].
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.options.exclude.push(pattern.into());
+        self
+    }
+
+    /// See [`ArchiveOptions::strip_prefix`].
+    pub fn strip_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.options.strip_prefix = Some(prefix.into());
+        self
+    }
+
+    /// See [`ArchiveOptions::add_prefix`].
+    pub fn add_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.options.add_prefix = Some(prefix.into());
+        self
+    }
+
+    /// See [`ArchiveOptions::ignore_files`].
+    pub fn ignore_files(mut self, ignore_files: bool) -> Self {
+        self.options.ignore_files = ignore_files;
+        self
+    }
+
+    /// Adds a `.gitignore`-style pattern to [`ArchiveOptions::ignore_patterns`].
+    pub fn ignore_pattern(mut self, pattern: impl Into<String>) -> Self {
+        self.options.ignore_patterns.push(pattern.into());
+        self
+    }
+
+    /// Adds an entry to [`ArchiveOptions::mime_by_extension`].
+    pub fn mime_for_extension(
+        mut self,
+        extension: impl Into<String>,
+        mime: impl Into<String>,
+    ) -> Self {
+        self.options
+            .mime_by_extension
+            .insert(extension.into(), mime.into());
+        self
+    }
+
+    /// Sets [`ArchiveOptions::mime_guesser`].
+    pub fn mime_guesser(
+        mut self,
+        guess: impl Fn(&Path) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.options.mime_guesser = Some(MimeGuesser::new(guess));
+        self
+    }
+
+    /// Sets [`ArchiveOptions::default_mime`].
+    pub fn default_mime(mut self, mime: impl Into<String>) -> Self {
+        self.options.default_mime = Some(mime.into());
+        self
+    }
+
+    /// Adds an entry to [`ArchiveOptions::mime_by_glob`].
+    pub fn mime_for_glob(mut self, pattern: impl Into<String>, mime: impl Into<String>) -> Self {
+        self.options
+            .mime_by_glob
+            .push((pattern.into(), mime.into()));
+        self
+    }
+
+    /// See [`ArchiveOptions::skip_hidden`].
+    pub fn skip_hidden(mut self, skip_hidden: bool) -> Self {
+        self.options.skip_hidden = skip_hidden;
+        self
+    }
+
+    /// Skips files larger than `max_file_size` bytes, see
+    /// [`ArchiveBuilder::fail_on_oversized`].
+    pub fn max_file_size(mut self, max_file_size: u64) -> Self {
+        self.options.max_file_size = Some(max_file_size);
+        self
+    }
+
+    /// Fails instead of skipping files larger than
+    /// [`ArchiveBuilder::max_file_size`].
+    pub fn fail_on_oversized(mut self, fail: bool) -> Self {
+        self.options.oversized = if fail {
+            OversizedFiles::Error
+        } else {
+            OversizedFiles::Skip
+        };
+        self
+    }
+
+    /// Skips instead of failing on files whose path collides with one archived
+    /// before, see [`ArchiveOptions::duplicate_paths`].
+    pub fn skip_duplicate_paths(mut self, skip: bool) -> Self {
+        self.options.duplicate_paths = if skip {
+            DuplicatePaths::Skip
+        } else {
+            DuplicatePaths::Error
+        };
+        self
+    }
+
+    /// See [`ArchiveOptions::follow_symlinks`].
+    pub fn follow_symlinks(mut self, follow: bool) -> Self {
+        self.options.follow_symlinks = follow;
+        self
+    }
+
+    /// See [`ArchiveOptions::store_symlinks`].
+    pub fn store_symlinks(mut self, store: bool) -> Self {
+        self.options.store_symlinks = store;
+        self
+    }
+
+    /// See [`ArchiveOptions::deterministic`].
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.options.deterministic = deterministic;
+        self
+    }
+
+    /// See [`ArchiveOptions::mtime`].
+    pub fn mtime(mut self, mtime: bool) -> Self {
+        self.options.mtime = mtime;
+        self
+    }
+
+    /// See [`ArchiveOptions::mode`].
+    pub fn mode(mut self, mode: bool) -> Self {
+        self.options.mode = mode;
+        self
+    }
+
+    /// See [`ArchiveOptions::content_hash`].
+    pub fn content_hash(mut self, content_hash: bool) -> Self {
+        self.options.content_hash = content_hash;
+        self
+    }
+
+    /// See [`ArchiveOptions::manifest`].
+    #[cfg(feature = "serde")]
+    pub fn manifest(mut self, path: impl Into<PathBuf>) -> Self {
+        self.options.manifest = Some(path.into());
+        self
+    }
+
+    /// See [`ArchiveOptions::dedup`].
+    pub fn dedup(mut self, dedup: bool) -> Self {
+        self.options.dedup = dedup;
+        self
+    }
+
+    /// See [`ArchiveOptions::parallel`].
+    #[cfg(feature = "rayon")]
+    pub fn parallel(mut self, parallel: bool) -> Self {
+        self.options.parallel = parallel;
+        self
+    }
+
+    /// See [`ArchiveOptions::buffer_size`].
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.options.buffer_size = Some(buffer_size);
+        self
+    }
+
+    /// See [`ArchiveOptions::shard_size`].
+    pub fn shard_size(mut self, shard_size: u64) -> Self {
+        self.options.shard_size = Some(shard_size);
+        self
+    }
+
+    /// See [`ArchiveOptions::encryption_key`].
+    #[cfg(feature = "encryption")]
+    pub fn encryption_key(mut self, key: EncryptionKey) -> Self {
+        self.options.encryption_key = Some(key);
+        self
+    }
+
+    /// Creates the archive, failing if the source, blob or index isn't set.
+    /// Calls `callback` after each file is archived, then once the archive
+    /// is written, e.g. to drive a progress bar. Builds without one don't pay
+    /// for it. Clones of the builder share the callback.
+    pub fn progress(mut self, callback: impl FnMut(&ProgressEvent) + Send + 'static) -> Self {
+        self.progress = Some(ProgressCallback::new(callback));
+        self
+    }
+
+    pub fn build(&self) -> Result<ArchiveSummary> {
+        let mut report = |event: &ProgressEvent| self.report(event);
+        AssetArchiver::create_archive_reporting(
+            Source::Dirs(
+                &[required(&self.source, "source directory not set")?],
+                Outputs::default(),
+            ),
+            required(&self.blob, "blob path not set")?,
+            required(&self.index, "index path not set")?,
+            self.overwrite,
+            &self.options,
+            self.progress.is_some().then_some(&mut report),
+        )
+    }
+
+    fn report(&self, event: &ProgressEvent) {
+        if let Some(progress) = &self.progress {
+            progress.report(event);
+        }
+    }
+
+    /// Same as [`ArchiveBuilder::build`], with
+    /// [`AssetArchiver::create_archive_async`].
+    #[cfg(feature = "tokio")]
+    pub async fn build_async(&self) -> Result<ArchiveSummary> {
+        let mut report = |event: &ProgressEvent| self.report(event);
+        AssetArchiver::create_archive_async_reporting(
+            required(&self.source, "source directory not set")?,
+            required(&self.blob, "blob path not set")?,
+            required(&self.index, "index path not set")?,
+            self.overwrite,
+            &self.options,
+            self.progress.is_some().then_some(&mut report),
+        )
+        .await
+    }
+}
+
+fn required<'a>(path: &'a Option<PathBuf>, what: &str) -> Result<&'a PathBuf> {
+    path.as_ref()
+        .ok_or_else(|| MonolithicaError::InvalidInput(what.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_util::{fixture_dir, write_file},
+        Archive,
+    };
+
+    #[test]
+    fn build_archive() {
+        let root = fixture_dir("builder_build_archive");
+        write_file(&root.join("src/index.html"), "<html></html>");
+        let builder = ArchiveBuilder::new()
+            .source(root.join("src"))
+            .blob(root.join("test.blob"))
+            .index(root.join("test.blob.idx"));
+        builder.build().unwrap();
+        assert!(builder.build().is_err());
+        builder.clone().overwrite(true).build().unwrap();
+
+        let archive = Archive::open(root.join("test.blob"), root.join("test.blob.idx")).unwrap();
+        assert_eq!(
+            archive.get("index.html").unwrap().unwrap(),
+            b"<html></html>"
+        );
+
+        let err = ArchiveBuilder::new().source(root.join("src")).build();
+        assert!(err.unwrap_err().to_string().contains("blob path not set"));
+    }
+
+    #[test]
+    fn filter_files() {
+        let root = fixture_dir("builder_filter_files");
+        write_file(&root.join("src/index.html"), "<html></html>");
+        write_file(&root.join("src/js/app.js"), "console.log(1);");
+        write_file(&root.join("src/js/app.js.map"), "{}");
+        write_file(&root.join("src/node_modules/lib/index.js"), "var v;");
+        let summary = ArchiveBuilder::new()
+            .source(root.join("src"))
+            .blob(root.join("test.blob"))
+            .index(root.join("test.blob.idx"))
+            .include("**/*.js")
+            .exclude("node_modules/**")
+            .build()
+            .unwrap();
+        assert_eq!((summary.file_count, summary.skipped), (1, 3));
+
+        let archive = Archive::open(root.join("test.blob"), root.join("test.blob.idx")).unwrap();
+        assert_eq!(archive.paths().collect::<Vec<_>>(), ["js/app.js"]);
+    }
+
+    #[test]
+    fn progress_events() {
+        let root = fixture_dir("builder_progress_events");
+        write_file(&root.join("src/index.html"), "<html></html>");
+        write_file(&root.join("src/js/app.js"), "console.log(1);");
+        let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        let builder = ArchiveBuilder::new()
+            .source(root.join("src"))
+            .blob(root.join("test.blob"))
+            .index(root.join("test.blob.idx"))
+            .deterministic(true)
+            .progress(move |event| {
+                let event = match event {
+                    ProgressEvent::File {
+                        path,
+                        bytes_written,
+                        file_count,
+                    } => format!("{path} {bytes_written} {file_count}"),
+                    ProgressEvent::Done(summary) => format!("done {}", summary.file_count),
+                };
+                recorded.lock().unwrap().push(event);
+            });
+        let summary = builder.build().unwrap();
+        assert_eq!(summary.file_count, 2);
+        assert_eq!(
+            *events.lock().unwrap(),
+            ["index.html 13 1", "js/app.js 28 2", "done 2"]
+        );
+
+        #[cfg(feature = "tokio")]
+        {
+            fn assert_send<T: Send>(_: &T) {}
+            assert_send(&builder.build_async());
+        }
+    }
+
+    #[test]
+    fn key_prefixes() {
+        let root = fixture_dir("builder_key_prefixes");
+        write_file(&root.join("src/dist/index.html"), "<html></html>");
+        write_file(&root.join("src/dist/js/app.js"), "console.log(1);");
+        write_file(&root.join("src/README.md"), "# readme");
+        let builder = ArchiveBuilder::new()
+            .source(root.join("src"))
+            .blob(root.join("test.blob"))
+            .index(root.join("test.blob.idx"))
+            .overwrite(true)
+            .strip_prefix("dist/");
+        let err = builder.clone().build().unwrap_err();
+        assert!(matches!(err, MonolithicaError::InvalidPath(_)), "{err}");
+
+        let builder = builder.include("dist/**").add_prefix("/static");
+        builder.build().unwrap();
+        let archive = Archive::open(root.join("test.blob"), root.join("test.blob.idx")).unwrap();
+        let mut paths: Vec<_> = archive.paths().collect();
+        paths.sort_unstable();
+        assert_eq!(paths, ["static/index.html", "static/js/app.js"]);
+        assert_eq!(
+            archive.get("static/js/app.js").unwrap().unwrap(),
+            b"console.log(1);"
+        );
+
+        let err = builder.add_prefix("../up").build().unwrap_err();
+        assert!(matches!(err, MonolithicaError::InvalidInput(_)), "{err}");
+    }
+
+    #[test]
+    fn respect_ignore_files() {
+        let root = fixture_dir("builder_respect_ignore_files");
+        write_file(&root.join("src/.gitignore"), "*.log\n");
+        write_file(&root.join("src/index.html"), "<html></html>");
+        write_file(&root.join("src/debug.log"), "");
+        write_file(&root.join("src/js/app.js"), "console.log(1);");
+        write_file(&root.join("src/js/app.tmp"), "");
+        write_file(&root.join("src/.git/HEAD"), "ref: refs/heads/main");
+        let builder = ArchiveBuilder::new()
+            .source(root.join("src"))
+            .blob(root.join("test.blob"))
+            .index(root.join("test.blob.idx"))
+            .overwrite(true);
+
+        builder.build().unwrap();
+        let archive = Archive::open(root.join("test.blob"), root.join("test.blob.idx")).unwrap();
+        assert_eq!(archive.len(), 6);
+
+        builder
+            .ignore_files(true)
+            .ignore_pattern("*.tmp")
+            .build()
+            .unwrap();
+        let archive = Archive::open(root.join("test.blob"), root.join("test.blob.idx")).unwrap();
+        let mut paths: Vec<_> = archive.paths().collect();
+        paths.sort();
+        assert_eq!(paths, [".gitignore", "index.html", "js/app.js"]);
+    }
+
+    #[test]
+    fn skip_hidden() {
+        let root = fixture_dir("builder_skip_hidden");
+        write_file(&root.join("src/index.html"), "<html></html>");
+        write_file(&root.join("src/.DS_Store"), "");
+        write_file(&root.join("src/js/.app.js.swp"), "");
+        write_file(&root.join("src/.git/HEAD"), "ref: refs/heads/main");
+        let summary = ArchiveBuilder::new()
+            .source(root.join("src"))
+            .blob(root.join("test.blob"))
+            .index(root.join("test.blob.idx"))
+            .skip_hidden(true)
+            .build()
+            .unwrap();
+        assert_eq!((summary.file_count, summary.skipped), (1, 3));
+
+        let archive = Archive::open(root.join("test.blob"), root.join("test.blob.idx")).unwrap();
+        assert_eq!(archive.paths().collect::<Vec<_>>(), ["index.html"]);
+    }
+
+    #[test]
+    fn max_file_size() {
+        let root = fixture_dir("builder_max_file_size");
+        write_file(&root.join("src/index.html"), "<html></html>");
+        write_file(&root.join("src/dump.sql"), vec![b'x'; 1024]);
+        let builder = ArchiveBuilder::new()
+            .source(root.join("src"))
+            .blob(root.join("test.blob"))
+            .index(root.join("test.blob.idx"))
+            .overwrite(true)
+            .max_file_size(1023);
+
+        let summary = builder.build().unwrap();
+        assert_eq!((summary.file_count, summary.skipped), (1, 1));
+        assert_eq!(summary.oversized, ["dump.sql"]);
+
+        let err = builder.clone().fail_on_oversized(true).build().unwrap_err();
+        assert!(err.to_string().contains("dump.sql is 1024 bytes"));
+
+        let summary = builder.max_file_size(1024).build().unwrap();
+        assert_eq!(summary.file_count, 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn duplicate_paths() {
+        let root = fixture_dir("builder_duplicate_paths");
+        write_file(&root.join("src/a/b.txt"), "slash");
+        write_file(&root.join("src/a\\b.txt"), "backslash");
+        let builder = ArchiveBuilder::new()
+            .source(root.join("src"))
+            .blob(root.join("test.blob"))
+            .index(root.join("test.blob.idx"))
+            .deterministic(true);
+
+        // `a` sorts first, so `a\b.txt` is the duplicate.
+        let err = builder.build().unwrap_err();
+        assert!(matches!(err, MonolithicaError::AssetExists(ref path) if path == "a\\b.txt"));
+
+        let summary = builder.skip_duplicate_paths(true).build().unwrap();
+        assert_eq!((summary.file_count, summary.skipped), (1, 1));
+        assert_eq!(summary.path_collisions, ["a\\b.txt"]);
+        let archive = Archive::open(root.join("test.blob"), root.join("test.blob.idx")).unwrap();
+        assert_eq!(archive.get_string("a/b.txt").unwrap().unwrap(), "slash");
+    }
+
+    #[test]
+    fn small_buffer_size() {
+        let root = fixture_dir("builder_small_buffer_size");
+        write_file(&root.join("src/index.html"), "<html><body></body></html>");
+        ArchiveBuilder::new()
+            .source(root.join("src"))
+            .blob(root.join("test.blob"))
+            .index(root.join("test.blob.idx"))
+            .buffer_size(3)
+            .build()
+            .unwrap();
+
+        let archive = Archive::open(root.join("test.blob"), root.join("test.blob.idx")).unwrap();
+        assert_eq!(
+            archive.get("index.html").unwrap().unwrap(),
+            b"<html><body></body></html>"
+        );
+        assert!(archive.verify().is_ok());
+    }
+
+    #[test]
+    fn deterministic_output() {
+        let root = fixture_dir("builder_deterministic_output");
+        let build = |name: &str, files: &[&str]| {
+            for file in files {
+                write_file(&root.join(name).join(file), file);
+            }
+            ArchiveBuilder::new()
+                .source(root.join(name))
+                .blob(root.join(format!("{name}.blob")))
+                .index(root.join(format!("{name}.blob.idx")))
+                .deterministic(true)
+                .build()
+                .unwrap();
+            (
+                std::fs::read(root.join(format!("{name}.blob"))).unwrap(),
+                std::fs::read(root.join(format!("{name}.blob.idx"))).unwrap(),
+            )
+        };
+
+        let files = ["b.js", "a.html", "c/z.css", "c/a.css", "B.txt"];
+        let mut reversed = files;
+        reversed.reverse();
+        let first = build("first", &files);
+        assert_eq!(first, build("second", &reversed));
+        let index = String::from_utf8(first.1).unwrap();
+        let paths: Vec<_> = index
+            .lines()
+            .map(|l| l.split("//").next().unwrap())
+            .collect();
+        assert_eq!(paths, ["B.txt", "a.html", "b.js", "c/a.css", "c/z.css"]);
+    }
+
+    #[test]
+    fn mime_overrides() {
+        let root = fixture_dir("builder_mime_overrides");
+        write_file(&root.join("src/app.wasm"), "\0asm");
+        write_file(&root.join("src/site.webmanifest"), "{}");
+        write_file(&root.join("src/index.html"), "<html></html>");
+        write_file(&root.join("src/LICENSE"), "MIT");
+        ArchiveBuilder::new()
+            .source(root.join("src"))
+            .blob(root.join("test.blob"))
+            .index(root.join("test.blob.idx"))
+            .mime_for_extension("wasm", "application/wasm")
+            .mime_for_glob("*.webmanifest", "application/manifest+json")
+            .default_mime("text/plain")
+            .build()
+            .unwrap();
+
+        let archive = Archive::open(root.join("test.blob"), root.join("test.blob.idx")).unwrap();
+        let mime = |path| archive.locate_asset(path).unwrap().mime.as_deref();
+        assert_eq!(mime("app.wasm"), Some("application/wasm"));
+        assert_eq!(mime("site.webmanifest"), Some("application/manifest+json"));
+        assert_eq!(mime("index.html"), Some("text/html"));
+        assert_eq!(mime("LICENSE"), Some("text/plain"));
+    }
+
+    #[test]
+    fn dedup() {
+        let root = fixture_dir("builder_dedup");
+        write_file(&root.join("src/a/icon.svg"), "<svg></svg>");
+        write_file(&root.join("src/b/icon.svg"), "<svg></svg>");
+        write_file(&root.join("src/b/other.svg"), "<svg/>");
+        let summary = ArchiveBuilder::new()
+            .source(root.join("src"))
+            .blob(root.join("test.blob"))
+            .index(root.join("test.blob.idx"))
+            .dedup(true)
+            .build()
+            .unwrap();
+        assert_eq!(summary.file_count, 3);
+        assert_eq!(summary.duplicates, 1);
+        assert_eq!(summary.blob_size, crate::format::HEADER_LEN + 11 + 6);
+
+        let archive = Archive::open(root.join("test.blob"), root.join("test.blob.idx")).unwrap();
+        let (a, b) = (
+            archive.locate_asset("a/icon.svg").unwrap(),
+            archive.locate_asset("b/icon.svg").unwrap(),
+        );
+        assert_eq!((a.offset, a.len), (b.offset, b.len));
+        assert_eq!(archive.get("b/icon.svg").unwrap().unwrap(), b"<svg></svg>");
+        assert!(archive.verify().unwrap().is_ok());
+    }
+
+    #[test]
+    fn content_hash() {
+        let root = fixture_dir("builder_content_hash");
+        write_file(&root.join("src/index.html"), "<html></html>");
+        write_file(&root.join("src/js/app.js"), "console.log(1);");
+        let builder = ArchiveBuilder::new()
+            .source(root.join("src"))
+            .blob(root.join("test.blob"))
+            .index(root.join("test.blob.idx"))
+            .overwrite(true)
+            .content_hash(true);
+        let expected = vec![
+            ("index.html", "b633a587c652d023"),
+            ("js/app.js", "35c146f76e129477"),
+        ];
+
+        builder.build().unwrap();
+        let archive = Archive::open(root.join("test.blob"), root.join("test.blob.idx")).unwrap();
+        assert_eq!(archive.content_hashes(), expected);
+
+        #[cfg(feature = "gzip")]
+        {
+            builder.clone().compression(Encoding::Gzip).build().unwrap();
+            let archive =
+                Archive::open(root.join("test.blob"), root.join("test.blob.idx")).unwrap();
+            assert_eq!(archive.content_hashes(), expected);
+        }
+
+        builder.content_hash(false).build().unwrap();
+        let archive = Archive::open(root.join("test.blob"), root.join("test.blob.idx")).unwrap();
+        assert!(archive.content_hashes().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks() {
+        let root = fixture_dir("builder_symlinks");
+        write_file(&root.join("src/js/app.js"), "console.log(1);");
+        write_file(&root.join("outside/secret.txt"), "secret");
+        std::os::unix::fs::symlink(root.join("outside"), root.join("src/linked")).unwrap();
+        std::os::unix::fs::symlink(root.join("src"), root.join("src/js/loop")).unwrap();
+        let builder = ArchiveBuilder::new()
+            .source(root.join("src"))
+            .blob(root.join("test.blob"))
+            .index(root.join("test.blob.idx"))
+            .overwrite(true);
+
+        let summary = builder.build().unwrap();
+        assert_eq!(summary.file_count, 1);
+        let mut symlinks = summary.symlinks.clone();
+        symlinks.sort();
+        assert_eq!(symlinks, ["js/loop", "linked"]);
+
+        let summary = builder.follow_symlinks(true).build().unwrap();
+        assert_eq!((summary.file_count, summary.skipped), (2, 1));
+        let archive = Archive::open(root.join("test.blob"), root.join("test.blob.idx")).unwrap();
+        assert_eq!(
+            archive.get("linked/secret.txt").unwrap().unwrap(),
+            b"secret"
+        );
+    }
+}
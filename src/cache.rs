@@ -0,0 +1,161 @@
+use crate::Result;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, MutexGuard, PoisonError,
+    },
+};
+
+/// Counters of an [`crate::Archive`]'s decode cache, see
+/// [`crate::Archive::with_decode_cache`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Reads of compressed assets served from the cache.
+    pub hits: u64,
+    /// Reads of compressed assets that had to decode them.
+    pub misses: u64,
+    /// Number of assets held.
+    pub entries: usize,
+    /// Decoded bytes held.
+    pub bytes: u64,
+}
+
+/// The decoded bytes of recently read compressed assets by path, evicting the
+/// least recently used ones once over `capacity` bytes.
+pub(crate) struct DecodeCache {
+    capacity: u64,
+    lru: Mutex<Lru>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Default)]
+struct Lru {
+    /// Bytes and last use of each path.
+    entries: HashMap<String, (Vec<u8>, u64)>,
+    /// Paths by last use, least recent first.
+    order: BTreeMap<u64, String>,
+    clock: u64,
+    bytes: u64,
+}
+
+impl DecodeCache {
+    pub fn new(capacity: u64) -> Self {
+        Self {
+            capacity,
+            lru: Mutex::new(Lru::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// The cached bytes of `path`, otherwise those `decode` returns, cached
+    /// unless larger than the whole cache. The lock isn't held while decoding,
+    /// so concurrent misses on one path may each decode it.
+    pub fn get_or_decode(
+        &self,
+        path: &str,
+        decode: impl FnOnce() -> Result<Vec<u8>>,
+    ) -> Result<Vec<u8>> {
+        if let Some(bytes) = self.lock().get(path) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(bytes);
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        let bytes = decode()?;
+        if bytes.len() as u64 <= self.capacity {
+            self.lock().insert(path, bytes.clone(), self.capacity);
+        }
+        Ok(bytes)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let lru = self.lock();
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: lru.entries.len(),
+            bytes: lru.bytes,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Lru> {
+        self.lru.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Lru {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn get(&mut self, path: &str) -> Option<Vec<u8>> {
+        let now = self.tick();
+        let (bytes, last_used) = self.entries.get_mut(path)?;
+        let path = self.order.remove(last_used).unwrap_or_default();
+        *last_used = now;
+        self.order.insert(now, path);
+        Some(bytes.clone())
+    }
+
+    fn insert(&mut self, path: &str, bytes: Vec<u8>, capacity: u64) {
+        let now = self.tick();
+        self.bytes += bytes.len() as u64;
+        if let Some((old, last_used)) = self.entries.insert(path.to_owned(), (bytes, now)) {
+            self.bytes -= old.len() as u64;
+            self.order.remove(&last_used);
+        }
+        self.order.insert(now, path.to_owned());
+
+        while self.bytes > capacity {
+            let Some((_, evicted)) = self.order.pop_first() else {
+                break;
+            };
+            if let Some((bytes, _)) = self.entries.remove(&evicted) {
+                self.bytes -= bytes.len() as u64;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let cache = DecodeCache::new(10);
+        let get = |path: &str, len| cache.get_or_decode(path, || Ok(vec![0; len])).unwrap();
+        get("a", 4);
+        get("b", 4);
+        get("a", 4);
+        // Over capacity: "b" was used least recently.
+        get("c", 4);
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 3,
+                entries: 2,
+                bytes: 8
+            }
+        );
+        get("a", 4);
+        get("b", 4);
+        assert_eq!((cache.stats().hits, cache.stats().misses), (2, 4));
+
+        // Too large to cache at all.
+        get("big", 11);
+        get("big", 11);
+        let stats = cache.stats();
+        assert_eq!((stats.misses, stats.bytes), (6, 8));
+        assert!(cache
+            .get_or_decode("err", || Err(crate::MonolithicaError::Unsupported(
+                String::new()
+            )))
+            .is_err());
+    }
+}
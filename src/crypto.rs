@@ -0,0 +1,245 @@
+//! Per-asset AES-256-GCM encryption, built with the `encryption` feature.
+
+use crate::{Asset, MonolithicaError, Result};
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Nonce,
+};
+use std::fmt;
+
+/// Length of the nonce each encrypted asset is stored with, see
+/// [`Asset::nonce`].
+pub(crate) const NONCE_LEN: usize = 12;
+
+/// A 256-bit key to encrypt the assets of an archive with, see
+/// [`crate::ArchiveOptions::encryption_key`], and to read them back with, see
+/// [`crate::Archive::open_with_key`]. Its `Debug` output leaves the key out.
+#[derive(Clone)]
+pub struct EncryptionKey(Aes256Gcm);
+
+impl EncryptionKey {
+    pub fn new(key: [u8; 32]) -> Self {
+        Self(Aes256Gcm::new(&key.into()))
+    }
+
+    /// Encrypts `data`, the bytes of the asset at `path`, under a fresh random
+    /// nonce, returning the nonce and the encrypted bytes followed by their
+    /// 16-byte authentication tag. The tag also covers `path`, so the bytes
+    /// only decrypt as the asset at that path.
+    pub(crate) fn encrypt(&self, path: &str, data: &[u8]) -> Result<([u8; NONCE_LEN], Vec<u8>)> {
+        let nonce = Aes256Gcm::generate_nonce(OsRng);
+        let payload = Payload {
+            msg: data,
+            aad: path.as_bytes(),
+        };
+        let sealed = self.0.encrypt(&nonce, payload).map_err(|_| {
+            tracing::error!("failed to encrypt {} bytes", data.len());
+            MonolithicaError::InvalidInput(format!("can't encrypt {} bytes", data.len()))
+        })?;
+        Ok((nonce.into(), sealed))
+    }
+
+    /// Decrypts `sealed`, the bytes of `asset` at `path` as stored. Assets
+    /// without bytes, such as symlinks, have no nonce and are returned as is.
+    pub(crate) fn decrypt(&self, path: &str, asset: &Asset, sealed: Vec<u8>) -> Result<Vec<u8>> {
+        let nonce = match &asset.nonce {
+            Some(nonce) => nonce,
+            None if sealed.is_empty() => return Ok(sealed),
+            None => {
+                tracing::error!("encrypted asset without a nonce: {path}");
+                return Err(MonolithicaError::CorruptArchive(format!(
+                    "{path} is in an encrypted archive but has no nonce"
+                )));
+            }
+        };
+        let payload = Payload {
+            msg: sealed.as_slice(),
+            aad: path.as_bytes(),
+        };
+        self.0
+            .decrypt(Nonce::from_slice(nonce), payload)
+            .map_err(|_| {
+                tracing::error!("failed to decrypt {path}");
+                MonolithicaError::DecryptionFailed {
+                    path: path.to_owned(),
+                }
+            })
+    }
+}
+
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Archive, ArchiveOptions, AssetArchiver, Encoding, IndexFormat};
+
+    fn encrypted(key: [u8; 32]) -> (Vec<u8>, String) {
+        let options = ArchiveOptions {
+            encryption_key: Some(EncryptionKey::new(key)),
+            ..Default::default()
+        };
+        let entries = [
+            ("index.html".to_owned(), b"<html></html>".to_vec()),
+            ("js/app.js".to_owned(), b"console.log(1);".to_vec()),
+            ("copy.js".to_owned(), b"console.log(1);".to_vec()),
+            ("empty.txt".to_owned(), Vec::new()),
+        ];
+        AssetArchiver::build_in_memory(entries, &options).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let (blob, index) = encrypted([7; 32]);
+        assert!(!blob.windows(13).any(|w| w == b"<html></html>"));
+        let archive =
+            Archive::from_vec_with_key(blob.clone(), &index, EncryptionKey::new([7; 32])).unwrap();
+        assert_eq!(
+            archive.get("index.html").unwrap().unwrap(),
+            b"<html></html>"
+        );
+        assert_eq!(archive.get("copy.js").unwrap().unwrap(), b"console.log(1);");
+        assert_eq!(archive.get("empty.txt").unwrap().unwrap(), b"");
+        assert_eq!(
+            archive.read_range("js/app.js", 8, 3).unwrap().unwrap(),
+            b"log"
+        );
+        let asset = archive.locate_asset("js/app.js").unwrap();
+        assert_eq!((asset.len, asset.original_len), (15 + 16, 15));
+        assert_ne!(archive.locate_asset("copy.js").unwrap().nonce, asset.nonce);
+        assert_ne!(
+            archive.locate_asset("index.html").unwrap().nonce,
+            asset.nonce
+        );
+        assert!(archive.verify().unwrap().is_ok());
+        let err = archive.open_asset("index.html").err().unwrap();
+        assert!(matches!(err, MonolithicaError::Unsupported(_)), "{err}");
+
+        let err = Archive::from_vec(blob, &index).err().unwrap();
+        assert!(matches!(err, MonolithicaError::KeyRequired), "{err}");
+        let (plain, plain_index) = AssetArchiver::build_in_memory(
+            [("a.txt".to_owned(), b"a".to_vec())],
+            &ArchiveOptions::default(),
+        )
+        .unwrap();
+        let err = Archive::from_vec_with_key(plain, plain_index, EncryptionKey::new([7; 32]))
+            .err()
+            .unwrap();
+        assert!(matches!(err, MonolithicaError::InvalidInput(_)), "{err}");
+    }
+
+    #[test]
+    fn tamper_detection() {
+        let (blob, index) = encrypted([7; 32]);
+        let open = |blob, index: &str, key| {
+            Archive::from_vec_with_key(blob, index, EncryptionKey::new(key)).unwrap()
+        };
+        let failed = |archive: &Archive, path| {
+            let err = archive.get(path).unwrap_err();
+            assert!(
+                matches!(&err, MonolithicaError::DecryptionFailed { path: p } if p == path),
+                "{err}"
+            );
+        };
+        failed(&open(blob.clone(), &index, [8; 32]), "index.html");
+
+        let asset = open(blob.clone(), &index, [7; 32])
+            .locate_asset("index.html")
+            .unwrap()
+            .clone();
+        for at in [asset.offset, asset.offset + asset.len - 1] {
+            let mut tampered = blob.clone();
+            tampered[at as usize] ^= 1;
+            let archive = open(tampered, &index, [7; 32]);
+            failed(&archive, "index.html");
+            assert!(archive.get("js/app.js").is_ok());
+            assert!(!archive.verify().unwrap().is_ok());
+        }
+
+        let nonce: String = asset
+            .nonce
+            .unwrap()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        let flipped = format!(
+            "{}{}",
+            &nonce[..23],
+            if nonce.ends_with('0') { '1' } else { '0' }
+        );
+        let tampered_index = index.replace(&nonce, &flipped);
+        failed(&open(blob.clone(), &tampered_index, [7; 32]), "index.html");
+
+        // Swap the entries of two paths, bytes and nonces included.
+        let line = |path: &str| {
+            let start = index.find(&format!("{path}//")).unwrap();
+            let end = start + index[start..].find('\n').unwrap();
+            &index[start..end]
+        };
+        let (html, js) = (line("index.html"), line("js/app.js"));
+        let swapped = index
+            .replace(html, "\0")
+            .replace(js, &html.replacen("index.html", "js/app.js", 1))
+            .replace("\0", &js.replacen("js/app.js", "index.html", 1));
+        let archive = open(blob, &swapped, [7; 32]);
+        failed(&archive, "index.html");
+        failed(&archive, "js/app.js");
+        assert!(archive.get("copy.js").is_ok());
+    }
+
+    #[cfg(feature = "std-fs")]
+    #[test]
+    fn open_with_key() {
+        use crate::test_util::{fixture_dir, write_file};
+
+        let root = fixture_dir("open_with_key");
+        let src = root.join("src");
+        write_file(&src.join("index.html"), "<html></html>".repeat(20));
+        write_file(&src.join("js/app.js"), "console.log(1);");
+        let (blob, index) = (root.join("test.blob"), root.join("test.blob.idx"));
+        let options = ArchiveOptions {
+            encryption_key: Some(EncryptionKey::new([7; 32])),
+            #[cfg(feature = "gzip")]
+            compression: Some(Encoding::Gzip),
+            ..Default::default()
+        };
+        AssetArchiver::create_archive_with_options(&src, &blob, &index, true, &options).unwrap();
+
+        let archive = Archive::open_with_key(&blob, &index, EncryptionKey::new([7; 32])).unwrap();
+        let html = archive.get_string("index.html").unwrap().unwrap();
+        assert_eq!(html, "<html></html>".repeat(20));
+        assert_eq!(
+            archive.read_range("index.html", 6, 7).unwrap().unwrap(),
+            b"</html>"
+        );
+        let err = Archive::open(&blob, &index).err().unwrap();
+        assert!(matches!(err, MonolithicaError::KeyRequired), "{err}");
+    }
+
+    #[test]
+    fn no_variants() {
+        let options = ArchiveOptions {
+            encryption_key: Some(EncryptionKey::new([7; 32])),
+            precompressed: vec![Encoding::Gzip],
+            index_format: IndexFormat::Text,
+            ..Default::default()
+        };
+        let err = AssetArchiver::build_in_memory([], &options).unwrap_err();
+        assert!(matches!(err, MonolithicaError::Unsupported(_)), "{err}");
+        let options = ArchiveOptions {
+            precompressed: Vec::new(),
+            dedup: true,
+            ..options
+        };
+        let err = AssetArchiver::build_in_memory([], &options).unwrap_err();
+        assert!(matches!(err, MonolithicaError::Unsupported(_)), "{err}");
+        assert_eq!(
+            format!("{:?}", EncryptionKey::new([7; 32])),
+            "EncryptionKey(..)"
+        );
+    }
+}
@@ -0,0 +1,693 @@
+use crate::{
+    atomic::TempPath,
+    error::IoContext,
+    format::{self, Header, HEADER_LEN},
+    mime::guess_mime,
+    normalize_path,
+    packer::Packer,
+    Archive, ArchiveOptions, ArchiveSummary, Asset, AssetArchiver, ExistingPaths, IndexFormat,
+    MimeGuesser, MonolithicaError, Result,
+};
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Cursor, Read, Seek, SeekFrom, Write},
+    path::Path,
+    sync::Arc,
+};
+
+impl AssetArchiver {
+    /// Adds `bytes` as the asset at `rel_path` to an existing archive, writing
+    /// them at the end of the blob instead of rebuilding it. A text index gets
+    /// a new line appended, other formats are rewritten.
+    ///
+    /// Fails if the archive already has `rel_path`, unless `overwrite` is set.
+    /// The new entry then shadows the old one, whose bytes stay in the blob as
+    /// dead space until the archive is compacted. `rel_path` has to be in the
+    /// form assets are stored under, see [`crate::normalize_path`]. Sharded
+    /// archives and those created with
+    /// [`crate::ArchiveOptions::blob_compression`] can't be appended to.
+    pub fn append_asset(
+        blob_path: impl AsRef<Path>,
+        blob_index_path: impl AsRef<Path>,
+        rel_path: &str,
+        bytes: &[u8],
+        overwrite: bool,
+    ) -> Result<()> {
+        let (blob_path, blob_index_path) = (blob_path.as_ref(), blob_index_path.as_ref());
+        if rel_path.is_empty() || normalize_path(rel_path).as_deref() != Some(rel_path) {
+            tracing::error!("invalid asset path: {rel_path:?}");
+            return Err(MonolithicaError::InvalidPath(format!(
+                "invalid asset path: {rel_path:?}"
+            )));
+        }
+
+        let index = fs::read(blob_index_path).at(blob_index_path)?;
+        let (index_format, mut entries) = format::read_entries(&index)?;
+        if entries.iter().any(|(_, asset)| asset.shard != 0) {
+            return Err(MonolithicaError::Unsupported(
+                "can't append to a sharded archive".to_owned(),
+            ));
+        }
+        let existing = entries.iter().position(|(path, _)| path == rel_path);
+        if existing.is_some() && !overwrite {
+            tracing::error!("asset already exists: {rel_path}");
+            return Err(MonolithicaError::AssetExists(rel_path.to_owned()));
+        }
+
+        let mut blob = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(blob_path)
+            .at(blob_path)?;
+        let mut header = Header::read(&mut blob)?;
+        if header.compression.is_some() {
+            return Err(MonolithicaError::Unsupported(
+                "can't append to a compressed blob".to_owned(),
+            ));
+        }
+        let offset = blob.seek(SeekFrom::End(0))?;
+        blob.write_all(bytes)?;
+        header.data_len = offset + bytes.len() as u64 - HEADER_LEN;
+        blob.seek(SeekFrom::Start(0))?;
+        header.write(&mut blob)?;
+
+        let asset = Asset {
+            offset,
+            len: bytes.len() as u64,
+            mime: guess_mime(rel_path),
+            original_len: bytes.len() as u64,
+            crc32: Some(crc32fast::hash(bytes)),
+            ..Default::default()
+        };
+
+        if index_format == IndexFormat::Text {
+            let mut index_file = OpenOptions::new()
+                .append(true)
+                .open(blob_index_path)
+                .at(blob_index_path)?;
+            if !index.is_empty() && !index.ends_with(b"\n") {
+                index_file.write_all(b"\n")?;
+            }
+            return format::write_index_line(&mut index_file, rel_path, &asset, None);
+        }
+
+        match existing {
+            Some(i) => entries[i].1 = asset,
+            None => entries.push((rel_path.to_owned(), asset)),
+        }
+        replace_index(blob_index_path, &entries, index_format)
+    }
+}
+
+impl AssetArchiver {
+    /// Archives the files under `src_dir` into an existing archive, as
+    /// [`AssetArchiver::create_archive_with_options`] would, e.g. to add the
+    /// assets of a new locale with [`ArchiveOptions::add_prefix`]. They're
+    /// written from the current end of the blob on and their entries merged
+    /// into the index: appended to a text index, which other formats and
+    /// replaced entries have rewritten. `existing` decides about files at paths
+    /// the archive already has.
+    ///
+    /// If anything fails the blob is truncated back to its previous length,
+    /// leaving the archive as it was. Sharded archives and those created with
+    /// [`ArchiveOptions::blob_compression`] can't be appended to.
+    /// [`ArchiveOptions::dedup`] only finds duplicates among the new files.
+    pub fn append_dir(
+        blob_path: impl AsRef<Path>,
+        blob_index_path: impl AsRef<Path>,
+        src_dir: impl AsRef<Path>,
+        existing: ExistingPaths,
+        options: &ArchiveOptions,
+    ) -> Result<ArchiveSummary> {
+        let (blob_path, blob_index_path) = (blob_path.as_ref(), blob_index_path.as_ref());
+        let src_dir = src_dir.as_ref();
+        if options.shard_size.is_some() || options.blob_compression.is_some() {
+            return Err(MonolithicaError::Unsupported(
+                "can't append sharded or compressed blobs".to_owned(),
+            ));
+        }
+
+        let index = fs::read(blob_index_path).at(blob_index_path)?;
+        let (index_format, entries) = format::read_entries(&index)?;
+        if entries.iter().any(|(_, asset)| asset.shard != 0) {
+            return Err(MonolithicaError::Unsupported(
+                "can't append to a sharded archive".to_owned(),
+            ));
+        }
+
+        let mut blob = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(blob_path)
+            .at(blob_path)?;
+        let mut header = Header::read(&mut blob)?;
+        if header.compression.is_some() {
+            return Err(MonolithicaError::Unsupported(
+                "can't append to a compressed blob".to_owned(),
+            ));
+        }
+        // Whatever the header says, new assets go after the last byte there is.
+        let end = blob.seek(SeekFrom::End(0))?;
+        let data_len = header.data_len;
+        let appended = (|| {
+            let mut writer = BufWriter::new(&mut blob);
+            let mut packer = Packer::new(&mut writer, src_dir, options)?;
+            packer.offset = end;
+            packer.outputs = Self::outputs(blob_path, blob_index_path, options);
+            if existing != ExistingPaths::Replace {
+                packer.claimed = entries.iter().map(|(path, _)| path.clone()).collect();
+            }
+            packer.pack()?;
+            let (new_entries, mut summary) = packer.finish();
+            if let (ExistingPaths::Error, Some(path)) = (existing, summary.overridden.first()) {
+                tracing::error!("asset already exists: {path}");
+                return Err(MonolithicaError::AssetExists(path.clone()));
+            }
+            writer.flush()?;
+            drop(writer);
+            summary.blob_size = blob.stream_position()?;
+            header.data_len = summary.blob_size - HEADER_LEN;
+            blob.seek(SeekFrom::Start(0))?;
+            header.write(&mut blob)?;
+            blob.sync_all()?;
+            merge_entries(blob_index_path, &index, index_format, entries, new_entries)?;
+            Ok(summary)
+        })();
+        if appended.is_err() {
+            header.data_len = data_len;
+            let restored = (|| -> Result<()> {
+                blob.set_len(end)?;
+                blob.seek(SeekFrom::Start(0))?;
+                header.write(&mut blob)
+            })();
+            if let Err(e) = restored {
+                tracing::error!("failed to restore {blob_path:?}: {e}");
+            }
+        }
+        appended
+    }
+
+    /// Rewrites the archive with only the bytes its index refers to, dropping
+    /// what overwritten or removed assets left behind. The new blob and index
+    /// are written to temporary files first and then renamed over the old
+    /// ones, so a failure while copying leaves the archive as it was. Only the
+    /// two renames at the end can't happen as one. Sharded archives can't be
+    /// compacted.
+    pub fn compact_archive(
+        blob_path: impl AsRef<Path>,
+        blob_index_path: impl AsRef<Path>,
+    ) -> Result<ArchiveSummary> {
+        let (blob_path, blob_index_path) = (blob_path.as_ref(), blob_index_path.as_ref());
+        let (index_format, mut entries) =
+            format::read_entries(&fs::read(blob_index_path).at(blob_index_path)?)?;
+
+        let mut blob = File::open(blob_path).at(blob_path)?;
+        if Header::read(&mut blob)?.compression.is_some() {
+            return Err(MonolithicaError::Unsupported(
+                "can't compact a compressed blob".to_owned(),
+            ));
+        }
+        if entries.iter().any(|(_, asset)| asset.shard != 0) {
+            return Err(MonolithicaError::Unsupported(
+                "can't compact a sharded blob".to_owned(),
+            ));
+        }
+        let old_len = blob.metadata()?.len();
+
+        let blob_tmp = TempPath::new(blob_path);
+        let mut new_blob = File::create(blob_tmp.tmp()).at(blob_tmp.tmp())?;
+        new_blob.seek(SeekFrom::Start(HEADER_LEN))?;
+        // Regions shared by several entries stay shared.
+        let mut moved: HashMap<(u64, u64), u64> = HashMap::new();
+        let mut offset = HEADER_LEN;
+        let mut relocate = |old_offset: u64, len: u64| -> Result<u64> {
+            if let Some(&new_offset) = moved.get(&(old_offset, len)) {
+                return Ok(new_offset);
+            }
+            blob.seek(SeekFrom::Start(old_offset))?;
+            let copied = io::copy(&mut (&mut blob).take(len), &mut new_blob)?;
+            if copied != len {
+                return Err(MonolithicaError::BlobTooShort {
+                    offset: old_offset,
+                    len,
+                    available: copied,
+                });
+            }
+            let new_offset = offset;
+            moved.insert((old_offset, len), new_offset);
+            offset += len;
+            Ok(new_offset)
+        };
+        for (_, asset) in &mut entries {
+            asset.offset = relocate(asset.offset, asset.len)?;
+            for variant in &mut asset.variants {
+                variant.offset = relocate(variant.offset, variant.len)?;
+            }
+        }
+
+        new_blob.seek(SeekFrom::Start(0))?;
+        Header {
+            flags: 0,
+            compression: None,
+            data_len: offset - HEADER_LEN,
+        }
+        .write(&mut new_blob)?;
+        new_blob.sync_all()?;
+
+        let index_tmp = TempPath::new(blob_index_path);
+        let mut new_index = File::create(index_tmp.tmp()).at(index_tmp.tmp())?;
+        format::write_index(&mut new_index, &entries, index_format)?;
+        new_index.sync_all()?;
+        drop((new_blob, new_index));
+
+        blob_tmp.persist()?;
+        index_tmp.persist()?;
+
+        Ok(ArchiveSummary {
+            file_count: entries.len(),
+            total_bytes: entries.iter().map(|(_, asset)| asset.original_len).sum(),
+            blob_size: offset,
+            bytes_reclaimed: old_len - offset,
+            ..Default::default()
+        })
+    }
+}
+
+impl AssetArchiver {
+    /// Writes the assets of an existing archive to a new one created with
+    /// `options`, e.g. to compress a plain archive or switch its index format,
+    /// without the directory it was built from. Assets are read and decoded
+    /// one at a time as they're written.
+    ///
+    /// Assets keep their mime, unless [`ArchiveOptions::mime_by_glob`] or
+    /// [`ArchiveOptions::mime_by_extension`] say otherwise, but not their
+    /// modification time or mode. As with
+    /// [`AssetArchiver::create_archive_from_entries`], the options that drive
+    /// the walk of a directory don't apply. The destination may be the source
+    /// itself, with `overwrite_existing` set, since the new files are only
+    /// renamed into place once complete.
+    pub fn repack(
+        src_blob_path: impl AsRef<Path>,
+        src_blob_index_path: impl AsRef<Path>,
+        blob_path: impl AsRef<Path>,
+        blob_index_path: impl AsRef<Path>,
+        overwrite_existing: bool,
+        options: &ArchiveOptions,
+    ) -> Result<ArchiveSummary> {
+        let archive = Archive::open(src_blob_path, src_blob_index_path)?;
+        let mimes: Arc<HashMap<String, String>> = Arc::new(
+            archive
+                .iter()
+                .filter_map(|(path, asset)| Some((path.to_owned(), asset.mime.clone()?)))
+                .collect(),
+        );
+        let guesser = options.mime_guesser.clone();
+        let options = ArchiveOptions {
+            mime_guesser: Some(MimeGuesser::new(move |path| {
+                let recorded = path.to_str().and_then(|path| mimes.get(path));
+                recorded.cloned().or_else(|| guesser.as_ref()?.guess(path))
+            })),
+            ..options.clone()
+        };
+
+        let entries = archive.contents().map(|(path, bytes)| {
+            let reader: Box<dyn Read> = match bytes {
+                Ok(bytes) => Box::new(Cursor::new(bytes)),
+                Err(error) => Box::new(FailingReader(Some(error))),
+            };
+            (path.to_owned(), reader)
+        });
+        Self::create_archive_from_entries(
+            entries,
+            blob_path,
+            blob_index_path,
+            overwrite_existing,
+            &options,
+        )
+    }
+}
+
+/// Merges the `new_entries` of [`AssetArchiver::append_dir`] into the
+/// `entries` of the index at `blob_index_path`, holding `index`. A text index
+/// only has the lines of the added entries appended, unless some replace
+/// existing ones; others are rewritten.
+fn merge_entries(
+    blob_index_path: &Path,
+    index: &[u8],
+    index_format: IndexFormat,
+    mut entries: Vec<(String, Asset)>,
+    new_entries: Vec<(String, Asset)>,
+) -> Result<()> {
+    let positions: HashMap<String, usize> = entries
+        .iter()
+        .enumerate()
+        .map(|(i, (path, _))| (path.clone(), i))
+        .collect();
+    let mut replaced = false;
+    let mut added = Vec::new();
+    for (path, asset) in new_entries {
+        match positions.get(&path) {
+            Some(&i) => {
+                entries[i].1 = asset;
+                replaced = true;
+            }
+            None => added.push((path, asset)),
+        }
+    }
+
+    if index_format == IndexFormat::Text && !replaced {
+        let mut index_file = OpenOptions::new()
+            .append(true)
+            .open(blob_index_path)
+            .at(blob_index_path)?;
+        if !index.is_empty() && !index.ends_with(b"\n") {
+            index_file.write_all(b"\n")?;
+        }
+        format::write_index(&mut index_file, &added, index_format)?;
+        return Ok(index_file.sync_all()?);
+    }
+    entries.extend(added);
+    replace_index(blob_index_path, &entries, index_format)
+}
+
+/// Writes `entries` as the index at `blob_index_path` in `index_format`,
+/// through a temporary file renamed over the old index, so that readers never
+/// see a partly written one.
+fn replace_index(
+    blob_index_path: &Path,
+    entries: &[(String, Asset)],
+    index_format: IndexFormat,
+) -> Result<()> {
+    let blob_index_tmp = TempPath::new(blob_index_path);
+    let mut index_file = File::create(blob_index_tmp.tmp()).at(blob_index_tmp.tmp())?;
+    format::write_index(&mut index_file, entries, index_format)?;
+    index_file.sync_all()?;
+    drop(index_file);
+    blob_index_tmp.persist()
+}
+
+/// Fails the first read with the error reading an asset of the source
+/// archive, so [`AssetArchiver::repack`] stops before writing anything.
+struct FailingReader(Option<MonolithicaError>);
+
+impl Read for FailingReader {
+    fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+        match self.0.take() {
+            Some(MonolithicaError::Io(error)) => Err(error),
+            Some(error) => Err(io::Error::other(error)),
+            None => Ok(0),
+        }
+    }
+}
+
+impl AssetArchiver {
+    /// Deletes the entry for `rel_path`, and its variants, from the index,
+    /// returning whether there was one. The bytes stay in the blob until the
+    /// archive is compacted.
+    pub fn remove_asset(blob_index_path: impl AsRef<Path>, rel_path: &str) -> Result<bool> {
+        let blob_index_path = blob_index_path.as_ref();
+        let (index_format, mut entries) =
+            format::read_entries(&fs::read(blob_index_path).at(blob_index_path)?)?;
+        let Some(i) = entries.iter().position(|(path, _)| path == rel_path) else {
+            return Ok(false);
+        };
+        entries.remove(i);
+        replace_index(blob_index_path, &entries, index_format)?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_util::{build_fixture, fixture_dir, write_file},
+        Archive, ArchiveOptions,
+    };
+
+    #[test]
+    fn append_asset() {
+        let (blob, index) = build_fixture("edit_append_asset");
+        AssetArchiver::append_asset(&blob, &index, "css/site.css", b"body{}", false).unwrap();
+
+        let archive = Archive::open(&blob, &index).unwrap();
+        assert_eq!(archive.get("css/site.css").unwrap().unwrap(), b"body{}");
+        assert_eq!(
+            archive
+                .locate_asset("css/site.css")
+                .unwrap()
+                .mime
+                .as_deref(),
+            Some("text/css")
+        );
+        assert_eq!(
+            archive.get("js/app.js").unwrap().unwrap(),
+            b"console.log(1);"
+        );
+        assert!(archive.verify().is_ok());
+
+        let err = AssetArchiver::append_asset(&blob, &index, "index.html", b"new", false);
+        assert!(err.unwrap_err().to_string().contains("already exists"));
+        AssetArchiver::append_asset(&blob, &index, "index.html", b"<p>new</p>", true).unwrap();
+        let archive = Archive::open(&blob, &index).unwrap();
+        assert_eq!(archive.get("index.html").unwrap().unwrap(), b"<p>new</p>");
+        assert_eq!(archive.len(), 4);
+
+        for rel_path in ["../escape.txt", "/abs.txt", "a//b.txt", ""] {
+            let err = AssetArchiver::append_asset(&blob, &index, rel_path, b"x", false);
+            assert!(
+                matches!(err, Err(MonolithicaError::InvalidPath(_))),
+                "{rel_path}"
+            );
+        }
+        let mut sharded = fs::read_to_string(&index).unwrap();
+        sharded.push_str("other.txt//16//1//text/plain//shard=1\n");
+        fs::write(&index, sharded).unwrap();
+        let err = AssetArchiver::append_asset(&blob, &index, "new.txt", b"x", false);
+        assert!(matches!(err, Err(MonolithicaError::Unsupported(_))));
+    }
+
+    #[test]
+    fn append_dir() {
+        let (blob, index) = build_fixture("edit_append_dir");
+        let root = blob.parent().unwrap().to_owned();
+        write_file(&root.join("fr/messages.json"), "{}");
+        write_file(&root.join("fr/js/app.js"), "alert(1);");
+        let blob_len = fs::metadata(&blob).unwrap().len();
+        let options = ArchiveOptions {
+            add_prefix: Some("locales/fr".to_owned()),
+            ..Default::default()
+        };
+        let summary = AssetArchiver::append_dir(
+            &blob,
+            &index,
+            root.join("fr"),
+            ExistingPaths::Error,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(summary.file_count, 2);
+        assert_eq!(summary.blob_size, fs::metadata(&blob).unwrap().len());
+
+        let archive = Archive::open(&blob, &index).unwrap();
+        assert_eq!(archive.len(), 5);
+        let offsets = ["locales/fr/js/app.js", "locales/fr/messages.json"]
+            .map(|path| archive.locate_asset(path).unwrap().offset);
+        assert_eq!(offsets.iter().min(), Some(&blob_len));
+        assert_eq!(
+            archive.get("locales/fr/js/app.js").unwrap().unwrap(),
+            b"alert(1);"
+        );
+        assert_eq!(
+            archive.get("js/app.js").unwrap().unwrap(),
+            b"console.log(1);"
+        );
+        assert!(archive.verify().is_ok());
+
+        // A collision leaves the archive as it was.
+        write_file(&root.join("update/index.html"), "<p>new</p>");
+        write_file(&root.join("update/new.txt"), "new");
+        let (old_blob, old_index) = (fs::read(&blob).unwrap(), fs::read(&index).unwrap());
+        let append = |existing| {
+            AssetArchiver::append_dir(
+                &blob,
+                &index,
+                root.join("update"),
+                existing,
+                &Default::default(),
+            )
+        };
+        let err = append(ExistingPaths::Error).unwrap_err();
+        assert!(
+            matches!(&err, MonolithicaError::AssetExists(path) if path == "index.html"),
+            "{err}"
+        );
+        assert_eq!(fs::read(&blob).unwrap(), old_blob);
+        assert_eq!(fs::read(&index).unwrap(), old_index);
+
+        let summary = append(ExistingPaths::Keep).unwrap();
+        assert_eq!(
+            (summary.file_count, summary.overridden),
+            (1, vec!["index.html".to_owned()])
+        );
+        let archive = Archive::open(&blob, &index).unwrap();
+        assert_eq!(
+            archive.get("index.html").unwrap().unwrap(),
+            b"<html></html>"
+        );
+        assert_eq!(archive.get("new.txt").unwrap().unwrap(), b"new");
+
+        fs::remove_file(root.join("update/new.txt")).unwrap();
+        append(ExistingPaths::Replace).unwrap();
+        let archive = Archive::open(&blob, &index).unwrap();
+        assert_eq!(archive.len(), 6);
+        assert_eq!(archive.get("index.html").unwrap().unwrap(), b"<p>new</p>");
+        assert_eq!(archive.get("new.txt").unwrap().unwrap(), b"new");
+
+        let sharded = ArchiveOptions {
+            shard_size: Some(1 << 20),
+            ..Default::default()
+        };
+        let err = AssetArchiver::append_dir(&blob, &index, &root, ExistingPaths::Keep, &sharded);
+        assert!(matches!(err, Err(MonolithicaError::Unsupported(_))));
+    }
+
+    #[test]
+    fn append_to_binary_index() {
+        let root = fixture_dir("edit_append_to_binary_index");
+        write_file(&root.join("src/index.html"), "<html></html>");
+        let (blob, index) = (root.join("test.blob"), root.join("test.blob.idx"));
+        let options = ArchiveOptions {
+            index_format: IndexFormat::Binary,
+            ..Default::default()
+        };
+        AssetArchiver::create_archive_with_options(
+            root.join("src"),
+            &blob,
+            &index,
+            false,
+            &options,
+        )
+        .unwrap();
+
+        AssetArchiver::append_asset(&blob, &index, "index.html", b"<p></p>", true).unwrap();
+        AssetArchiver::append_asset(&blob, &index, "app.js", b"1;", false).unwrap();
+        assert!(fs::read(&index)
+            .unwrap()
+            .starts_with(format::BINARY_INDEX_MAGIC));
+        let archive = Archive::open(&blob, &index).unwrap();
+        assert_eq!(archive.len(), 2);
+        assert_eq!(archive.get("index.html").unwrap().unwrap(), b"<p></p>");
+        assert_eq!(archive.get("app.js").unwrap().unwrap(), b"1;");
+    }
+
+    #[test]
+    fn compact_archive() {
+        let (blob, index) = build_fixture("edit_compact_archive");
+        AssetArchiver::append_asset(&blob, &index, "index.html", b"<p></p>", true).unwrap();
+        AssetArchiver::append_asset(&blob, &index, "js/app.js", b"1;", true).unwrap();
+        let old_len = fs::metadata(&blob).unwrap().len();
+
+        let summary = AssetArchiver::compact_archive(&blob, &index).unwrap();
+        assert_eq!(summary.bytes_reclaimed, 13 + 15);
+        assert_eq!(summary.blob_size, old_len - 13 - 15);
+        assert_eq!(fs::metadata(&blob).unwrap().len(), summary.blob_size);
+        assert_eq!(summary.file_count, 3);
+
+        let archive = Archive::open(&blob, &index).unwrap();
+        assert!(archive.verify().is_ok());
+        assert_eq!(archive.get("index.html").unwrap().unwrap(), b"<p></p>");
+        assert_eq!(archive.get("js/app.js").unwrap().unwrap(), b"1;");
+        assert_eq!(
+            archive.get(".rustc_info.json").unwrap().unwrap(),
+            br#"{"rustc":"1.0"}"#
+        );
+        assert!(!TempPath::new(&blob).tmp().exists());
+
+        let summary = AssetArchiver::compact_archive(&blob, &index).unwrap();
+        assert_eq!(summary.bytes_reclaimed, 0);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn repack() {
+        let root = fixture_dir("edit_repack");
+        write_file(
+            &root.join("src/index.html"),
+            "<p>compressible</p>".repeat(50),
+        );
+        write_file(&root.join("src/app.tpl"), "{{ x }}");
+        let (blob, index) = (root.join("test.blob"), root.join("test.blob.idx"));
+        let options = ArchiveOptions {
+            mime_by_extension: HashMap::from([("tpl".to_owned(), "text/x-template".to_owned())]),
+            ..Default::default()
+        };
+        AssetArchiver::create_archive_with_options(
+            root.join("src"),
+            &blob,
+            &index,
+            false,
+            &options,
+        )
+        .unwrap();
+
+        let (new_blob, new_index) = (root.join("new.blob"), root.join("new.blob.idx"));
+        let options = ArchiveOptions {
+            compression: Some(crate::Encoding::Gzip),
+            index_format: IndexFormat::Binary,
+            ..Default::default()
+        };
+        let summary =
+            AssetArchiver::repack(&blob, &index, &new_blob, &new_index, false, &options).unwrap();
+        assert_eq!(summary.file_count, 2);
+        let (old, new) = (
+            Archive::open(&blob, &index).unwrap(),
+            Archive::open(&new_blob, &new_index).unwrap(),
+        );
+        assert_eq!(
+            new.contents()
+                .map(|(p, b)| (p, b.unwrap()))
+                .collect::<Vec<_>>(),
+            old.contents()
+                .map(|(p, b)| (p, b.unwrap()))
+                .collect::<Vec<_>>()
+        );
+        let asset = new.locate_asset("index.html").unwrap();
+        assert!(asset.is_compressed());
+        assert_eq!(asset.mime.as_deref(), Some("text/html"));
+        // The mime the source recorded is kept, though not guessable.
+        assert_eq!(
+            new.locate_asset("app.tpl").unwrap().mime.as_deref(),
+            Some("text/x-template")
+        );
+        assert!(fs::read(&new_index)
+            .unwrap()
+            .starts_with(format::BINARY_INDEX_MAGIC));
+
+        // An asset that can't be read fails the repack before anything is
+        // written.
+        fs::write(&index, "index.html//0//100000//-\n").unwrap();
+        let (bad_blob, bad_index) = (root.join("bad.blob"), root.join("bad.blob.idx"));
+        assert!(
+            AssetArchiver::repack(&blob, &index, &bad_blob, &bad_index, false, &options).is_err()
+        );
+        assert!(!bad_blob.exists() && !bad_index.exists());
+    }
+
+    #[test]
+    fn remove_asset() {
+        let (blob, index) = build_fixture("edit_remove_asset");
+        assert!(AssetArchiver::remove_asset(&index, "js/app.js").unwrap());
+        assert!(!AssetArchiver::remove_asset(&index, "js/app.js").unwrap());
+        assert!(!AssetArchiver::remove_asset(&index, "missing.js").unwrap());
+        assert!(!index.with_file_name("test.blob.idx.tmp").exists());
+
+        let archive = Archive::open(&blob, &index).unwrap();
+        assert!(archive.get("js/app.js").unwrap().is_none());
+        assert_eq!(archive.len(), 2);
+
+        let summary = AssetArchiver::compact_archive(&blob, &index).unwrap();
+        assert_eq!(summary.bytes_reclaimed, 15);
+    }
+}
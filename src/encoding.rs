@@ -0,0 +1,157 @@
+use crate::{MonolithicaError, Result};
+use std::io::Read;
+
+/// How an asset's bytes are encoded in the blob.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+#[non_exhaustive]
+pub enum Encoding {
+    Gzip,
+    Zstd,
+    #[cfg_attr(feature = "serde", serde(rename = "br"))]
+    Brotli,
+}
+
+impl Encoding {
+    /// The name recorded in the index, which is also the `Content-Encoding`
+    /// token for the encoding.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Zstd => "zstd",
+            Encoding::Brotli => "br",
+        }
+    }
+
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            Encoding::Gzip => 1,
+            Encoding::Zstd => 2,
+            Encoding::Brotli => 3,
+        }
+    }
+
+    pub(crate) fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(Encoding::Gzip),
+            2 => Some(Encoding::Zstd),
+            3 => Some(Encoding::Brotli),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "gzip" => Some(Encoding::Gzip),
+            "zstd" => Some(Encoding::Zstd),
+            "br" => Some(Encoding::Brotli),
+            _ => None,
+        }
+    }
+
+    #[cfg_attr(
+        not(any(feature = "gzip", feature = "zstd", feature = "brotli")),
+        allow(unused_variables)
+    )]
+    pub(crate) fn encode(self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            #[cfg(feature = "gzip")]
+            Encoding::Gzip => {
+                use std::io::Write;
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+            #[cfg(feature = "zstd")]
+            Encoding::Zstd => Ok(zstd::encode_all(data, 19)?),
+            #[cfg(feature = "brotli")]
+            Encoding::Brotli => {
+                use std::io::Write;
+                let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, 11, 22);
+                encoder.write_all(data)?;
+                Ok(encoder.into_inner())
+            }
+            #[allow(unreachable_patterns)]
+            _ => Err(MonolithicaError::Unsupported(format!(
+                "{} support is not enabled",
+                self.as_str()
+            ))),
+        }
+    }
+
+    pub(crate) fn decode(self, data: &[u8]) -> Result<Vec<u8>> {
+        self.decode_limited(data, u64::MAX)
+    }
+
+    /// Same as [`Encoding::decode`], stopping after `limit + 1` decoded bytes,
+    /// so that callers can tell data decoding to more than `limit` bytes apart
+    /// without decoding all of it.
+    pub(crate) fn decode_limited(self, data: &[u8], limit: u64) -> Result<Vec<u8>> {
+        let mut decoded = Vec::new();
+        self.decoder(data)?
+            .take(limit.saturating_add(1))
+            .read_to_end(&mut decoded)?;
+        Ok(decoded)
+    }
+
+    #[cfg_attr(
+        not(any(feature = "gzip", feature = "zstd", feature = "brotli")),
+        allow(unused_variables)
+    )]
+    fn decoder<'a>(self, data: &'a [u8]) -> Result<Box<dyn Read + 'a>> {
+        match self {
+            #[cfg(feature = "gzip")]
+            Encoding::Gzip => Ok(Box::new(flate2::read::GzDecoder::new(data))),
+            #[cfg(feature = "zstd")]
+            Encoding::Zstd => Ok(Box::new(zstd::stream::read::Decoder::with_buffer(data)?)),
+            #[cfg(feature = "brotli")]
+            Encoding::Brotli => Ok(Box::new(brotli::Decompressor::new(data, 4096))),
+            #[allow(unreachable_patterns)]
+            _ => Err(MonolithicaError::Unsupported(format!(
+                "{} support is not enabled",
+                self.as_str()
+            ))),
+        }
+    }
+}
+
+#[cfg(all(test, any(feature = "gzip", feature = "zstd", feature = "brotli")))]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn gzip_round_trip() {
+        let data = b"hello hello hello hello hello".repeat(10);
+        let encoded = Encoding::Gzip.encode(&data).unwrap();
+        assert!(encoded.len() < data.len());
+        assert_eq!(Encoding::Gzip.decode(&encoded).unwrap(), data);
+        assert!(Encoding::Gzip.decode(b"not gzip").is_err());
+        assert_eq!(
+            Encoding::Gzip.decode_limited(&encoded, 10).unwrap(),
+            &data[..11]
+        );
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd_round_trip() {
+        let data = b"hello hello hello hello hello".repeat(10);
+        let encoded = Encoding::Zstd.encode(&data).unwrap();
+        assert!(encoded.len() < data.len());
+        assert_eq!(Encoding::Zstd.decode(&encoded).unwrap(), data);
+        assert!(Encoding::Zstd.decode(b"not zstd").is_err());
+    }
+
+    #[cfg(feature = "brotli")]
+    #[test]
+    fn brotli_round_trip() {
+        let data = b"hello hello hello hello hello".repeat(10);
+        let encoded = Encoding::Brotli.encode(&data).unwrap();
+        assert!(encoded.len() < data.len());
+        assert_eq!(Encoding::Brotli.decode(&encoded).unwrap(), data);
+        assert!(Encoding::Brotli.decode(b"not brotli").is_err());
+    }
+}
@@ -0,0 +1,143 @@
+#[cfg(feature = "std-fs")]
+use std::path::Path;
+use std::{io, path::PathBuf};
+
+/// Everything that can go wrong creating, opening or reading an archive.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum MonolithicaError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// An I/O error on the file or directory at `path`.
+    #[error("{path:?}: {source}")]
+    PathIo {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    /// An output file exists and overwriting it wasn't asked for.
+    #[error("file already exists: {0:?}")]
+    FileExists(PathBuf),
+    /// An output path is taken by something other than a file.
+    #[error("path exists but not a file: {0:?}")]
+    NotAFile(PathBuf),
+    /// An asset is added under a path the archive already has.
+    #[error("asset already exists: {0}")]
+    AssetExists(String),
+    /// The blob or single-file archive doesn't start with the magic.
+    #[error("not a monolithica archive")]
+    NotAnArchive,
+    /// The header, footer or data of a blob or single-file archive is
+    /// malformed.
+    #[error("{0}")]
+    CorruptArchive(String),
+    /// The blob ends before a region the index points at does, `available`
+    /// bytes in.
+    #[error("blob too short, expected {len} bytes at offset {offset}, got {available}")]
+    BlobTooShort {
+        offset: u64,
+        len: u64,
+        available: u64,
+    },
+    /// The blob ends before the asset at `path` does, `available` of its
+    /// `len` bytes in.
+    #[error("asset {path} is truncated, expected {len} bytes at offset {offset}, the blob has {available}")]
+    AssetTruncated {
+        path: String,
+        offset: u64,
+        len: u64,
+        available: u64,
+    },
+    /// A malformed line of a text index, counting from 1.
+    #[error("index line {line}: {problem}")]
+    IndexLine {
+        line: usize,
+        problem: IndexLineProblem,
+    },
+    /// A malformed binary or JSON index, or entries that don't fit one.
+    #[error("{0}")]
+    InvalidIndex(String),
+    /// A glob or ignore pattern, or an ignore file, that doesn't parse.
+    #[error("invalid pattern {pattern:?}: {message}")]
+    InvalidPattern { pattern: String, message: String },
+    /// A path that can't be stored in or extracted from an archive.
+    #[error("{0}")]
+    InvalidPath(String),
+    /// A file was written to while it was being archived.
+    #[error("{path} changed while archiving, read {read} of {expected} bytes")]
+    FileChanged {
+        path: String,
+        read: u64,
+        expected: u64,
+    },
+    /// A file over [`crate::ArchiveOptions::max_file_size`], with
+    /// [`crate::OversizedFiles::Error`].
+    #[error("{path} is {len} bytes, more than the maximum of {max}")]
+    FileTooLarge { path: String, len: u64, max: u64 },
+    /// An asset read as text that isn't valid UTF-8.
+    #[error("asset {path} is not valid UTF-8: {source}")]
+    NotUtf8 {
+        path: String,
+        #[source]
+        source: std::string::FromUtf8Error,
+    },
+    /// The blob's assets are encrypted and no key was given to read them,
+    /// see `Archive::open_with_key` of the `encryption` feature.
+    #[error("archive is encrypted, a key is needed to read it")]
+    KeyRequired,
+    /// The asset at `path` doesn't decrypt: the key is wrong, or its bytes or
+    /// nonce were tampered with.
+    #[error("asset {path} failed to decrypt, wrong key or tampered data")]
+    DecryptionFailed { path: String },
+    /// Something this build or this kind of archive doesn't support, e.g. an
+    /// encoding whose feature is disabled.
+    #[error("{0}")]
+    Unsupported(String),
+    /// An argument that doesn't make sense, e.g. a builder without a source
+    /// directory.
+    #[error("{0}")]
+    InvalidInput(String),
+}
+
+/// What's wrong with a line of a text index, see
+/// [`MonolithicaError::IndexLine`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum IndexLineProblem {
+    /// Fewer than the path, offset, length and MIME type fields.
+    #[error("expected at least 4 fields, found {found}")]
+    FieldCount { found: usize },
+    /// A numeric field, named by `field`, that doesn't parse.
+    #[error("invalid {field}: {value:?}")]
+    InvalidNumber { field: &'static str, value: String },
+    /// A bad path escape, extra field or variant.
+    #[error("{0}")]
+    Invalid(String),
+}
+
+impl From<globset::Error> for MonolithicaError {
+    fn from(err: globset::Error) -> Self {
+        MonolithicaError::InvalidPattern {
+            pattern: err.glob().unwrap_or_default().to_owned(),
+            message: err.kind().to_string(),
+        }
+    }
+}
+
+/// Attaches the path an I/O operation was on to its error.
+#[cfg(feature = "std-fs")]
+pub(crate) trait IoContext<T> {
+    fn at(self, path: impl AsRef<Path>) -> Result<T>;
+}
+
+#[cfg(feature = "std-fs")]
+impl<T> IoContext<T> for io::Result<T> {
+    fn at(self, path: impl AsRef<Path>) -> Result<T> {
+        self.map_err(|source| MonolithicaError::PathIo {
+            path: path.as_ref().to_owned(),
+            source,
+        })
+    }
+}
+
+pub type Result<T, E = MonolithicaError> = std::result::Result<T, E>;
@@ -0,0 +1,323 @@
+use crate::{
+    error::IoContext, path::resolve_link, Archive, AssetArchiver, MonolithicaError, Result,
+};
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+impl Archive {
+    /// Writes every asset, decoded, to its path under `dest_dir`, creating
+    /// directories as needed. Fails on an existing file unless `overwrite` is
+    /// set, and on any index path that could escape `dest_dir`, before
+    /// writing anything.
+    ///
+    /// Archives only hold files, so directories that had none when the
+    /// archive was created, including those whose files were all left out,
+    /// aren't recreated. Files get the permission bits recorded in the index,
+    /// see [`crate::ArchiveOptions::mode`], on Unix, and default ones
+    /// otherwise.
+    ///
+    /// Symlink entries, see [`crate::ArchiveOptions::store_symlinks`], are
+    /// recreated as symlinks on Unix. Elsewhere they get a copy of the asset
+    /// they point to, failing if it's a directory. Links whose target leads
+    /// out of `dest_dir`, and entries under a link, are rejected up front as
+    /// unsafe paths.
+    pub fn extract_to(&self, dest_dir: impl AsRef<Path>, overwrite: bool) -> Result<()> {
+        let dest_dir = dest_dir.as_ref();
+        self.check_links()?;
+        let targets = self
+            .paths()
+            .map(|path| Ok((path, extract_path(dest_dir, path)?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        for (path, target) in targets {
+            if !overwrite && fs::symlink_metadata(&target).is_ok() {
+                tracing::error!("file already exists: {target:?}");
+                return Err(MonolithicaError::FileExists(target));
+            }
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent).at(parent)?;
+            }
+            if let Some(link) = self.locate_asset(path).and_then(|a| a.symlink.as_deref()) {
+                self.create_link(path, link, &target)?;
+                continue;
+            }
+            let data = self
+                .get(path)?
+                .ok_or_else(|| MonolithicaError::CorruptArchive("asset disappeared".to_owned()))?;
+            fs::write(&target, data).at(&target)?;
+            if let Some(mode) = self.locate_asset(path).and_then(|asset| asset.mode) {
+                set_mode(&target, mode)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Archive {
+    /// Fails unless every symlink entry points inside the archive, and no
+    /// entry lies under one, where writing it would follow the link.
+    fn check_links(&self) -> Result<()> {
+        let links: HashSet<&str> = self
+            .iter()
+            .filter(|(_, asset)| asset.symlink.is_some())
+            .map(|(path, _)| path)
+            .collect();
+        if links.is_empty() {
+            return Ok(());
+        }
+        for (path, asset) in self.iter() {
+            let escapes = asset
+                .symlink
+                .as_deref()
+                .is_some_and(|link| resolve_link(path, link).is_none());
+            let under_link = path
+                .match_indices('/')
+                .any(|(i, _)| links.contains(&path[..i]));
+            if escapes || under_link {
+                tracing::error!("unsafe symlink in archive: {path:?}");
+                return Err(MonolithicaError::InvalidPath(format!(
+                    "unsafe path in archive: {path:?} leads out through a symlink"
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates the symlink entry at `path` pointing at `link` as `target`,
+    /// replacing what's there.
+    #[cfg(unix)]
+    fn create_link(&self, _path: &str, link: &str, target: &Path) -> Result<()> {
+        if fs::symlink_metadata(target).is_ok() {
+            fs::remove_file(target).at(target)?;
+        }
+        std::os::unix::fs::symlink(link, target).at(target)
+    }
+
+    /// Writes a copy of the asset the symlink entry at `path` points to as
+    /// `target`, for lack of symlinks.
+    #[cfg(not(unix))]
+    fn create_link(&self, path: &str, link: &str, target: &Path) -> Result<()> {
+        let resolved = resolve_link(path, link).unwrap_or_default();
+        let bytes = match self.locate_asset(&resolved) {
+            Some(asset) if asset.symlink.is_none() => self.get(&resolved)?,
+            _ => None,
+        };
+        let Some(bytes) = bytes else {
+            tracing::error!("can't copy symlink target: {path}");
+            return Err(MonolithicaError::Unsupported(format!(
+                "can't extract symlink {path} to {link:?} without symlink support"
+            )));
+        };
+        fs::write(target, bytes).at(target)
+    }
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(mode)).at(path)
+}
+
+#[cfg(not(unix))]
+fn set_mode(_: &Path, _: u32) -> Result<()> {
+    Ok(())
+}
+
+impl AssetArchiver {
+    /// Opens the archive and runs [`Archive::extract_to`] on it, the inverse of
+    /// [`AssetArchiver::create_archive`].
+    pub fn extract_archive(
+        blob_path: impl AsRef<Path>,
+        blob_index_path: impl AsRef<Path>,
+        dest_dir: impl AsRef<Path>,
+        overwrite: bool,
+    ) -> Result<()> {
+        Archive::open(blob_path, blob_index_path)?.extract_to(dest_dir, overwrite)
+    }
+}
+
+/// Joins `path` to `dest_dir`, failing if it is empty, absolute or has a `..`
+/// component.
+fn extract_path(dest_dir: &Path, path: &str) -> Result<PathBuf> {
+    let rel_path = Path::new(path);
+    let safe = rel_path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    if path.is_empty() || !safe {
+        tracing::error!("unsafe path in archive: {path:?}");
+        return Err(MonolithicaError::InvalidPath(format!(
+            "unsafe path in archive: {path:?}"
+        )));
+    }
+    Ok(dest_dir.join(rel_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{build_fixture, fixture_dir, write_file};
+
+    #[test]
+    fn extract_round_trip() {
+        let (blob, index) = build_fixture("extract_round_trip");
+        let dest = blob.parent().unwrap().join("out");
+        AssetArchiver::extract_archive(&blob, &index, &dest, false).unwrap();
+        assert_eq!(
+            fs::read(dest.join("js/app.js")).unwrap(),
+            b"console.log(1);"
+        );
+        assert_eq!(fs::read(dest.join("index.html")).unwrap(), b"<html></html>");
+
+        assert!(AssetArchiver::extract_archive(&blob, &index, &dest, false).is_err());
+        AssetArchiver::extract_archive(&blob, &index, &dest, true).unwrap();
+    }
+
+    #[test]
+    fn empty_dirs_are_dropped() {
+        let root = fixture_dir("extract_empty_dirs_are_dropped");
+        let src = root.join("src");
+        write_file(&src.join("index.html"), "<html></html>");
+        write_file(&src.join("maps/app.js.map"), "{}");
+        fs::create_dir_all(src.join("empty/nested")).unwrap();
+        let (blob, index) = (root.join("test.blob"), root.join("test.blob.idx"));
+        let options = crate::ArchiveOptions {
+            exclude: vec!["**/*.map".to_owned()],
+            ..Default::default()
+        };
+        AssetArchiver::create_archive_with_options(&src, &blob, &index, false, &options).unwrap();
+
+        let archive = Archive::open(&blob, &index).unwrap();
+        assert_eq!(archive.paths().collect::<Vec<_>>(), ["index.html"]);
+        let dest = root.join("out");
+        archive.extract_to(&dest, false).unwrap();
+        assert!(dest.join("index.html").is_file());
+        assert!(!dest.join("empty").exists());
+        assert!(!dest.join("maps").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn restore_modes() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = fixture_dir("extract_restore_modes");
+        let src = root.join("src");
+        write_file(&src.join("run.sh"), "#!/bin/sh\n");
+        write_file(&src.join("README"), "read me");
+        fs::set_permissions(src.join("run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+        fs::set_permissions(src.join("README"), fs::Permissions::from_mode(0o600)).unwrap();
+        let (blob, index) = (root.join("test.blob"), root.join("test.blob.idx"));
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o7777;
+
+        let options = crate::ArchiveOptions {
+            mode: true,
+            ..Default::default()
+        };
+        AssetArchiver::create_archive_with_options(&src, &blob, &index, false, &options).unwrap();
+        assert!(fs::read_to_string(&index).unwrap().contains("//mode=755"));
+        let dest = root.join("out");
+        AssetArchiver::extract_archive(&blob, &index, &dest, false).unwrap();
+        assert_eq!(mode(&dest.join("run.sh")), 0o755);
+        assert_eq!(mode(&dest.join("README")), 0o600);
+
+        // Without the option, files get default permissions.
+        AssetArchiver::create_archive(&src, &blob, &index, true).unwrap();
+        assert!(!fs::read_to_string(&index).unwrap().contains("mode="));
+        let dest = root.join("out_default");
+        AssetArchiver::extract_archive(&blob, &index, &dest, false).unwrap();
+        assert_eq!(mode(&dest.join("run.sh")) & 0o111, 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn restore_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let root = fixture_dir("extract_restore_symlinks");
+        let src = root.join("src");
+        write_file(&src.join("js/app.js"), "console.log(1);");
+        write_file(&root.join("outside.txt"), "secret");
+        symlink("js/app.js", src.join("latest.js")).unwrap();
+        symlink("js", src.join("scripts")).unwrap();
+        symlink("../outside.txt", src.join("escape.txt")).unwrap();
+        symlink(root.join("outside.txt"), src.join("absolute.txt")).unwrap();
+        let (blob, index) = (root.join("test.blob"), root.join("test.blob.idx"));
+        let options = crate::ArchiveOptions {
+            store_symlinks: true,
+            ..Default::default()
+        };
+        let summary =
+            AssetArchiver::create_archive_with_options(&src, &blob, &index, false, &options)
+                .unwrap();
+        let mut left_out = summary.symlinks.clone();
+        left_out.sort();
+        assert_eq!(left_out, ["absolute.txt", "escape.txt"]);
+        assert!(fs::read_to_string(&index)
+            .unwrap()
+            .contains("//link=js/app.js"));
+
+        let archive = Archive::open(&blob, &index).unwrap();
+        assert_eq!(
+            archive.locate_asset("scripts").unwrap().symlink.as_deref(),
+            Some("js")
+        );
+        let dest = root.join("out");
+        archive.extract_to(&dest, false).unwrap();
+        assert_eq!(
+            fs::read_link(dest.join("latest.js")).unwrap(),
+            Path::new("js/app.js")
+        );
+        assert_eq!(
+            fs::read(dest.join("scripts/app.js")).unwrap(),
+            b"console.log(1);"
+        );
+        assert!(archive.extract_to(&dest, false).is_err());
+        archive.extract_to(&dest, true).unwrap();
+        assert_eq!(
+            fs::read(dest.join("latest.js")).unwrap(),
+            b"console.log(1);"
+        );
+
+        // Crafted indexes can't point out of the destination.
+        for lines in [
+            "up//16//0//-//link=../../outside.txt\n",
+            "back//16//0//-//link=scripts/../../outside.txt\nscripts//16//0//-//link=js\n",
+            "d/up//16//0//-//link=..\nd/up/evil.txt//16//5//-\n",
+        ] {
+            let evil = root.join("evil.idx");
+            fs::write(&evil, lines).unwrap();
+            let err =
+                AssetArchiver::extract_archive(&blob, &evil, root.join("evil"), false).unwrap_err();
+            assert!(err.to_string().contains("unsafe path"), "{lines}: {err}");
+        }
+        assert!(!root.join("evil").exists());
+    }
+
+    #[test]
+    fn reject_path_traversal() {
+        let (blob, _) = build_fixture("extract_reject_path_traversal");
+        let root = blob.parent().unwrap();
+        let dest = root.join("out");
+        for path in ["../evil.txt", "js/../../evil.txt", "/tmp/evil.txt", ""] {
+            let index = root.join("evil.idx");
+            fs::write(
+                &index,
+                format!("index.html//16//13//-\n{path}//16//13//-\n"),
+            )
+            .unwrap();
+            let err = AssetArchiver::extract_archive(&blob, &index, &dest, false).unwrap_err();
+            assert!(err.to_string().contains("unsafe path"), "{path}: {err}");
+        }
+        assert!(!root.join("evil.txt").exists());
+        assert!(!dest.exists());
+
+        assert_eq!(
+            extract_path(Path::new("out"), "a/./b").unwrap(),
+            Path::new("out/a/b")
+        );
+    }
+}
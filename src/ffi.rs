@@ -0,0 +1,207 @@
+//! A lookup for hosts embedding an index through a C ABI, built with the
+//! `ffi` feature: an indexer is opened with [`monolithica_indexer_open`],
+//! looked up in with [`monolithica_locate`] and freed with
+//! [`monolithica_indexer_free`].
+
+use crate::{Asset, AssetIndexer, OwnedAssetIndexer};
+use std::{ptr, slice};
+
+/// What [`monolithica_locate`] returns.
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LookupStatus {
+    /// The asset was found and `out` filled in.
+    Found = 0,
+    /// The index has no asset at the path. `out` is left untouched.
+    NotFound = 1,
+    /// A pointer was null, or the path not UTF-8. `out` is left untouched.
+    InvalidArgument = 2,
+}
+
+/// Where an asset's bytes are in the blob, as [`monolithica_locate`] reports
+/// it. Strings are given as a pointer and a length in bytes, not terminated
+/// by a NUL; a null pointer with a length of 0 when there's none.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AssetInfo {
+    /// See [`Asset::offset`].
+    pub offset: u64,
+    /// See [`Asset::len`].
+    pub len: u64,
+    /// See [`Asset::original_len`].
+    pub original_len: u64,
+    /// See [`Asset::shard`].
+    pub shard: u32,
+    /// The mime recorded in the index. Points into the indexer, so it stays
+    /// valid for as long as the indexer does and must not be written to or
+    /// freed.
+    pub mime: *const u8,
+    pub mime_len: usize,
+    /// The [`crate::Encoding::as_str`] name of the encoding the bytes are
+    /// stored with, such as `gzip`, if any. Points to static memory.
+    pub encoding: *const u8,
+    pub encoding_len: usize,
+}
+
+impl From<&Asset> for AssetInfo {
+    fn from(asset: &Asset) -> Self {
+        let (mime, mime_len) = str_parts(asset.mime.as_deref());
+        let (encoding, encoding_len) = str_parts(asset.encoding.map(|e| e.as_str()));
+        Self {
+            offset: asset.offset,
+            len: asset.len,
+            original_len: asset.original_len,
+            shard: asset.shard,
+            mime,
+            mime_len,
+            encoding,
+            encoding_len,
+        }
+    }
+}
+
+fn str_parts(s: Option<&str>) -> (*const u8, usize) {
+    s.map_or((ptr::null(), 0), |s| (s.as_ptr(), s.len()))
+}
+
+/// Parses the `index_len` bytes of an index at `index`, in any format, see
+/// [`AssetIndexer::from_bytes`]. Returns an indexer holding a copy of what it
+/// needs, to be freed with [`monolithica_indexer_free`], or null if `index`
+/// is null or the index malformed.
+///
+/// # Safety
+///
+/// `index` must point to `index_len` readable bytes, which are only read
+/// during the call.
+#[no_mangle]
+pub unsafe extern "C" fn monolithica_indexer_open(
+    index: *const u8,
+    index_len: usize,
+) -> *mut OwnedAssetIndexer {
+    if index.is_null() {
+        return ptr::null_mut();
+    }
+    // SAFETY: the caller guarantees `index` points to `index_len` bytes.
+    let index = unsafe { slice::from_raw_parts(index, index_len) };
+    match AssetIndexer::from_bytes(index) {
+        Ok(indexer) => Box::into_raw(Box::new(indexer.into_owned())),
+        Err(e) => {
+            tracing::error!("failed to parse the index: {e}");
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Frees an indexer opened with [`monolithica_indexer_open`]. Does nothing if
+/// `indexer` is null.
+///
+/// # Safety
+///
+/// `indexer` must be null or returned by [`monolithica_indexer_open`] and not
+/// freed yet. The mimes [`monolithica_locate`] wrote from it are no longer
+/// valid afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn monolithica_indexer_free(indexer: *mut OwnedAssetIndexer) {
+    if !indexer.is_null() {
+        // SAFETY: the caller guarantees `indexer` came from `Box::into_raw` in
+        // `monolithica_indexer_open` and is freed only once.
+        drop(unsafe { Box::from_raw(indexer) });
+    }
+}
+
+/// Looks up the asset at the `path_len` bytes of UTF-8 at `path` in
+/// `indexer`, as [`AssetIndexer::locate_asset`] does, without allocating, and
+/// writes where it is to `out`. The path has to be in the form assets are
+/// stored under, see [`crate::normalize_path`].
+///
+/// # Safety
+///
+/// `indexer` must point to a live [`AssetIndexer`], `path` to `path_len`
+/// readable bytes and `out` to an [`AssetInfo`] that can be written. The
+/// mime written to `out` is only valid until the indexer is dropped or freed.
+#[no_mangle]
+pub unsafe extern "C" fn monolithica_locate(
+    indexer: *const AssetIndexer<'_>,
+    path: *const u8,
+    path_len: usize,
+    out: *mut AssetInfo,
+) -> LookupStatus {
+    if indexer.is_null() || path.is_null() || out.is_null() {
+        return LookupStatus::InvalidArgument;
+    }
+    // SAFETY: the caller guarantees `path` points to `path_len` bytes.
+    let path = unsafe { slice::from_raw_parts(path, path_len) };
+    let Ok(path) = std::str::from_utf8(path) else {
+        return LookupStatus::InvalidArgument;
+    };
+    // SAFETY: the caller guarantees `indexer` points to a live indexer.
+    let Some(asset) = (unsafe { &*indexer }).locate_asset(path) else {
+        return LookupStatus::NotFound;
+    };
+    // SAFETY: the caller guarantees `out` can be written.
+    unsafe { out.write(AssetInfo::from(asset)) };
+    LookupStatus::Found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem::MaybeUninit;
+
+    fn locate(indexer: &AssetIndexer, path: &[u8]) -> (LookupStatus, Option<AssetInfo>) {
+        let mut out = MaybeUninit::uninit();
+        let status =
+            unsafe { monolithica_locate(indexer, path.as_ptr(), path.len(), out.as_mut_ptr()) };
+        let info = (status == LookupStatus::Found).then(|| unsafe { out.assume_init() });
+        (status, info)
+    }
+
+    #[test]
+    fn locate_through_ffi() {
+        let indexer = AssetIndexer::parse(
+            "index.html//16//13//text/html\n\
+             js/app.js//29//10//application/javascript//enc=gzip//size=15\n\
+             data.bin//39//4//-\n",
+        )
+        .unwrap();
+
+        let (status, info) = locate(&indexer, b"js/app.js");
+        assert_eq!(status, LookupStatus::Found);
+        let info = info.unwrap();
+        assert_eq!((info.offset, info.len, info.shard), (29, 10, 0));
+        let str_of = |ptr, len| unsafe { std::str::from_utf8(slice::from_raw_parts(ptr, len)) };
+        assert_eq!(
+            str_of(info.mime, info.mime_len),
+            Ok("application/javascript")
+        );
+        assert_eq!(str_of(info.encoding, info.encoding_len), Ok("gzip"));
+        assert_eq!(info.original_len, 15);
+
+        let info = locate(&indexer, b"data.bin").1.unwrap();
+        assert!(info.mime.is_null() && info.encoding.is_null());
+        assert_eq!(info.mime_len, 0);
+
+        assert_eq!(locate(&indexer, b"/index.html").0, LookupStatus::NotFound);
+        assert_eq!(locate(&indexer, b"\xff").0, LookupStatus::InvalidArgument);
+        let status = unsafe { monolithica_locate(&indexer, ptr::null(), 0, ptr::null_mut()) };
+        assert_eq!(status, LookupStatus::InvalidArgument);
+    }
+
+    #[test]
+    fn indexer_lifecycle() {
+        let index = b"index.html//16//13//text/html\n".to_vec();
+        let indexer = unsafe { monolithica_indexer_open(index.as_ptr(), index.len()) };
+        assert!(!indexer.is_null());
+        drop(index);
+
+        let info = locate(unsafe { &*indexer }, b"index.html").1.unwrap();
+        assert_eq!((info.offset, info.len), (16, 13));
+        unsafe { monolithica_indexer_free(indexer) };
+
+        let malformed = b"index.html//x//13//-\n";
+        let indexer = unsafe { monolithica_indexer_open(malformed.as_ptr(), malformed.len()) };
+        assert!(indexer.is_null());
+        assert!(unsafe { monolithica_indexer_open(ptr::null(), 0) }.is_null());
+        unsafe { monolithica_indexer_free(ptr::null_mut()) };
+    }
+}
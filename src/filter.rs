@@ -0,0 +1,232 @@
+#[cfg(feature = "std-fs")]
+use crate::error::IoContext;
+#[cfg(feature = "std-fs")]
+use crate::MonolithicaError;
+use crate::Result;
+use globset::{Glob, GlobBuilder};
+#[cfg(feature = "std-fs")]
+use globset::{GlobSet, GlobSetBuilder};
+#[cfg(feature = "std-fs")]
+use ignore::{
+    gitignore::{Gitignore, GitignoreBuilder},
+    Match,
+};
+#[cfg(feature = "std-fs")]
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Ignore files read in each directory, later ones taking precedence.
+#[cfg(feature = "std-fs")]
+pub(crate) const IGNORE_FILES: [&str; 2] = [".gitignore", ".ignore"];
+
+/// Reads the ignore files of `dir` that exist.
+#[cfg(feature = "std-fs")]
+fn read_ignore_files(dir: &Path) -> Result<Vec<(PathBuf, String)>> {
+    let mut files = Vec::new();
+    for name in IGNORE_FILES {
+        let path = dir.join(name);
+        if path.is_file() {
+            let content = fs::read_to_string(&path).at(&path)?;
+            files.push((path, content));
+        }
+    }
+    Ok(files)
+}
+
+/// Decides which files go into an archive by their archive-relative path, see
+/// [`crate::ArchiveOptions::include`].
+#[cfg(feature = "std-fs")]
+pub(crate) struct PathFilter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+#[cfg(feature = "std-fs")]
+impl PathFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self> {
+        Ok(Self {
+            include: match include {
+                [] => None,
+                patterns => Some(build_set(patterns)?),
+            },
+            exclude: build_set(exclude)?,
+        })
+    }
+
+    pub fn matches(&self, rel_path: &str) -> bool {
+        self.include
+            .as_ref()
+            .is_none_or(|set| set.is_match(rel_path))
+            && !self.exclude.is_match(rel_path)
+    }
+}
+
+#[cfg(feature = "std-fs")]
+fn build_set(patterns: &[String]) -> Result<GlobSet> {
+    let mut set = GlobSetBuilder::new();
+    for pattern in patterns {
+        set.add(glob(pattern)?);
+    }
+    Ok(set.build()?)
+}
+
+#[cfg(feature = "std-fs")]
+fn invalid_pattern(pattern: &str, err: impl std::fmt::Display) -> MonolithicaError {
+    MonolithicaError::InvalidPattern {
+        pattern: pattern.to_owned(),
+        message: err.to_string(),
+    }
+}
+
+/// Compiles `pattern` so `*` stays within one path segment, like in
+/// `.gitignore`, and `**` crosses segments.
+pub(crate) fn glob(pattern: &str) -> Result<Glob> {
+    Ok(GlobBuilder::new(pattern).literal_separator(true).build()?)
+}
+
+/// The ignore rules in effect while walking a directory tree, see
+/// [`crate::ArchiveOptions::ignore_files`].
+#[cfg(feature = "std-fs")]
+pub(crate) struct IgnoreStack {
+    /// The user's patterns, which take precedence over any ignore file.
+    patterns: Gitignore,
+    read_files: bool,
+    /// Rules from the ignore files of the directories being walked, innermost
+    /// last.
+    levels: Vec<Gitignore>,
+}
+
+#[cfg(feature = "std-fs")]
+impl IgnoreStack {
+    pub fn new(root: &Path, patterns: &[String], read_files: bool) -> Result<Self> {
+        let mut builder = GitignoreBuilder::new(root);
+        for pattern in patterns {
+            builder
+                .add_line(None, pattern)
+                .map_err(|err| invalid_pattern(pattern, err))?;
+        }
+        Ok(Self {
+            patterns: builder
+                .build()
+                .map_err(|err| invalid_pattern(&patterns.join("\n"), err))?,
+            read_files,
+            levels: Vec::new(),
+        })
+    }
+
+    /// Loads the ignore files of `dir` before walking it, `.ignore` rules
+    /// overriding `.gitignore` ones. Must be paired with [`IgnoreStack::leave`].
+    pub fn enter(&mut self, dir: &Path) -> Result<()> {
+        if !self.read_files {
+            return Ok(());
+        }
+        self.enter_with(dir, &read_ignore_files(dir)?)
+    }
+
+    /// Like [`IgnoreStack::enter`], given the paths and contents of the
+    /// ignore files of `dir` in [`IGNORE_FILES`] order.
+    pub fn enter_with(&mut self, dir: &Path, files: &[(PathBuf, String)]) -> Result<()> {
+        if !self.read_files {
+            return Ok(());
+        }
+        let mut builder = GitignoreBuilder::new(dir);
+        for (path, content) in files {
+            for line in content.lines() {
+                builder
+                    .add_line(Some(path.clone()), line)
+                    .map_err(|err| invalid_pattern(&path.display().to_string(), err))?;
+            }
+        }
+        let level = builder
+            .build()
+            .map_err(|err| invalid_pattern(&dir.display().to_string(), err))?;
+        self.levels.push(level);
+        Ok(())
+    }
+
+    pub fn leave(&mut self) {
+        self.levels.pop();
+    }
+
+    /// Whether `path`, inside the directory entered last, is ignored. The
+    /// `.git` directory is always ignored when ignore files are honored.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        if self.read_files && is_dir && path.file_name().is_some_and(|name| name == ".git") {
+            return true;
+        }
+        std::iter::once(&self.patterns)
+            .chain(self.levels.iter().rev())
+            .map(|rules| rules.matched(path, is_dir))
+            .find(|m| !m.is_none())
+            .is_some_and(|m| matches!(m, Match::Ignore(_)))
+    }
+}
+
+#[cfg(all(test, feature = "std-fs"))]
+mod tests {
+    use super::*;
+    use crate::test_util::{fixture_dir, write_file};
+
+    fn filter(include: &[&str], exclude: &[&str]) -> PathFilter {
+        let strings =
+            |patterns: &[&str]| patterns.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        PathFilter::new(&strings(include), &strings(exclude)).unwrap()
+    }
+
+    #[test]
+    fn include_and_exclude() {
+        let all = filter(&[], &[]);
+        assert!(all.matches("index.html"));
+
+        let js = filter(&["**/*.js"], &[]);
+        assert!(js.matches("app.js"));
+        assert!(js.matches("js/vendor/lib.js"));
+        assert!(!js.matches("index.html"));
+        assert!(!js.matches("js/app.js.map"));
+
+        let top_level = filter(&["*.js"], &[]);
+        assert!(top_level.matches("app.js"));
+        assert!(!top_level.matches("js/app.js"));
+
+        let no_modules = filter(&[], &["node_modules/**", "**/*.map"]);
+        assert!(no_modules.matches("index.html"));
+        assert!(!no_modules.matches("node_modules/lib/index.js"));
+        assert!(!no_modules.matches("js/app.js.map"));
+        assert!(no_modules.matches("js/node_modules.js"));
+
+        let both = filter(&["**/*.js"], &["vendor/**"]);
+        assert!(both.matches("js/app.js"));
+        assert!(!both.matches("vendor/lib.js"));
+
+        assert!(PathFilter::new(&["[".to_owned()], &[]).is_err());
+    }
+
+    #[test]
+    fn ignore_files() {
+        let root = fixture_dir("filter_ignore_files");
+        write_file(&root.join(".gitignore"), "*.log\n/build\n");
+        write_file(&root.join("js/.ignore"), "!keep.log\n");
+
+        let mut stack = IgnoreStack::new(&root, &["*.tmp".to_owned()], true).unwrap();
+        stack.enter(&root).unwrap();
+        assert!(stack.is_ignored(&root.join("debug.log"), false));
+        assert!(stack.is_ignored(&root.join("build"), true));
+        assert!(stack.is_ignored(&root.join(".git"), true));
+        assert!(stack.is_ignored(&root.join("a.tmp"), false));
+        assert!(!stack.is_ignored(&root.join("index.html"), false));
+
+        stack.enter(&root.join("js")).unwrap();
+        assert!(stack.is_ignored(&root.join("js/debug.log"), false));
+        assert!(!stack.is_ignored(&root.join("js/keep.log"), false));
+        assert!(!stack.is_ignored(&root.join("js/build"), true));
+        stack.leave();
+        stack.leave();
+
+        let mut patterns_only = IgnoreStack::new(&root, &["*.tmp".to_owned()], false).unwrap();
+        patterns_only.enter(&root).unwrap();
+        assert!(!patterns_only.is_ignored(&root.join("debug.log"), false));
+        assert!(patterns_only.is_ignored(&root.join("js/a.tmp"), false));
+    }
+}
@@ -0,0 +1,758 @@
+//! Each line of the text index is `path//offset//len//mime`, with `-` (or an
+//! empty field, in older indexes) for an unknown mime. In the path, `%`,
+//! line breaks, and any `/` that follows another `/` or ends the path are
+//! percent-escaped, so the path never contains `//` and splitting a line on
+//! `//` is unambiguous.
+//!
+//! Optional metadata follows as extra `key=value` fields, readers skip keys
+//! they don't know:
+//!
+//! - `enc`: the [`Encoding`] of the stored bytes
+//! - `size`: the decoded length of an encoded or encrypted asset
+//! - `crc32`: the hex CRC32 of the decoded bytes
+//! - `mtime`: the modification time of the file, in seconds since the Unix
+//!   epoch
+//! - `mode`: the Unix permission bits of the file, in octal
+//! - `link`: the target of a symlink entry, escaped like paths
+//! - `hash`: the first 8 bytes of the SHA-256 of the decoded bytes, in hex
+//! - `shard`: the shard of the blob holding the asset, if not the first, see
+//!   [`crate::ArchiveOptions::shard_size`]
+//! - `variant`: `1` if the line is a precompressed copy of the asset on the
+//!   line with the same path before it, rather than an asset of its own
+//! - `nonce`: the hex AES-GCM nonce the stored bytes are encrypted with, see
+//!   [`crate::Asset::nonce`]
+//!
+//! The binary index ([`IndexFormat::Binary`]) is the magic `MONI` and a
+//! little-endian `u16` version, followed by a mime table (`u32` count, then a
+//! `u16` length and the bytes of each mime) and the entries (`u32` count, then
+//! for each a `u32` path length, the path bytes, the `u64` offset and length,
+//! the `u32` position of its mime in the table, [`NO_MIME`] if unknown, and
+//! since version 2 a `u16` length and the extra fields joined by `//`).
+//!
+//! The JSON index ([`IndexFormat::Json`]) is an array of objects holding the
+//! `path` of each asset next to the fields of its [`Asset`].
+//!
+//! Layout of the single-file archive:
+//!
+//! ```text
+//! +--------+-----------+------------+----------+--------+
+//! | header | blob data | index text | checksum | footer |
+//! +--------+-----------+------------+----------+--------+
+//! ```
+//!
+//! The header is the magic, a little-endian `u16` version, a flags byte (see
+//! [`FLAG_ENCRYPTED`]), the id of the [`Encoding`] everything after the header
+//! is compressed with (`0` if it's stored raw) and the little-endian `u64`
+//! length of the data after the header once decoded. The blob of a two-file
+//! archive starts with the same header. The footer is the little-endian `u64`
+//! offset and length of the index followed by the magic again, so a reader can
+//! find the index by reading the last [`FOOTER_LEN`] bytes. The checksum,
+//! absent from archives written before it was added, is the id of the hash
+//! algorithm ([`CHECKSUM_SHA256`]), a byte holding the digest length and the
+//! digest of everything before it, header included. Readers that don't know the
+//! algorithm skip the checksum, which older readers never look at. Asset
+//! offsets in the index are absolute offsets into the file, or into the header
+//! followed by the decoded data for a compressed blob.
+
+use crate::{
+    Asset, AssetMap, AssetVariant, Encoding, IndexFormat, IndexLineProblem, MonolithicaError,
+    Result,
+};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    io::{Read, Write},
+};
+
+pub(crate) const MAGIC: &[u8; 4] = b"MONO";
+pub(crate) const VERSION: u16 = 1;
+pub(crate) const HEADER_LEN: u64 = 16;
+pub(crate) const FOOTER_LEN: u64 = 20;
+/// Header flag of a blob whose assets are encrypted, see
+/// [`crate::ArchiveOptions::encryption_key`]. Readers refuse such blobs unless
+/// given a key, and any flag they don't know.
+pub(crate) const FLAG_ENCRYPTED: u8 = 0x01;
+/// Algorithm id of a [`BlobChecksum`] holding a SHA-256 digest.
+pub(crate) const CHECKSUM_SHA256: u8 = 1;
+pub(crate) const BINARY_INDEX_MAGIC: &[u8; 4] = b"MONI";
+const BINARY_INDEX_VERSION: u16 = 2;
+const NO_MIME: u32 = u32::MAX;
+
+pub(crate) struct Header {
+    pub flags: u8,
+    pub compression: Option<Encoding>,
+    pub data_len: u64,
+}
+
+impl Header {
+    pub fn write<W: Write>(&self, out: &mut W) -> Result<()> {
+        out.write_all(MAGIC)?;
+        out.write_all(&VERSION.to_le_bytes())?;
+        out.write_all(&[self.flags, self.compression.map_or(0, Encoding::id)])?;
+        out.write_all(&self.data_len.to_le_bytes())?;
+        Ok(())
+    }
+
+    /// Reads the header of a blob that isn't encrypted.
+    pub fn read<R: Read>(input: &mut R) -> Result<Self> {
+        Self::read_for(input, false)
+    }
+
+    /// Reads the header of a blob to be opened with a key if `keyed`, failing
+    /// if the blob is encrypted but there's no key, or the other way around.
+    pub fn read_for<R: Read>(input: &mut R, keyed: bool) -> Result<Self> {
+        let mut buf = [0u8; HEADER_LEN as usize];
+        if let Err(e) = input.read_exact(&mut buf) {
+            if e.kind() != std::io::ErrorKind::UnexpectedEof {
+                return Err(e.into());
+            }
+            buf[..4].fill(0);
+        }
+        if &buf[..4] != MAGIC {
+            tracing::error!("not a monolithica archive");
+            return Err(MonolithicaError::NotAnArchive);
+        }
+
+        let version = u16::from_le_bytes([buf[4], buf[5]]);
+        if version != VERSION {
+            tracing::error!("unsupported archive version: {version}");
+            return Err(MonolithicaError::CorruptArchive(format!(
+                "unsupported archive version: {version}, expected {VERSION}"
+            )));
+        }
+
+        let flags = buf[6];
+        match (flags & FLAG_ENCRYPTED != 0, keyed) {
+            (true, false) => {
+                tracing::error!("archive is encrypted");
+                return Err(MonolithicaError::KeyRequired);
+            }
+            (false, true) => {
+                tracing::error!("archive is not encrypted");
+                return Err(MonolithicaError::InvalidInput(
+                    "archive is not encrypted, open it without a key".to_owned(),
+                ));
+            }
+            _ => {}
+        }
+        if flags & !FLAG_ENCRYPTED != 0 {
+            tracing::error!("unknown archive flags: {flags:#04x}");
+            return Err(MonolithicaError::Unsupported(format!(
+                "unknown archive flags: {flags:#04x}"
+            )));
+        }
+
+        let compression = match buf[7] {
+            0 => None,
+            id => Some(Encoding::from_id(id).ok_or_else(|| {
+                MonolithicaError::CorruptArchive(format!("unknown blob compression: {id}"))
+            })?),
+        };
+
+        Ok(Self {
+            flags,
+            compression,
+            data_len: u64::from_le_bytes(buf[8..16].try_into().unwrap()),
+        })
+    }
+}
+
+pub(crate) struct Footer {
+    pub index_offset: u64,
+    pub index_len: u64,
+}
+
+impl Footer {
+    #[cfg(feature = "std-fs")]
+    pub fn write<W: Write>(&self, out: &mut W) -> Result<()> {
+        out.write_all(&self.index_offset.to_le_bytes())?;
+        out.write_all(&self.index_len.to_le_bytes())?;
+        out.write_all(MAGIC)?;
+        Ok(())
+    }
+
+    pub fn read<R: Read>(input: &mut R) -> Result<Self> {
+        let mut buf = [0u8; FOOTER_LEN as usize];
+        input.read_exact(&mut buf)?;
+        if &buf[16..] != MAGIC {
+            tracing::error!("archive footer is corrupt");
+            return Err(MonolithicaError::CorruptArchive(
+                "archive footer is corrupt".to_owned(),
+            ));
+        }
+
+        Ok(Self {
+            index_offset: u64::from_le_bytes(buf[..8].try_into().unwrap()),
+            index_len: u64::from_le_bytes(buf[8..16].try_into().unwrap()),
+        })
+    }
+}
+
+/// The hash of a whole single-file archive, stored between the index and the
+/// footer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct BlobChecksum {
+    pub algorithm: u8,
+    pub digest: Vec<u8>,
+}
+
+impl BlobChecksum {
+    #[cfg(feature = "std-fs")]
+    pub fn sha256(digest: &[u8]) -> Self {
+        Self {
+            algorithm: CHECKSUM_SHA256,
+            digest: digest.to_vec(),
+        }
+    }
+
+    #[cfg(feature = "std-fs")]
+    pub fn write<W: Write>(&self, out: &mut W) -> Result<()> {
+        out.write_all(&[self.algorithm, self.digest.len() as u8])?;
+        out.write_all(&self.digest)?;
+        Ok(())
+    }
+
+    /// Parses the bytes between the index and the footer, `None` if there are
+    /// none.
+    pub fn parse(bytes: &[u8]) -> Result<Option<Self>> {
+        let [algorithm, len, digest @ ..] = bytes else {
+            return match bytes.is_empty() {
+                true => Ok(None),
+                false => Err(corrupt_checksum()),
+            };
+        };
+        if digest.len() != *len as usize {
+            return Err(corrupt_checksum());
+        }
+        Ok(Some(Self {
+            algorithm: *algorithm,
+            digest: digest.to_vec(),
+        }))
+    }
+}
+
+fn corrupt_checksum() -> MonolithicaError {
+    tracing::error!("archive checksum is corrupt");
+    MonolithicaError::CorruptArchive("archive checksum is corrupt".to_owned())
+}
+
+pub(crate) fn write_index<W: Write>(
+    out: &mut W,
+    entries: &[(String, Asset)],
+    index_format: IndexFormat,
+) -> Result<()> {
+    match index_format {
+        IndexFormat::Text => {
+            for (path, asset) in entries {
+                write_index_line(out, path, asset, None)?;
+                for variant in &asset.variants {
+                    write_index_line(out, path, asset, Some(variant))?;
+                }
+            }
+        }
+        IndexFormat::Binary => write_binary_index(out, entries)?,
+        #[cfg(feature = "serde")]
+        IndexFormat::Json => {
+            let entries: Vec<_> = entries
+                .iter()
+                .map(|(path, asset)| JsonEntry {
+                    path: Cow::Borrowed(path),
+                    asset: asset.clone(),
+                })
+                .collect();
+            serde_json::to_writer_pretty(&mut *out, &entries)
+                .map_err(|e| invalid_index(format!("failed to write JSON index: {e}")))?;
+            out.write_all(b"\n")?;
+        }
+    }
+    Ok(())
+}
+
+/// An element of the JSON index.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(crate) struct JsonEntry<'a> {
+    #[serde(borrow)]
+    pub path: Cow<'a, str>,
+    #[serde(flatten)]
+    pub asset: Asset,
+}
+
+/// Whether `content` is a JSON index rather than a text index, whose first
+/// path could also start with `[`.
+pub(crate) fn is_json_index(content: &[u8]) -> bool {
+    match content.trim_ascii_start().strip_prefix(b"[") {
+        Some(rest) => {
+            let rest = rest.trim_ascii();
+            rest.starts_with(b"{") || rest == b"]"
+        }
+        None => false,
+    }
+}
+
+fn invalid_index(message: impl Into<String>) -> MonolithicaError {
+    MonolithicaError::InvalidIndex(message.into())
+}
+
+fn invalid_line(message: impl Into<String>) -> IndexLineProblem {
+    IndexLineProblem::Invalid(message.into())
+}
+
+fn invalid_number(field: &'static str, value: &str) -> IndexLineProblem {
+    IndexLineProblem::InvalidNumber {
+        field,
+        value: value.to_owned(),
+    }
+}
+
+/// Parses the 24 hex digits of a `nonce` field.
+fn parse_nonce(value: &str) -> Result<[u8; 12], IndexLineProblem> {
+    let mut nonce = [0u8; 12];
+    if value.len() != 2 * nonce.len() {
+        return Err(invalid_number("nonce", value));
+    }
+    for (i, byte) in nonce.iter_mut().enumerate() {
+        *byte = value
+            .get(2 * i..2 * i + 2)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| invalid_number("nonce", value))?;
+    }
+    Ok(nonce)
+}
+
+/// Reports `problem` as found on the text index line at `line_no`, counting
+/// from 0.
+pub(crate) fn at_line(line_no: usize, problem: IndexLineProblem) -> MonolithicaError {
+    MonolithicaError::IndexLine {
+        line: line_no + 1,
+        problem,
+    }
+}
+
+fn write_binary_index<W: Write>(out: &mut W, entries: &[(String, Asset)]) -> Result<()> {
+    let mut mimes: Vec<&str> = Vec::new();
+    let mut mime_ids = HashMap::new();
+    for mime in entries
+        .iter()
+        .filter_map(|(_, asset)| asset.mime.as_deref())
+    {
+        mime_ids.entry(mime).or_insert_with(|| {
+            mimes.push(mime);
+            mimes.len() as u32 - 1
+        });
+    }
+
+    out.write_all(BINARY_INDEX_MAGIC)?;
+    out.write_all(&BINARY_INDEX_VERSION.to_le_bytes())?;
+    out.write_all(&(mimes.len() as u32).to_le_bytes())?;
+    for mime in &mimes {
+        let len = u16::try_from(mime.len()).map_err(|_| invalid_index("mime too long"))?;
+        out.write_all(&len.to_le_bytes())?;
+        out.write_all(mime.as_bytes())?;
+    }
+
+    let records = entries.iter().flat_map(|(path, asset)| {
+        std::iter::once((path, asset, None)).chain(
+            asset
+                .variants
+                .iter()
+                .map(move |variant| (path, asset, Some(variant))),
+        )
+    });
+    let count =
+        u32::try_from(records.clone().count()).map_err(|_| invalid_index("too many entries"))?;
+    out.write_all(&count.to_le_bytes())?;
+    for (path, asset, variant) in records {
+        let len = u32::try_from(path.len()).map_err(|_| invalid_index("path too long"))?;
+        out.write_all(&len.to_le_bytes())?;
+        out.write_all(path.as_bytes())?;
+        let (offset, stored_len) = variant.map_or((asset.offset, asset.len), |v| (v.offset, v.len));
+        out.write_all(&offset.to_le_bytes())?;
+        out.write_all(&stored_len.to_le_bytes())?;
+        let mime_id = asset.mime.as_deref().map_or(NO_MIME, |mime| mime_ids[mime]);
+        out.write_all(&mime_id.to_le_bytes())?;
+        let extras = extra_fields(asset, variant).join("//");
+        let len =
+            u16::try_from(extras.len()).map_err(|_| invalid_index("extra fields too long"))?;
+        out.write_all(&len.to_le_bytes())?;
+        out.write_all(extras.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Parses an index of any format into its format and entries, in index order.
+/// A later entry for a path replaces the earlier one like it does in
+/// [`crate::AssetIndexer`], keeping the earlier one's position.
+#[cfg(feature = "std-fs")]
+pub(crate) fn read_entries(content: &[u8]) -> Result<(IndexFormat, Vec<(String, Asset)>)> {
+    let (index_format, records) = if content.starts_with(BINARY_INDEX_MAGIC) {
+        (IndexFormat::Binary, parse_binary_index(content)?)
+    } else if is_json_index(content) {
+        #[cfg(feature = "serde")]
+        {
+            let entries: Vec<JsonEntry> = serde_json::from_slice(content)
+                .map_err(|e| invalid_index(format!("invalid JSON index: {e}")))?;
+            let records = entries
+                .into_iter()
+                .map(|entry| IndexRecord {
+                    path: entry.path,
+                    asset: entry.asset,
+                    variant: false,
+                })
+                .collect();
+            (IndexFormat::Json, records)
+        }
+        #[cfg(not(feature = "serde"))]
+        return Err(MonolithicaError::Unsupported(
+            "JSON index support is not enabled".to_owned(),
+        ));
+    } else {
+        let mut records = Vec::new();
+        let content = std::str::from_utf8(content)
+            .map_err(|e| invalid_index(format!("index is not valid UTF-8: {e}")))?;
+        for (line_no, line) in content.lines().enumerate() {
+            if !line.trim().is_empty() {
+                records.push(parse_index_line(line).map_err(|e| at_line(line_no, e))?);
+            }
+        }
+        (IndexFormat::Text, records)
+    };
+
+    let mut entries: Vec<(String, Asset)> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for record in records {
+        if !record.variant {
+            match positions.get(record.path.as_ref()) {
+                Some(&i) => entries[i].1 = record.asset,
+                None => {
+                    positions.insert(record.path.to_string(), entries.len());
+                    entries.push((record.path.into_owned(), record.asset));
+                }
+            }
+            continue;
+        }
+
+        let encoding = record
+            .asset
+            .encoding
+            .ok_or_else(|| invalid_index("variant without encoding"))?;
+        let &i = positions
+            .get(record.path.as_ref())
+            .ok_or_else(|| invalid_index(format!("variant of unknown asset: {:?}", record.path)))?;
+        entries[i].1.variants.push(AssetVariant {
+            offset: record.asset.offset,
+            len: record.asset.len,
+            encoding,
+        });
+    }
+    Ok((index_format, entries))
+}
+
+/// One parsed index entry, either an asset or a variant of the asset with the
+/// same path.
+pub(crate) struct IndexRecord<'a> {
+    pub path: Cow<'a, str>,
+    pub asset: Asset,
+    pub variant: bool,
+}
+
+impl<'a> IndexRecord<'a> {
+    /// Adds the record to `map`, returning its path.
+    pub fn insert_into(self, map: &mut AssetMap<'a>) -> Result<Cow<'a, str>, IndexLineProblem> {
+        if !self.variant {
+            map.insert(self.path.clone(), self.asset);
+            return Ok(self.path);
+        }
+
+        let encoding = self
+            .asset
+            .encoding
+            .ok_or_else(|| invalid_line("variant without encoding"))?;
+        let asset = map
+            .get_mut(&self.path)
+            .ok_or_else(|| invalid_line(format!("variant of unknown asset: {:?}", self.path)))?;
+        asset.variants.push(AssetVariant {
+            offset: self.asset.offset,
+            len: self.asset.len,
+            encoding,
+        });
+        Ok(self.path)
+    }
+}
+
+pub(crate) fn parse_binary_index(content: &[u8]) -> Result<Vec<IndexRecord<'_>>> {
+    let mut input = SliceReader(content);
+    if input.take(4)? != BINARY_INDEX_MAGIC {
+        return Err(invalid_index("not a binary index"));
+    }
+    let version = input.u16()?;
+    if !(1..=BINARY_INDEX_VERSION).contains(&version) {
+        return Err(invalid_index(format!(
+            "unsupported binary index version: {version}"
+        )));
+    }
+
+    let mime_count = input.u32()?;
+    let mut mimes = Vec::new();
+    for _ in 0..mime_count {
+        let len = input.u16()?;
+        let mime = input.take(len as usize)?;
+        mimes.push(std::str::from_utf8(mime).map_err(|_| invalid_index("invalid mime"))?);
+    }
+
+    let count = input.u32()?;
+    let mut entries = Vec::new();
+    for i in 0..count {
+        let len = input.u32()?;
+        let path = std::str::from_utf8(input.take(len as usize)?)
+            .map_err(|_| invalid_index(format!("invalid path in entry {i}")))?;
+        let offset = input.u64()?;
+        let len = input.u64()?;
+        let mime_id = input.u32()?;
+        let mime = match mime_id {
+            NO_MIME => None,
+            _ => Some(
+                mimes
+                    .get(mime_id as usize)
+                    .ok_or_else(|| {
+                        invalid_index(format!("invalid mime id {mime_id} in entry {i}"))
+                    })?
+                    .to_string(),
+            ),
+        };
+        let mut asset = Asset {
+            offset,
+            len,
+            mime,
+            original_len: len,
+            ..Default::default()
+        };
+        let mut variant = false;
+        if version >= 2 {
+            let len = input.u16()?;
+            let extras = std::str::from_utf8(input.take(len as usize)?)
+                .map_err(|_| invalid_index(format!("invalid extra fields in entry {i}")))?;
+            variant = apply_extra_fields(&mut asset, extras.split("//").filter(|f| !f.is_empty()))
+                .map_err(|e| invalid_index(format!("entry {i}: {e}")))?;
+        }
+        entries.push(IndexRecord {
+            path: Cow::Borrowed(path),
+            asset,
+            variant,
+        });
+    }
+
+    if !input.0.is_empty() {
+        return Err(invalid_index(format!(
+            "{} trailing bytes after binary index",
+            input.0.len()
+        )));
+    }
+    Ok(entries)
+}
+
+struct SliceReader<'a>(&'a [u8]);
+
+impl<'a> SliceReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(invalid_index("binary index truncated"));
+        }
+        let (head, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+/// Writes the index line of `asset`, or of one of its variants.
+pub(crate) fn write_index_line<W: Write>(
+    out: &mut W,
+    path: &str,
+    asset: &Asset,
+    variant: Option<&AssetVariant>,
+) -> Result<()> {
+    let (offset, len) = variant.map_or((asset.offset, asset.len), |v| (v.offset, v.len));
+    write!(
+        out,
+        "{}//{}//{}//{}",
+        escape_path(path),
+        offset,
+        len,
+        asset.mime.as_deref().unwrap_or("-")
+    )?;
+    for field in extra_fields(asset, variant) {
+        write!(out, "//{field}")?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+fn extra_fields(asset: &Asset, variant: Option<&AssetVariant>) -> Vec<String> {
+    let mut fields = Vec::new();
+    if let Some(encoding) = variant.map(|v| v.encoding).or(asset.encoding) {
+        fields.push(format!("enc={}", encoding.as_str()));
+        fields.push(format!("size={}", asset.original_len));
+    } else if asset.nonce.is_some() {
+        fields.push(format!("size={}", asset.original_len));
+    }
+    if let Some(crc32) = asset.crc32.filter(|_| variant.is_none()) {
+        fields.push(format!("crc32={crc32:08x}"));
+    }
+    if let Some(mtime) = asset.mtime.filter(|_| variant.is_none()) {
+        fields.push(format!("mtime={mtime}"));
+    }
+    if let Some(mode) = asset.mode.filter(|_| variant.is_none()) {
+        fields.push(format!("mode={mode:o}"));
+    }
+    if let Some(target) = asset.symlink.as_ref().filter(|_| variant.is_none()) {
+        fields.push(format!("link={}", escape_path(target)));
+    }
+    if let Some(hash) = asset.content_hash.as_ref().filter(|_| variant.is_none()) {
+        fields.push(format!("hash={hash}"));
+    }
+    if asset.shard != 0 && variant.is_none() {
+        fields.push(format!("shard={}", asset.shard));
+    }
+    if let Some(nonce) = asset.nonce.filter(|_| variant.is_none()) {
+        let hex: String = nonce.iter().map(|byte| format!("{byte:02x}")).collect();
+        fields.push(format!("nonce={hex}"));
+    }
+    if variant.is_some() {
+        fields.push("variant=1".to_owned());
+    }
+    fields
+}
+
+/// Applies the `key=value` fields to `asset`, returning whether they mark it as
+/// a variant.
+fn apply_extra_fields<'a>(
+    asset: &mut Asset,
+    fields: impl Iterator<Item = &'a str>,
+) -> Result<bool, IndexLineProblem> {
+    let mut variant = false;
+    for field in fields {
+        let (key, value) = field
+            .split_once('=')
+            .ok_or_else(|| invalid_line(format!("invalid extra field: {field:?}")))?;
+        match key {
+            "enc" => {
+                asset.encoding = Some(
+                    Encoding::from_name(value)
+                        .ok_or_else(|| invalid_line(format!("unknown encoding: {value:?}")))?,
+                )
+            }
+            "size" => {
+                asset.original_len = value.parse().map_err(|_| invalid_number("size", value))?
+            }
+            "crc32" => {
+                asset.crc32 = Some(
+                    u32::from_str_radix(value, 16).map_err(|_| invalid_number("crc32", value))?,
+                )
+            }
+            "mtime" => {
+                asset.mtime = Some(value.parse().map_err(|_| invalid_number("mtime", value))?)
+            }
+            "mode" => {
+                asset.mode =
+                    Some(u32::from_str_radix(value, 8).map_err(|_| invalid_number("mode", value))?)
+            }
+            "link" => asset.symlink = Some(unescape_path(value)?.into_owned()),
+            "hash" => asset.content_hash = Some(value.to_owned()),
+            "shard" => asset.shard = value.parse().map_err(|_| invalid_number("shard", value))?,
+            "variant" => variant = value == "1",
+            "nonce" => asset.nonce = Some(parse_nonce(value)?),
+            _ => {}
+        }
+    }
+    Ok(variant)
+}
+
+pub(crate) fn parse_index_line(line: &str) -> Result<IndexRecord<'_>, IndexLineProblem> {
+    // `str::lines` leaves a `\r` that isn't followed by `\n`, paths have it
+    // escaped.
+    let line = line.trim_end_matches('\r');
+    let fields: Vec<&str> = line.split("//").collect();
+    if fields.len() < 4 {
+        return Err(IndexLineProblem::FieldCount {
+            found: fields.len(),
+        });
+    }
+
+    let path = unescape_path(fields[0])?;
+    let mut asset = Asset {
+        offset: fields[1]
+            .parse()
+            .map_err(|_| invalid_number("offset", fields[1]))?,
+        len: fields[2]
+            .parse()
+            .map_err(|_| invalid_number("len", fields[2]))?,
+        mime: match fields[3] {
+            "" | "-" => None,
+            mime => Some(mime.to_owned()),
+        },
+        ..Default::default()
+    };
+    asset.original_len = asset.len;
+    let variant = apply_extra_fields(&mut asset, fields[4..].iter().copied())?;
+
+    Ok(IndexRecord {
+        path,
+        asset,
+        variant,
+    })
+}
+
+fn escape_path(path: &str) -> Cow<'_, str> {
+    let needs_escape = |(i, c): (usize, char)| match c {
+        '%' | '\n' | '\r' => true,
+        '/' => i + 1 == path.len() || path[..i].ends_with('/'),
+        _ => false,
+    };
+    if !path.char_indices().any(needs_escape) {
+        return Cow::Borrowed(path);
+    }
+
+    let mut escaped = String::with_capacity(path.len() + 8);
+    for (i, c) in path.char_indices() {
+        if needs_escape((i, c)) {
+            escaped.push_str(&format!("%{:02X}", c as u8));
+        } else {
+            escaped.push(c);
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+fn unescape_path(path: &str) -> Result<Cow<'_, str>, IndexLineProblem> {
+    if !path.contains('%') {
+        return Ok(Cow::Borrowed(path));
+    }
+
+    let mut unescaped = String::with_capacity(path.len());
+    let mut rest = path;
+    while let Some(pos) = rest.find('%') {
+        unescaped.push_str(&rest[..pos]);
+        let byte = rest
+            .get(pos + 1..pos + 3)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .filter(u8::is_ascii)
+            .ok_or_else(|| invalid_line(format!("invalid escape in path: {path:?}")))?;
+        unescaped.push(byte as char);
+        rest = &rest[pos + 3..];
+    }
+    unescaped.push_str(rest);
+
+    Ok(Cow::Owned(unescaped))
+}
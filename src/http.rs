@@ -0,0 +1,1049 @@
+//! Serving an [`Archive`] over HTTP with axum, behind the `axum` feature.
+
+use crate::{
+    filter::glob, mime_essence, normalize_path, Archive, Asset, Encoding, MonolithicaError, Result,
+};
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    response::{IntoResponse, Response},
+    Router,
+};
+use globset::GlobMatcher;
+use std::{
+    ops::Range,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Answers `GET` and `HEAD` requests with the assets of an archive, their
+/// stored mime as `Content-Type`. `/` and directory paths are served their
+/// `index.html`, anything else missing is a 404. Responses carry the
+/// [`Asset::etag`] of the asset, and requests whose `If-None-Match` has it are
+/// answered with a 304, as are requests without `If-None-Match` whose
+/// `If-Modified-Since` isn't older than the recorded [`Asset::mtime`], also
+/// sent as `Last-Modified`. A single `Range` is answered with a 206 and the
+/// requested part of the asset. Precompressed variants, and assets stored
+/// compressed, are served as is to clients whose `Accept-Encoding` allows it.
+/// With [`ArchiveService::with_fallback`], missing paths that don't
+/// [look like files](crate::looks_like_file) are served the fallback asset,
+/// for single-page apps that route on the client. With
+/// [`ArchiveService::with_cache_policy`], responses carry a `Cache-Control`
+/// header.
+///
+/// ```no_run
+/// # async fn serve() -> anyhow::Result<()> {
+/// use monolithica::{Archive, ArchiveService};
+///
+/// let archive = Archive::open("assets.blob", "assets.blob.idx")?;
+/// let app = ArchiveService::new(archive).into_router();
+/// let listener = tokio::net::TcpListener::bind("127.0.0.1:8080").await?;
+/// axum::serve(listener, app).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ArchiveService {
+    archive: Arc<Archive>,
+    fallback: Option<Arc<str>>,
+    cache_policy: Option<Arc<CachePolicy>>,
+}
+
+/// Decides the `Cache-Control` header of each response of an
+/// [`ArchiveService`] from the asset's path and mime, see
+/// [`ArchiveService::with_cache_policy`]. The first of these that applies
+/// is sent:
+///
+/// 1. the value of the first glob added with [`CachePolicy::for_glob`] that
+///    matches the archive path,
+/// 2. the value for the asset's mime, see [`CachePolicy::for_mime`],
+/// 3. the value for [fingerprinted](CachePolicy::for_fingerprinted) files,
+/// 4. the [fallback](CachePolicy::fallback) value.
+///
+/// Otherwise no `Cache-Control` is sent. [`CachePolicy::default`] sends
+/// `no-cache` for HTML, so pages are revalidated on each load, and caches
+/// fingerprinted files for a year as immutable.
+///
+/// ```
+/// # fn main() -> monolithica::Result<()> {
+/// use axum::http::HeaderValue;
+/// use monolithica::CachePolicy;
+///
+/// let policy = CachePolicy::default()
+///     .for_glob("static/**", HeaderValue::from_static("public, max-age=3600"))?
+///     .for_mime("image/*", HeaderValue::from_static("public, max-age=86400"));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct CachePolicy {
+    globs: Vec<(GlobMatcher, HeaderValue)>,
+    mimes: Vec<(String, HeaderValue)>,
+    fingerprinted: Option<HeaderValue>,
+    fallback: Option<HeaderValue>,
+}
+
+impl Default for CachePolicy {
+    fn default() -> Self {
+        Self::empty()
+            .for_mime("text/html", HeaderValue::from_static("no-cache"))
+            .for_fingerprinted(HeaderValue::from_static(
+                "public, max-age=31536000, immutable",
+            ))
+    }
+}
+
+impl CachePolicy {
+    /// A policy without any values, to build up from scratch.
+    pub fn empty() -> Self {
+        Self {
+            globs: Vec::new(),
+            mimes: Vec::new(),
+            fingerprinted: None,
+            fallback: None,
+        }
+    }
+
+    /// Sends `value` for assets whose archive path matches `pattern`, taking
+    /// precedence over everything else, globs added earlier first.
+    pub fn for_glob(mut self, pattern: &str, value: HeaderValue) -> Result<Self> {
+        self.globs.push((glob(pattern)?.compile_matcher(), value));
+        Ok(self)
+    }
+
+    /// Sends `value` for assets of `mime`, compared without parameters, or of
+    /// any subtype for a `type/*` mime. Replaces an earlier value for the same
+    /// mime. Assets without a mime are matched as [`crate::DEFAULT_MIME`].
+    pub fn for_mime(mut self, mime: &str, value: HeaderValue) -> Self {
+        let mime = mime_essence(mime).to_ascii_lowercase();
+        self.mimes.retain(|(other, _)| *other != mime);
+        self.mimes.push((mime, value));
+        self
+    }
+
+    /// Sends `value` for assets whose file name is fingerprinted with a hash
+    /// of their content, which this takes to be a part of 8 or more hex
+    /// digits between dots, dashes or underscores before the extension, as in
+    /// `app.3f2a9c1b.js` or `logo-0d4e5f6a7b8c.png`.
+    pub fn for_fingerprinted(mut self, value: HeaderValue) -> Self {
+        self.fingerprinted = Some(value);
+        self
+    }
+
+    /// Sends `value` for assets nothing else applies to.
+    pub fn fallback(mut self, value: HeaderValue) -> Self {
+        self.fallback = Some(value);
+        self
+    }
+
+    /// The `Cache-Control` value for `asset` at `path`.
+    pub fn value_for(&self, path: &str, asset: &Asset) -> Option<&HeaderValue> {
+        if let Some((_, value)) = self.globs.iter().find(|(glob, _)| glob.is_match(path)) {
+            return Some(value);
+        }
+        let mime = mime_essence(asset.mime_or_default()).to_ascii_lowercase();
+        let by_mime = self
+            .mimes
+            .iter()
+            .find(|(pattern, _)| *pattern == mime)
+            .or_else(|| {
+                let wildcard = format!("{}/*", mime.split('/').next().unwrap_or_default());
+                self.mimes.iter().find(|(pattern, _)| *pattern == wildcard)
+            });
+        if let Some((_, value)) = by_mime {
+            return Some(value);
+        }
+        match &self.fingerprinted {
+            Some(value) if is_fingerprinted(path) => Some(value),
+            _ => self.fallback.as_ref(),
+        }
+    }
+}
+
+/// Whether the file name of `path` has a hex hash in it, see
+/// [`CachePolicy::for_fingerprinted`].
+fn is_fingerprinted(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or_default();
+    let Some((stem, _extension)) = name.rsplit_once('.') else {
+        return false;
+    };
+    stem.split(['.', '-', '_'])
+        .skip(1)
+        .any(|part| part.len() >= 8 && part.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
+impl ArchiveService {
+    pub fn new(archive: Archive) -> Self {
+        Self::from_shared(Arc::new(archive))
+    }
+
+    /// Serves an archive that is also used elsewhere.
+    pub fn from_shared(archive: Arc<Archive>) -> Self {
+        Self {
+            archive,
+            fallback: None,
+            cache_policy: None,
+        }
+    }
+
+    /// Serves the asset at `fallback`, typically `index.html`, for missing
+    /// paths that don't look like files, see [`Archive::locate_or_fallback`].
+    pub fn with_fallback(mut self, fallback: &str) -> Self {
+        self.fallback = Some(fallback.into());
+        self
+    }
+
+    /// Sends the `Cache-Control` header `policy` decides on with each
+    /// response, not modified ones included.
+    pub fn with_cache_policy(mut self, policy: CachePolicy) -> Self {
+        self.cache_policy = Some(Arc::new(policy));
+        self
+    }
+
+    pub fn archive(&self) -> &Arc<Archive> {
+        &self.archive
+    }
+
+    /// A router serving every path from the archive, to use as is or to
+    /// [`Router::nest`] or [`Router::merge`] into an application.
+    pub fn into_router(self) -> Router {
+        Router::new().fallback(handle).with_state(self)
+    }
+
+    /// Answers `request`, for use from a handler of your own. Reads the asset
+    /// synchronously, see [`ArchiveService::into_router`] for a handler that
+    /// doesn't block the runtime.
+    pub fn respond<B>(&self, request: &Request<B>) -> Response {
+        let method = request.method();
+        if method != Method::GET && method != Method::HEAD {
+            let mut response = StatusCode::METHOD_NOT_ALLOWED.into_response();
+            response
+                .headers_mut()
+                .insert(header::ALLOW, HeaderValue::from_static("GET, HEAD"));
+            return response;
+        }
+
+        let Some((path, asset)) = self.resolve(request.uri().path()) else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+
+        let accept_encoding: Vec<_> = request
+            .headers()
+            .get_all(header::ACCEPT_ENCODING)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect();
+        // The bytes of an encrypted archive are stored with an authentication
+        // tag, so they're only ever served decrypted and decoded.
+        let encoded = match self.archive.is_encrypted() {
+            true => None,
+            false => negotiate_encoding(asset, &accept_encoding.join(",")),
+        };
+        let vary = asset.is_compressed() || !asset.variants.is_empty();
+        let etag = match encoded {
+            Some(stored) => encoded_etag(&asset.etag(), stored.encoding),
+            None => asset.etag(),
+        };
+
+        let last_modified = asset
+            .mtime
+            .map(|mtime| httpdate::fmt_http_date(system_time(mtime)));
+        let cache_control = self
+            .cache_policy
+            .as_ref()
+            .and_then(|policy| policy.value_for(&path, asset))
+            .cloned();
+
+        if is_not_modified(request.headers(), &etag, asset.mtime) {
+            let mut response = Response::builder()
+                .status(StatusCode::NOT_MODIFIED)
+                .header(header::ETAG, etag);
+            if let Some(last_modified) = last_modified {
+                response = response.header(header::LAST_MODIFIED, last_modified);
+            }
+            if let Some(cache_control) = cache_control {
+                response = response.header(header::CACHE_CONTROL, cache_control);
+            }
+            if vary {
+                response = response.header(header::VARY, "accept-encoding");
+            }
+            return response.body(Body::empty()).unwrap();
+        }
+
+        let len = encoded.map_or(asset.original_len, |stored| stored.len);
+        let range = request
+            .headers()
+            .get(header::RANGE)
+            .and_then(|value| value.to_str().ok().map(|value| parse_range(value, len)));
+        let range = match range {
+            Some(ByteRange::Satisfiable(range)) => Some(range),
+            Some(ByteRange::Unsatisfiable) => {
+                return Response::builder()
+                    .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                    .header(header::CONTENT_RANGE, format!("bytes */{len}"))
+                    .body(Body::empty())
+                    .unwrap();
+            }
+            Some(ByteRange::Ignored) | None => None,
+        };
+
+        let body_range = range.clone().unwrap_or(0..len);
+        let mut response = Response::builder()
+            .header(header::CONTENT_TYPE, asset.mime_or_default())
+            .header(header::CONTENT_LENGTH, body_range.end - body_range.start)
+            .header(header::ACCEPT_RANGES, "bytes")
+            .header(header::ETAG, etag);
+        if let Some(last_modified) = last_modified {
+            response = response.header(header::LAST_MODIFIED, last_modified);
+        }
+        if let Some(cache_control) = cache_control {
+            response = response.header(header::CACHE_CONTROL, cache_control);
+        }
+        if vary {
+            response = response.header(header::VARY, "accept-encoding");
+        }
+        if let Some(stored) = encoded {
+            response = response.header(header::CONTENT_ENCODING, stored.encoding.as_str());
+        }
+        if let Some(range) = range {
+            response = response.status(StatusCode::PARTIAL_CONTENT).header(
+                header::CONTENT_RANGE,
+                format!("bytes {}-{}/{len}", range.start, range.end - 1),
+            );
+        }
+        if method == Method::HEAD {
+            return response.body(Body::empty()).unwrap();
+        }
+
+        match self.read_body(&path, asset, encoded, body_range) {
+            Ok(body) => response.body(Body::from(body)).unwrap(),
+            Err(e) => {
+                tracing::error!("failed to read {path}: {e:#}");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        }
+    }
+
+    /// Reads `range` of the `encoded` form of the asset, or of the decoded
+    /// asset. Only that part of the blob is read unless the asset has to be
+    /// decoded.
+    fn read_body(
+        &self,
+        path: &str,
+        asset: &Asset,
+        encoded: Option<Stored>,
+        range: Range<u64>,
+    ) -> Result<Vec<u8>> {
+        if let Some(stored) = encoded {
+            return self.archive.read_asset_region(
+                path,
+                asset.shard,
+                stored.offset + range.start,
+                range.end - range.start,
+            );
+        }
+        self.archive
+            .read_range(path, range.start, range.end - range.start)?
+            .ok_or_else(|| MonolithicaError::CorruptArchive("asset disappeared".to_owned()))
+    }
+
+    /// Finds the asset a request path refers to, along with its archive path.
+    fn resolve(&self, request_path: &str) -> Option<(String, &Asset)> {
+        let decoded = percent_decode(request_path)?;
+        if !decoded.ends_with('/') {
+            if let Some((path, asset)) = self.archive.resolve(&decoded) {
+                return Some((path.to_owned(), asset));
+            }
+        }
+        let path = normalize_path(&decoded)?;
+        let index = match path.as_ref() {
+            "" => "index.html".to_owned(),
+            dir => format!("{dir}/index.html"),
+        };
+        if let Some(asset) = self.archive.locate_asset(&index) {
+            return Some((index, asset));
+        }
+        let fallback = self.fallback.as_deref()?;
+        let (fallback, asset) = self.archive.locate_or_fallback(&path, fallback)?;
+        Some((fallback.to_owned(), asset))
+    }
+}
+
+async fn handle(State(service): State<ArchiveService>, request: Request) -> Response {
+    let (parts, _) = request.into_parts();
+    let request = Request::from_parts(parts, ());
+    tokio::task::spawn_blocking(move || service.respond(&request))
+        .await
+        .unwrap_or_else(|e| {
+            tracing::error!("asset handler failed: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        })
+}
+
+/// An encoded form of an asset in the blob.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stored {
+    encoding: Encoding,
+    offset: u64,
+    len: u64,
+}
+
+/// Server preference among encodings the client accepts equally.
+const PREFERRED_ENCODINGS: [Encoding; 3] = [Encoding::Brotli, Encoding::Zstd, Encoding::Gzip];
+
+/// Picks the encoded form of `asset` to serve a client sending
+/// `accept_encoding`, the one with the highest quality value and then the
+/// server's preference. `None` to serve the asset decoded.
+fn negotiate_encoding(asset: &Asset, accept_encoding: &str) -> Option<Stored> {
+    let mut forms: Vec<Stored> = asset
+        .variants
+        .iter()
+        .map(|variant| Stored {
+            encoding: variant.encoding,
+            offset: variant.offset,
+            len: variant.len,
+        })
+        .collect();
+    if let Some(encoding) = asset.encoding {
+        forms.push(Stored {
+            encoding,
+            offset: asset.offset,
+            len: asset.len,
+        });
+    }
+    if forms.is_empty() {
+        return None;
+    }
+
+    let mut qualities = Vec::new();
+    for coding in accept_encoding.to_ascii_lowercase().split(',') {
+        let mut params = coding.split(';');
+        let name = params.next().unwrap_or_default().trim().to_owned();
+        let quality = params
+            .find_map(|param| param.trim().strip_prefix("q="))
+            .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())
+            .filter(|q| (0.0..=1.0).contains(q));
+        if let (false, Some(quality)) = (name.is_empty(), quality) {
+            qualities.push((name, quality));
+        }
+    }
+    let quality = |encoding: Encoding| {
+        let named = |name: &str| qualities.iter().find(|(n, _)| n == name).map(|(_, q)| *q);
+        let alias = (encoding == Encoding::Gzip)
+            .then(|| named("x-gzip"))
+            .flatten();
+        named(encoding.as_str())
+            .or(alias)
+            .or_else(|| named("*"))
+            .unwrap_or(0.0)
+    };
+
+    let rank = |stored: &Stored| {
+        let preference = PREFERRED_ENCODINGS
+            .iter()
+            .position(|&e| e == stored.encoding)
+            .unwrap_or(PREFERRED_ENCODINGS.len());
+        (quality(stored.encoding), std::cmp::Reverse(preference))
+    };
+    forms
+        .into_iter()
+        .filter(|stored| quality(stored.encoding) > 0.0)
+        .max_by(|a, b| rank(a).partial_cmp(&rank(b)).unwrap())
+}
+
+/// The entity tag of the `encoding` form of the asset tagged `etag`.
+fn encoded_etag(etag: &str, encoding: Encoding) -> String {
+    let unquoted = etag.strip_suffix('"').unwrap_or(etag);
+    format!("{unquoted}-{}\"", encoding.as_str())
+}
+
+enum ByteRange {
+    Satisfiable(Range<u64>),
+    Unsatisfiable,
+    /// Malformed, or several ranges, which are answered with the whole asset.
+    Ignored,
+}
+
+/// Parses a `Range` header for an asset of `len` bytes, clamping the end to
+/// the asset. Only a single range is supported.
+fn parse_range(value: &str, len: u64) -> ByteRange {
+    let Some((start, end)) = value
+        .trim()
+        .strip_prefix("bytes=")
+        .filter(|spec| !spec.contains(','))
+        .and_then(|spec| spec.split_once('-'))
+    else {
+        return ByteRange::Ignored;
+    };
+    let (start, end) = (start.trim(), end.trim());
+
+    if start.is_empty() {
+        return match end.parse::<u64>() {
+            Ok(0) => ByteRange::Unsatisfiable,
+            Ok(_) if len == 0 => ByteRange::Unsatisfiable,
+            Ok(suffix) => ByteRange::Satisfiable(len.saturating_sub(suffix)..len),
+            Err(_) => ByteRange::Ignored,
+        };
+    }
+
+    let Ok(start) = start.parse::<u64>() else {
+        return ByteRange::Ignored;
+    };
+    let end = match end {
+        "" => u64::MAX,
+        end => match end.parse::<u64>() {
+            Ok(end) if end >= start => end,
+            _ => return ByteRange::Ignored,
+        },
+    };
+    if start >= len {
+        return ByteRange::Unsatisfiable;
+    }
+    ByteRange::Satisfiable(start..end.min(len - 1) + 1)
+}
+
+/// Whether the `If-None-Match` list `tags` has `etag`, compared weakly as
+/// required for that header.
+fn etag_matches(tags: &str, etag: &str) -> bool {
+    fn opaque(tag: &str) -> &str {
+        tag.trim().trim_start_matches("W/")
+    }
+    tags.split(',')
+        .any(|tag| tag.trim() == "*" || opaque(tag) == opaque(etag))
+}
+
+/// Decodes `%XX` escapes in a request path, `None` if that doesn't yield UTF-8.
+fn percent_decode(path: &str) -> Option<String> {
+    let hex = |b: u8| (b as char).to_digit(16);
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = match bytes.get(i..i + 3) {
+            Some(&[b'%', hi, lo]) => hex(hi).zip(hex(lo)).map(|(hi, lo)| (hi * 16 + lo) as u8),
+            _ => None,
+        };
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// Whether the client's copy of the asset with `etag` last modified at `mtime`
+/// is current, as told by `If-None-Match`, or without it by
+/// `If-Modified-Since` (RFC 9110, section 13.2.2). Values that don't parse
+/// and dates in the future are ignored, so the asset is sent.
+fn is_not_modified(headers: &HeaderMap, etag: &str, mtime: Option<u64>) -> bool {
+    if headers.contains_key(header::IF_NONE_MATCH) {
+        return headers
+            .get_all(header::IF_NONE_MATCH)
+            .iter()
+            .any(|value| value.to_str().is_ok_and(|tags| etag_matches(tags, etag)));
+    }
+    let Some(mtime) = mtime else {
+        return false;
+    };
+    headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| httpdate::parse_http_date(value).ok())
+        .is_some_and(|since| since <= SystemTime::now() && system_time(mtime) <= since)
+}
+
+fn system_time(mtime: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(mtime)
+}
+
+#[cfg(all(test, feature = "std-fs"))]
+mod tests {
+    use super::*;
+    use crate::test_util::{fixture_dir, write_file};
+    use crate::{ArchiveOptions, AssetArchiver, AssetVariant};
+    use axum::body::to_bytes;
+    use tower::ServiceExt;
+
+    fn service(name: &str) -> ArchiveService {
+        let root = fixture_dir(name);
+        write_file(&root.join("src/index.html"), "<html></html>");
+        write_file(&root.join("src/docs/index.html"), "<p>docs</p>");
+        write_file(&root.join("src/my file.css"), "body{}");
+        let (blob, index) = (root.join("test.blob"), root.join("test.blob.idx"));
+        AssetArchiver::create_archive(root.join("src"), &blob, &index, false).unwrap();
+        ArchiveService::new(Archive::open(&blob, &index).unwrap())
+    }
+
+    fn get(uri: &str) -> Request<()> {
+        Request::builder().uri(uri).body(()).unwrap()
+    }
+
+    async fn body(response: Response) -> Vec<u8> {
+        to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap()
+            .to_vec()
+    }
+
+    #[tokio::test]
+    async fn serve_assets() {
+        let service = service("http_serve_assets");
+
+        let response = service.respond(&get("/my%20file.css"));
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/css");
+        assert_eq!(response.headers()[header::CONTENT_LENGTH], "6");
+        assert_eq!(body(response).await, b"body{}");
+
+        for (uri, expected) in [
+            ("/", &b"<html></html>"[..]),
+            ("/docs", b"<p>docs</p>"),
+            ("/docs/", b"<p>docs</p>"),
+        ] {
+            let response = service.respond(&get(uri));
+            assert_eq!(response.status(), StatusCode::OK, "{uri}");
+            assert_eq!(body(response).await, expected, "{uri}");
+        }
+
+        for uri in ["/missing.js", "/index.html/", "/../index.html", "/%ff"] {
+            let response = service.respond(&get(uri));
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{uri}");
+        }
+
+        let head = Request::head("/index.html").body(()).unwrap();
+        let response = service.respond(&head);
+        assert_eq!(response.headers()[header::CONTENT_LENGTH], "13");
+        assert!(body(response).await.is_empty());
+
+        let post = Request::post("/index.html").body(()).unwrap();
+        let response = service.respond(&post);
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers()[header::ALLOW], "GET, HEAD");
+    }
+
+    #[tokio::test]
+    async fn spa_fallback() {
+        let service = service("http_spa_fallback").with_fallback("index.html");
+        for uri in ["/users/42", "/docs/missing/", "/settings"] {
+            let response = service.respond(&get(uri));
+            assert_eq!(response.status(), StatusCode::OK, "{uri}");
+            assert_eq!(body(response).await, b"<html></html>", "{uri}");
+        }
+        let response = service.respond(&get("/docs"));
+        assert_eq!(body(response).await, b"<p>docs</p>");
+        for uri in ["/missing.js", "/js/app.min.css", "/../users"] {
+            let response = service.respond(&get(uri));
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{uri}");
+        }
+
+        let service = service.with_fallback("missing.html");
+        let response = service.respond(&get("/users/42"));
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn not_modified() {
+        let service = service("http_not_modified");
+        let response = service.respond(&get("/index.html"));
+        let etag = response.headers()[header::ETAG].clone();
+        assert!(etag.to_str().unwrap().starts_with('"'));
+
+        let conditional = |tags: &str| {
+            Request::get("/index.html")
+                .header(header::IF_NONE_MATCH, tags)
+                .body(())
+                .unwrap()
+        };
+        for tags in [
+            etag.to_str().unwrap().to_owned(),
+            format!("\"other\", W/{}", etag.to_str().unwrap()),
+            "*".to_owned(),
+        ] {
+            let response = service.respond(&conditional(&tags));
+            assert_eq!(response.status(), StatusCode::NOT_MODIFIED, "{tags}");
+            assert_eq!(response.headers()[header::ETAG], etag);
+            assert!(body(response).await.is_empty());
+        }
+
+        let response = service.respond(&conditional("\"other\""));
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body(response).await, b"<html></html>");
+    }
+
+    #[tokio::test]
+    async fn cache_control() {
+        let root = fixture_dir("http_cache_control");
+        write_file(&root.join("src/index.html"), "<html></html>");
+        write_file(&root.join("src/js/app.3f2a9c1b.js"), "console.log(1);");
+        write_file(&root.join("src/js/app.js"), "console.log(2);");
+        write_file(&root.join("src/static/logo-0d4e5f6a7b8c.png"), "png");
+        let (blob, index) = (root.join("test.blob"), root.join("test.blob.idx"));
+        AssetArchiver::create_archive(root.join("src"), &blob, &index, false).unwrap();
+        let service = ArchiveService::new(Archive::open(&blob, &index).unwrap());
+        let cache_control = |service: &ArchiveService, uri: &str| {
+            let response = service.respond(&get(uri));
+            assert_eq!(response.status(), StatusCode::OK, "{uri}");
+            response.headers().get(header::CACHE_CONTROL).cloned()
+        };
+        assert_eq!(cache_control(&service, "/index.html"), None);
+
+        let service = service.with_cache_policy(CachePolicy::default());
+        let immutable = "public, max-age=31536000, immutable";
+        assert_eq!(cache_control(&service, "/").unwrap(), "no-cache");
+        assert_eq!(
+            cache_control(&service, "/js/app.3f2a9c1b.js").unwrap(),
+            immutable
+        );
+        assert_eq!(
+            cache_control(&service, "/static/logo-0d4e5f6a7b8c.png").unwrap(),
+            immutable
+        );
+        assert_eq!(cache_control(&service, "/js/app.js"), None);
+
+        // Not modified responses carry it too.
+        let etag = service.respond(&get("/index.html")).headers()[header::ETAG].clone();
+        let conditional = Request::get("/index.html")
+            .header(header::IF_NONE_MATCH, etag)
+            .body(())
+            .unwrap();
+        let response = service.respond(&conditional);
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "no-cache");
+
+        let policy = CachePolicy::default()
+            .for_glob("static/**", HeaderValue::from_static("max-age=60"))
+            .unwrap()
+            .for_mime(
+                "text/html; charset=utf-8",
+                HeaderValue::from_static("no-store"),
+            )
+            .for_mime("application/*", HeaderValue::from_static("max-age=10"))
+            .fallback(HeaderValue::from_static("max-age=600"));
+        let service = service.with_cache_policy(policy);
+        assert_eq!(cache_control(&service, "/index.html").unwrap(), "no-store");
+        assert_eq!(
+            cache_control(&service, "/static/logo-0d4e5f6a7b8c.png").unwrap(),
+            "max-age=60"
+        );
+        // Mimes take precedence over fingerprints.
+        assert_eq!(
+            cache_control(&service, "/js/app.3f2a9c1b.js").unwrap(),
+            "max-age=10"
+        );
+
+        let service = service.with_cache_policy(
+            CachePolicy::empty()
+                .for_fingerprinted(HeaderValue::from_static(immutable))
+                .fallback(HeaderValue::from_static("max-age=600")),
+        );
+        assert_eq!(
+            cache_control(&service, "/index.html").unwrap(),
+            "max-age=600"
+        );
+        assert_eq!(
+            cache_control(&service, "/js/app.3f2a9c1b.js").unwrap(),
+            immutable
+        );
+        assert!(CachePolicy::empty()
+            .for_glob("a/{b", HeaderValue::from_static("no-cache"))
+            .is_err());
+
+        for (path, fingerprinted) in [
+            ("app.3f2a9c1b.js", true),
+            ("a/b/app_3F2A9C1B.min.js", true),
+            ("3f2a9c1b.js", false),
+            ("app.3f2a9c1.js", false),
+            ("app.js.3f2a9c1b", false),
+            ("deadbeefcafe", false),
+        ] {
+            assert_eq!(is_fingerprinted(path), fingerprinted, "{path}");
+        }
+    }
+
+    #[tokio::test]
+    async fn last_modified() {
+        let root = fixture_dir("http_last_modified");
+        write_file(&root.join("src/index.html"), "<html></html>");
+        // An hour ago, so that a minute later isn't in the future.
+        std::fs::File::options()
+            .write(true)
+            .open(root.join("src/index.html"))
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(3600))
+            .unwrap();
+        let (blob, index) = (root.join("test.blob"), root.join("test.blob.idx"));
+        let options = ArchiveOptions {
+            mtime: true,
+            ..Default::default()
+        };
+        AssetArchiver::create_archive_with_options(
+            root.join("src"),
+            &blob,
+            &index,
+            false,
+            &options,
+        )
+        .unwrap();
+        let served = ArchiveService::new(Archive::open(&blob, &index).unwrap());
+
+        let response = served.respond(&get("/index.html"));
+        let last_modified = response.headers()[header::LAST_MODIFIED].clone();
+        let etag = response.headers()[header::ETAG].clone();
+        let since = httpdate::parse_http_date(last_modified.to_str().unwrap()).unwrap();
+
+        let conditional = |since: SystemTime, etag: Option<&str>| {
+            let mut request = Request::get("/index.html")
+                .header(header::IF_MODIFIED_SINCE, httpdate::fmt_http_date(since));
+            if let Some(etag) = etag {
+                request = request.header(header::IF_NONE_MATCH, etag);
+            }
+            served.respond(&request.body(()).unwrap()).status()
+        };
+        assert_eq!(conditional(since, None), StatusCode::NOT_MODIFIED);
+        let later = since + Duration::from_secs(60);
+        assert_eq!(conditional(later, None), StatusCode::NOT_MODIFIED);
+        let earlier = since - Duration::from_secs(60);
+        assert_eq!(conditional(earlier, None), StatusCode::OK);
+        assert_eq!(conditional(since, Some("\"other\"")), StatusCode::OK);
+        let etag = etag.to_str().unwrap();
+        assert_eq!(conditional(earlier, Some(etag)), StatusCode::NOT_MODIFIED);
+
+        let without_mtime = service("http_last_modified_without_mtime");
+        let response = without_mtime.respond(&get("/index.html"));
+        assert!(!response.headers().contains_key(header::LAST_MODIFIED));
+        let request = Request::get("/index.html")
+            .header(header::IF_MODIFIED_SINCE, httpdate::fmt_http_date(since))
+            .body(())
+            .unwrap();
+        assert_eq!(without_mtime.respond(&request).status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn conditional_requests() {
+        let root = fixture_dir("http_conditional_requests");
+        write_file(&root.join("src/index.html"), "<html></html>");
+        let (blob, index) = (root.join("test.blob"), root.join("test.blob.idx"));
+        let options = ArchiveOptions {
+            mtime: true,
+            ..Default::default()
+        };
+        AssetArchiver::create_archive_with_options(
+            root.join("src"),
+            &blob,
+            &index,
+            false,
+            &options,
+        )
+        .unwrap();
+        let served = ArchiveService::new(Archive::open(&blob, &index).unwrap());
+        let response = served.respond(&get("/index.html"));
+        let etag = response.headers()[header::ETAG]
+            .to_str()
+            .unwrap()
+            .to_owned();
+        let modified = response.headers()[header::LAST_MODIFIED].clone();
+        let modified = modified.to_str().unwrap();
+        let future = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(3600));
+
+        type Headers<'a> = &'a [(header::HeaderName, &'a [u8])];
+        let respond = |method: Method, headers: Headers| {
+            let mut request = Request::builder().method(method).uri("/index.html");
+            for (name, value) in headers {
+                request = request.header(name, HeaderValue::from_bytes(value).unwrap());
+            }
+            served.respond(&request.body(()).unwrap())
+        };
+        let status = |headers: Headers| respond(Method::GET, headers).status();
+        let (inm, ims) = (header::IF_NONE_MATCH, header::IF_MODIFIED_SINCE);
+
+        // If-None-Match wins over If-Modified-Since either way.
+        let cases: &[(Headers, StatusCode)] = &[
+            (&[(inm.clone(), etag.as_bytes())], StatusCode::NOT_MODIFIED),
+            (
+                &[(ims.clone(), modified.as_bytes())],
+                StatusCode::NOT_MODIFIED,
+            ),
+            (
+                &[
+                    (inm.clone(), b"\"other\""),
+                    (ims.clone(), modified.as_bytes()),
+                ],
+                StatusCode::OK,
+            ),
+            (
+                &[
+                    (inm.clone(), etag.as_bytes()),
+                    (ims.clone(), b"Thu, 01 Jan 1970 00:00:00 GMT"),
+                ],
+                StatusCode::NOT_MODIFIED,
+            ),
+            // Malformed values are ignored.
+            (&[(inm.clone(), b"not-a-tag")], StatusCode::OK),
+            (&[(inm.clone(), b"\xff\"tag\"")], StatusCode::OK),
+            (&[(inm.clone(), b"")], StatusCode::OK),
+            (&[(ims.clone(), b"yesterday")], StatusCode::OK),
+            (&[(ims.clone(), b"")], StatusCode::OK),
+            (&[(ims.clone(), future.as_bytes())], StatusCode::OK),
+            (
+                &[
+                    (inm.clone(), b"not-a-tag"),
+                    (ims.clone(), modified.as_bytes()),
+                ],
+                StatusCode::OK,
+            ),
+        ];
+        for (i, (headers, expected)) in cases.iter().enumerate() {
+            assert_eq!(status(headers), *expected, "case {i}");
+        }
+
+        let response = respond(Method::HEAD, &[(inm.clone(), etag.as_bytes())]);
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[header::ETAG], etag.as_str());
+        assert_eq!(response.headers()[header::LAST_MODIFIED], modified);
+        assert!(!response.headers().contains_key(header::CONTENT_LENGTH));
+        let response = respond(Method::GET, &[(ims, b"yesterday")]);
+        assert_eq!(body(response).await, b"<html></html>");
+    }
+
+    #[tokio::test]
+    async fn range_requests() {
+        let service = service("http_range_requests");
+        let ranged = |range: &str| {
+            Request::get("/index.html")
+                .header(header::RANGE, range)
+                .body(())
+                .unwrap()
+        };
+        for (range, content_range, expected) in [
+            ("bytes=0-5", "bytes 0-5/13", &b"<html>"[..]),
+            ("bytes=6-", "bytes 6-12/13", b"</html>"),
+            ("bytes=6-100", "bytes 6-12/13", b"</html>"),
+            ("bytes=-2", "bytes 11-12/13", b"l>"),
+            ("bytes=-100", "bytes 0-12/13", b"<html></html>"),
+        ] {
+            let response = service.respond(&ranged(range));
+            assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT, "{range}");
+            assert_eq!(response.headers()[header::CONTENT_RANGE], content_range);
+            let len = expected.len().to_string();
+            assert_eq!(response.headers()[header::CONTENT_LENGTH], len.as_str());
+            assert_eq!(body(response).await, expected, "{range}");
+        }
+
+        for range in ["bytes=13-", "bytes=100-200", "bytes=-0"] {
+            let response = service.respond(&ranged(range));
+            assert_eq!(
+                response.status(),
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                "{range}"
+            );
+            assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes */13");
+        }
+
+        for range in ["bytes=0-1,4-5", "bytes=5-1", "items=0-1", "bytes=x-"] {
+            let response = service.respond(&ranged(range));
+            assert_eq!(response.status(), StatusCode::OK, "{range}");
+            assert_eq!(body(response).await, b"<html></html>");
+        }
+    }
+
+    #[test]
+    fn negotiate() {
+        let variant = |encoding, offset| AssetVariant {
+            offset,
+            len: 1,
+            encoding,
+        };
+        let asset = Asset {
+            offset: 16,
+            len: 10,
+            encoding: Some(Encoding::Zstd),
+            variants: vec![variant(Encoding::Gzip, 26), variant(Encoding::Brotli, 27)],
+            ..Default::default()
+        };
+        let pick = |accept| negotiate_encoding(&asset, accept).map(|s| (s.encoding, s.offset));
+        assert_eq!(pick("gzip, br, zstd"), Some((Encoding::Brotli, 27)));
+        assert_eq!(pick("gzip;q=1.0, br;q=0.5"), Some((Encoding::Gzip, 26)));
+        assert_eq!(pick("zstd"), Some((Encoding::Zstd, 16)));
+        assert_eq!(pick("x-gzip"), Some((Encoding::Gzip, 26)));
+        assert_eq!(pick("*;q=0.1, br;q=0"), Some((Encoding::Zstd, 16)));
+        assert_eq!(pick("BR;Q=NaN, gzip;q=0.2"), Some((Encoding::Gzip, 26)));
+        assert_eq!(pick("identity"), None);
+        assert_eq!(pick(""), None);
+        assert_eq!(negotiate_encoding(&Asset::default(), "br"), None);
+
+        assert_eq!(encoded_etag(r#""abc""#, Encoding::Brotli), r#""abc-br""#);
+        assert_eq!(
+            encoded_etag(r#"W/"abc""#, Encoding::Gzip),
+            r#"W/"abc-gzip""#
+        );
+    }
+
+    #[cfg(all(feature = "gzip", feature = "brotli"))]
+    #[tokio::test]
+    async fn serve_precompressed() {
+        let root = fixture_dir("http_serve_precompressed");
+        let html = "<html>".repeat(100);
+        write_file(&root.join("src/index.html"), &html);
+        let (blob, index) = (root.join("test.blob"), root.join("test.blob.idx"));
+        crate::ArchiveBuilder::new()
+            .source(root.join("src"))
+            .blob(&blob)
+            .index(&index)
+            .precompressed([Encoding::Gzip, Encoding::Brotli])
+            .build()
+            .unwrap();
+        let service = ArchiveService::new(Archive::open(&blob, &index).unwrap());
+        let request = |accept: &str| {
+            Request::get("/index.html")
+                .header(header::ACCEPT_ENCODING, accept)
+                .body(())
+                .unwrap()
+        };
+
+        let response = service.respond(&request("gzip, deflate, br"));
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "br");
+        assert_eq!(response.headers()[header::VARY], "accept-encoding");
+        let brotli_etag = response.headers()[header::ETAG].clone();
+        let encoded = body(response).await;
+        assert!(encoded.len() < html.len());
+        assert_eq!(Encoding::Brotli.decode(&encoded).unwrap(), html.as_bytes());
+
+        let response = service.respond(&request("gzip"));
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_ne!(response.headers()[header::ETAG], brotli_etag);
+
+        let response = service.respond(&request("identity"));
+        assert!(response.headers().get(header::CONTENT_ENCODING).is_none());
+        assert_eq!(response.headers()[header::VARY], "accept-encoding");
+        assert_eq!(body(response).await, html.as_bytes());
+    }
+
+    #[tokio::test]
+    async fn router() {
+        let router = service("http_router").into_router();
+        let request = Request::builder()
+            .uri("/docs/")
+            .body(Body::empty())
+            .unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/html");
+        assert_eq!(body(response).await, b"<p>docs</p>");
+    }
+
+    #[test]
+    fn decode_request_paths() {
+        assert_eq!(percent_decode("/a%20b%2Fc").as_deref(), Some("/a b/c"));
+        assert_eq!(percent_decode("/100%").as_deref(), Some("/100%"));
+        assert_eq!(percent_decode("/%zz%4").as_deref(), Some("/%zz%4"));
+        assert_eq!(percent_decode("/%E2%9C%93").as_deref(), Some("/\u{2713}"));
+        assert_eq!(percent_decode("/%ff"), None);
+    }
+}
@@ -1,168 +1,2374 @@
-use anyhow::{bail, Result};
-use std::collections::HashMap;
+mod archive;
+#[cfg(feature = "tokio")]
+mod async_archive;
+#[cfg(feature = "std-fs")]
+mod atomic;
+#[cfg(feature = "std-fs")]
+pub mod build;
+#[cfg(feature = "std-fs")]
+mod builder;
+mod cache;
+#[cfg(feature = "encryption")]
+mod crypto;
+#[cfg(feature = "std-fs")]
+mod edit;
+mod encoding;
+mod error;
+#[cfg(feature = "std-fs")]
+mod extract;
+#[cfg(feature = "ffi")]
+mod ffi;
+mod filter;
+mod format;
+#[cfg(feature = "axum")]
+mod http;
+#[cfg(feature = "serde")]
+mod manifest;
+mod mime;
+mod overlay;
+mod packer;
+mod path;
+mod progress;
+mod query;
+mod read_at;
+#[cfg(feature = "std-fs")]
+mod shard;
+mod sorted;
+mod swap;
+#[cfg(all(test, feature = "std-fs"))]
+mod test_util;
+mod verify;
+
+pub use archive::Archive;
+#[cfg(feature = "tokio")]
+pub use async_archive::AsyncAssetStream;
+#[cfg(feature = "std-fs")]
+pub use builder::ArchiveBuilder;
+pub use cache::CacheStats;
+#[cfg(feature = "encryption")]
+pub use crypto::EncryptionKey;
+pub use encoding::Encoding;
+pub use error::{IndexLineProblem, MonolithicaError, Result};
+#[cfg(feature = "ffi")]
+pub use ffi::{
+    monolithica_indexer_free, monolithica_indexer_open, monolithica_locate, AssetInfo, LookupStatus,
+};
+#[cfg(feature = "axum")]
+pub use http::{ArchiveService, CachePolicy};
+#[cfg(feature = "serde")]
+pub use manifest::{Manifest, ManifestEntry};
+pub use mime::MimeGuesser;
+pub use overlay::OverlayArchive;
+pub use path::{looks_like_file, normalize_path};
+pub use progress::ProgressEvent;
+pub use read_at::ReadAt;
+#[cfg(feature = "std-fs")]
+pub use shard::shard_path;
+pub use sorted::SortedIndex;
+pub use swap::SwappableArchive;
+pub use verify::{VerifyProblem, VerifyReport};
+
+#[cfg(feature = "std-fs")]
+use atomic::{Outputs, TempPath};
+#[cfg(feature = "std-fs")]
+use error::IoContext;
+#[cfg(feature = "std-fs")]
+use format::{BlobChecksum, Footer};
+use format::{Header, HEADER_LEN};
+use packer::{NextShard, Packer, Source};
+use progress::Progress;
+use read_at::read_region_at;
+#[cfg(feature = "std-fs")]
+use sha2::{Digest, Sha256};
+use std::{borrow::Cow, collections::HashMap, convert::Infallible, sync::OnceLock};
+#[cfg(feature = "std-fs")]
 use std::{
     fs::{self, File},
-    io::{Read, Write},
+    io::{self, BufReader},
+};
+use std::{
+    io::{BufRead, Cursor, Read, Seek, SeekFrom, Write},
     path::Path,
 };
 
+/// Encoding of the index written next to the blob.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IndexFormat {
+    /// One `path//offset//len//mime` line per asset, `-` for an unknown mime.
+    #[default]
+    Text,
+    /// Length-prefixed records with a shared mime table, faster to load for
+    /// large archives, see [`AssetIndexer::from_binary`].
+    Binary,
+    /// A JSON array of assets with their paths, for tools outside of Rust, see
+    /// [`AssetIndexer::from_json`].
+    #[cfg(feature = "serde")]
+    Json,
+}
+
+/// Options for [`AssetArchiver::create_archive_with_options`].
+#[derive(Debug, Clone, Default)]
+pub struct ArchiveOptions {
+    pub index_format: IndexFormat,
+    /// Encodes each file of a compressible mime, see
+    /// [`ArchiveOptions::compressible_mimes`], before storing it. Other files,
+    /// and those that don't shrink, are stored raw.
+    pub compression: Option<Encoding>,
+    /// Encodes the whole blob instead of each file, which compresses many small
+    /// similar files better. The blob is decoded into memory when opened.
+    pub blob_compression: Option<Encoding>,
+    /// Additionally stores each asset of a compressible mime encoded with each
+    /// of these encodings, as variants to serve to clients that accept them.
+    /// Variants that don't shrink the asset are skipped.
+    pub precompressed: Vec<Encoding>,
+    /// Mimes worth compressing, matched ignoring parameters, in place of
+    /// [`COMPRESSIBLE_MIMES`]. Already compressed formats such as images, fonts
+    /// and videos would only grow, as would files of unknown type, which are
+    /// never compressed.
+    pub compressible_mimes: Option<Vec<String>>,
+    /// Glob patterns of the archive-relative paths to archive, all files if
+    /// empty. `*` doesn't cross `/`, `**` does, so `**/*.js` selects every
+    /// javascript file.
+    pub include: Vec<String>,
+    /// Glob patterns of paths to leave out even if they are included, such as
+    /// `node_modules/**` or `**/*.map`.
+    pub exclude: Vec<String>,
+    /// Directory removed from the start of each archive path, e.g. `dist` to
+    /// store `dist/index.html` as `index.html`. Every archived file has to be
+    /// under it, exclude the others. Applied after `include` and `exclude`,
+    /// which match paths relative to the source directory.
+    pub strip_prefix: Option<String>,
+    /// Directory prepended to each archive path after `strip_prefix`, e.g.
+    /// `static` to store `app.js` as `static/app.js`.
+    pub add_prefix: Option<String>,
+    /// Leaves out what `.gitignore` and `.ignore` files in the source tree
+    /// ignore, as well as `.git` directories.
+    pub ignore_files: bool,
+    /// Additional `.gitignore`-style patterns, relative to the source
+    /// directory, that take precedence over any ignore file.
+    pub ignore_patterns: Vec<String>,
+    /// Leaves out files and directories whose name starts with `.`, such as
+    /// `.DS_Store` or `.git`.
+    pub skip_hidden: bool,
+    /// Size in bytes above which a file is handled as set by `oversized`.
+    pub max_file_size: Option<u64>,
+    pub oversized: OversizedFiles,
+    /// What to do with a file whose archive path, once normalized like
+    /// lookups, is that of a file archived before in the same build, e.g.
+    /// `a\b.txt` and `a/b.txt`. Only one of them could be looked up.
+    pub duplicate_paths: DuplicatePaths,
+    /// Archives what symlinks point to, skipping directory links that lead
+    /// back to one of their own parents. Otherwise symlinks are left out and
+    /// listed in [`ArchiveSummary::symlinks`].
+    pub follow_symlinks: bool,
+    /// Records symlinks that aren't followed as entries holding their target
+    /// rather than any bytes, see [`Asset::symlink`], for
+    /// [`Archive::extract_to`] to recreate. Links whose target is absolute or
+    /// leads out of the archived tree are left out as without it. Filters
+    /// apply to them as to files.
+    pub store_symlinks: bool,
+    /// Walks each directory in name order rather than in the order the file
+    /// system lists it, so the same tree always yields byte-identical blob and
+    /// index files. Costs a sort of each directory's entries.
+    pub deterministic: bool,
+    /// Stores files with the same content only once, the index entries of the
+    /// later ones pointing at the bytes of the first. Each file is hashed as
+    /// it's read and held in memory until known not to be a duplicate, except
+    /// files over 16 MiB, which take a pass of their own to hash.
+    pub dedup: bool,
+    /// Mimes to record for files with these extensions, given without the dot
+    /// and matched ignoring case, over the ones guessed from the extension.
+    pub mime_by_extension: HashMap<String, String>,
+    /// Mimes to record for files whose archive path matches these globs, the
+    /// first match taking precedence over [`ArchiveOptions::mime_by_extension`].
+    pub mime_by_glob: Vec<(String, String)>,
+    /// Guesses the mime of files no override matches, instead of the
+    /// extension table of `mime_guess`. A `None` it returns falls back to
+    /// [`ArchiveOptions::default_mime`].
+    pub mime_guesser: Option<MimeGuesser>,
+    /// The mime recorded for files whose mime isn't known, e.g.
+    /// `application/octet-stream`, so the index has one for every asset.
+    /// Without it they're recorded without a mime, and readers fall back to
+    /// [`DEFAULT_MIME`].
+    pub default_mime: Option<String>,
+    /// Records the modification time of each file in the index, as
+    /// [`Asset::mtime`]. Left out for files whose file system doesn't report
+    /// it.
+    pub mtime: bool,
+    /// Records the Unix permission bits of each file in the index, as
+    /// [`Asset::mode`], for [`Archive::extract_to`] to restore, e.g. to keep
+    /// scripts executable. Left out on other platforms.
+    pub mode: bool,
+    /// Records a short SHA-256 of each file in the index, as
+    /// [`Asset::content_hash`], e.g. to build fingerprinted URLs. Costs hashing
+    /// every file.
+    pub content_hash: bool,
+    /// Writes a [`Manifest`] of the archived files to this path, hashing them
+    /// as with [`ArchiveOptions::content_hash`], which it implies.
+    #[cfg(feature = "serde")]
+    pub manifest: Option<std::path::PathBuf>,
+    /// Reads, hashes and encodes files on the rayon thread pool, including the
+    /// hashes of [`ArchiveOptions::content_hash`] and
+    /// [`ArchiveOptions::dedup`]. The output is the same as without it, files
+    /// being written in the order they're walked. Files to encode are read
+    /// whole, a batch at a time; those stored as is are streamed through the
+    /// hashers, then copied into the blob in a second read.
+    #[cfg(feature = "rayon")]
+    pub parallel: bool,
+    /// Size of the buffer files are copied into the blob through, 64 KiB if
+    /// unset.
+    pub buffer_size: Option<usize>,
+    /// Splits the blob into files of at most this many bytes, header included,
+    /// e.g. to stay under a file size limit. The first shard is written at the
+    /// blob path, the next ones next to it with a `.1`, `.2`, ... suffix, see
+    /// [`shard_path`]. An asset is never split across shards: once it doesn't
+    /// fit in the current one, the next shard is started, so a shard only
+    /// exceeds the limit when it holds a single larger asset. Only supported
+    /// by the functions writing to paths, without `blob_compression`.
+    pub shard_size: Option<u64>,
+    /// Encrypts the bytes stored for each asset with AES-256-GCM under this
+    /// key, each with a random nonce recorded in [`Asset::nonce`], so the
+    /// archive can only be read with [`Archive::open_with_key`]. The path of
+    /// each asset is authenticated along with its bytes, so they don't decrypt
+    /// under another path. Files are read whole, and can't have `precompressed`
+    /// variants or be deduplicated with `dedup`. Paths, mimes and sizes in the
+    /// index are left in the clear.
+    #[cfg(feature = "encryption")]
+    pub encryption_key: Option<EncryptionKey>,
+}
+
+impl ArchiveOptions {
+    /// Whether each file's SHA-256 is computed.
+    pub(crate) fn hashes_content(&self) -> bool {
+        #[cfg(feature = "serde")]
+        if self.manifest.is_some() {
+            return true;
+        }
+        self.content_hash
+    }
+
+    /// The header flags of the blobs written with these options.
+    pub(crate) fn header_flags(&self) -> u8 {
+        #[cfg(feature = "encryption")]
+        if self.encryption_key.is_some() {
+            return format::FLAG_ENCRYPTED;
+        }
+        0
+    }
+}
+
+/// What to do with files larger than [`ArchiveOptions::max_file_size`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OversizedFiles {
+    /// Leave them out with a warning, listing them in
+    /// [`ArchiveSummary::oversized`].
+    #[default]
+    Skip,
+    /// Fail the archive creation, to catch large files sneaking into a build.
+    Error,
+}
+
+/// What to do with files whose path collides with one archived before, see
+/// [`ArchiveOptions::duplicate_paths`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicatePaths {
+    /// Fail the archive creation, naming the path.
+    #[default]
+    Error,
+    /// Keep the first file and leave out the later ones with a warning,
+    /// listing them in [`ArchiveSummary::path_collisions`].
+    Skip,
+}
+
+/// What [`AssetArchiver::append_dir`] does with files at paths the archive
+/// already has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExistingPaths {
+    /// Fail without changing the archive, naming the first such path.
+    #[default]
+    Error,
+    /// Keep the archived asset and leave out the file, listing it in
+    /// [`ArchiveSummary::overridden`].
+    Keep,
+    /// Point the entry at the file. The bytes of the archived asset stay in
+    /// the blob as dead space until the archive is compacted.
+    Replace,
+}
+
+/// The mimes compressed by default, see [`ArchiveOptions::compressible_mimes`]:
+/// html, css, javascript, json, svg, xml and plain text.
+pub const COMPRESSIBLE_MIMES: &[&str] = &[
+    "text/html",
+    "text/css",
+    "text/javascript",
+    "application/javascript",
+    "application/json",
+    "image/svg+xml",
+    "application/xml",
+    "text/xml",
+    "text/plain",
+];
+
+/// Whether assets of `mime` are worth compressing, being in
+/// [`COMPRESSIBLE_MIMES`]. Parameters such as `; charset=utf-8` are ignored.
+pub fn is_compressible_mime(mime: &str) -> bool {
+    COMPRESSIBLE_MIMES.contains(&mime_essence(mime))
+}
+
+/// `mime` without its parameters.
+pub(crate) fn mime_essence(mime: &str) -> &str {
+    mime.split(';').next().unwrap_or_default().trim()
+}
+
+/// What [`AssetArchiver::create_archive`] did, for logging or to check the
+/// archive isn't unexpectedly empty.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ArchiveSummary {
+    /// Number of files archived, not counting their precompressed variants.
+    pub file_count: usize,
+    /// Total size of the archived files before any compression.
+    pub total_bytes: u64,
+    /// Size of the written blob, header included, of all its shards together
+    /// if sharded. For a single-file archive, the size of the whole file.
+    pub blob_size: u64,
+    /// Number of files left out of the archive, ignored directories counting
+    /// as one.
+    pub skipped: usize,
+    /// Archive paths of the files skipped for exceeding
+    /// [`ArchiveOptions::max_file_size`], also counted in `skipped`.
+    pub oversized: Vec<String>,
+    /// Archive paths of the symlinks left out because
+    /// [`ArchiveOptions::follow_symlinks`] is off, and with
+    /// [`ArchiveOptions::store_symlinks`] those whose target can't be stored,
+    /// also counted in `skipped`.
+    pub symlinks: Vec<String>,
+    /// Archive paths of the files left out because a later source of
+    /// [`AssetArchiver::create_archive_from_dirs`] has a file at the same path,
+    /// or because the archive [`AssetArchiver::append_dir`] appends to does,
+    /// also counted in `skipped`.
+    pub overridden: Vec<String>,
+    /// Archive paths of the files left out because of
+    /// [`DuplicatePaths::Skip`], also counted in `skipped`.
+    pub path_collisions: Vec<String>,
+    /// Bytes of dead space removed by [`AssetArchiver::compact_archive`].
+    pub bytes_reclaimed: u64,
+    /// Number of files whose content was already stored, see
+    /// [`ArchiveOptions::dedup`].
+    pub duplicates: usize,
+}
+
 pub struct AssetArchiver {}
 impl AssetArchiver {
+    #[cfg(feature = "std-fs")]
     pub fn create_archive(
-        src_dir: &str,
+        src_dir: impl AsRef<Path>,
+        blob_path: impl AsRef<Path>,
+        blob_index_path: impl AsRef<Path>,
+        overwrite_existing: bool,
+    ) -> Result<ArchiveSummary> {
+        Self::create_archive_with_options(
+            src_dir,
+            blob_path,
+            blob_index_path,
+            overwrite_existing,
+            &ArchiveOptions::default(),
+        )
+    }
+
+    /// Archives the files under `src_dir` into `blob_path` and
+    /// `blob_index_path`, leaving out directories that hold none, see
+    /// [`Archive::extract_to`]. Each file is written next to its destination
+    /// under a `.tmp` suffix and renamed into place once complete, so a failed
+    /// build leaves existing outputs untouched.
+    #[cfg(feature = "std-fs")]
+    pub fn create_archive_with_options(
+        src_dir: impl AsRef<Path>,
+        blob_path: impl AsRef<Path>,
+        blob_index_path: impl AsRef<Path>,
+        overwrite_existing: bool,
+        options: &ArchiveOptions,
+    ) -> Result<ArchiveSummary> {
+        Self::create_archive_from_dirs(
+            &[src_dir.as_ref()],
+            blob_path,
+            blob_index_path,
+            overwrite_existing,
+            options,
+        )
+    }
+
+    /// Same as [`AssetArchiver::create_archive_with_options`], but merges the
+    /// trees of several source directories into one archive, each file's
+    /// archive path being relative to the source it's found in.
+    ///
+    /// Later sources override earlier ones: where several have a file at the
+    /// same path, the archive holds the one of the last source, the others
+    /// being listed in [`ArchiveSummary::overridden`]. Sources are walked
+    /// last first, so their files come in that order in the blob and overridden
+    /// files take no room in it. Filters and ignore patterns apply to each
+    /// source relative to its own root.
+    #[cfg(feature = "std-fs")]
+    pub fn create_archive_from_dirs(
+        src_dirs: &[&Path],
+        blob_path: impl AsRef<Path>,
+        blob_index_path: impl AsRef<Path>,
+        overwrite_existing: bool,
+        options: &ArchiveOptions,
+    ) -> Result<ArchiveSummary> {
+        Self::create_archive_reporting(
+            Source::Dirs(src_dirs, Outputs::default()),
+            blob_path.as_ref(),
+            blob_index_path.as_ref(),
+            overwrite_existing,
+            options,
+            None,
+        )
+    }
+
+    /// Same as [`AssetArchiver::create_archive_with_options`], but archives
+    /// the bytes of each reader of `entries` at its path rather than files
+    /// found on disk, e.g. generated content or assets read from another
+    /// archive. Readers are streamed into the blob unless compressed or
+    /// deduplicated, the mime of each entry is guessed from its path.
+    ///
+    /// Paths are normalized like lookups, see [`normalize_path`], and fail to
+    /// archive if empty, escaping the root or given twice. The options that
+    /// drive the walk of a directory, such as `include`, `exclude`, ignore
+    /// files, size limits or prefixes, don't apply.
+    #[cfg(feature = "std-fs")]
+    pub fn create_archive_from_entries<R: Read>(
+        entries: impl IntoIterator<Item = (String, R)>,
+        blob_path: impl AsRef<Path>,
+        blob_index_path: impl AsRef<Path>,
+        overwrite_existing: bool,
+        options: &ArchiveOptions,
+    ) -> Result<ArchiveSummary> {
+        let mut entries = entries
+            .into_iter()
+            .map(|(path, reader)| (path, Box::new(reader) as Box<dyn Read>));
+        Self::create_archive_reporting(
+            Source::Entries(&mut entries),
+            blob_path.as_ref(),
+            blob_index_path.as_ref(),
+            overwrite_existing,
+            options,
+            None,
+        )
+    }
+
+    /// Archives `source`, telling `progress` about each file.
+    #[cfg(feature = "std-fs")]
+    pub(crate) fn create_archive_reporting(
+        source: Source,
         blob_path: &Path,
         blob_index_path: &Path,
         overwrite_existing: bool,
-    ) -> Result<()> {
+        options: &ArchiveOptions,
+        progress: Option<Progress>,
+    ) -> Result<ArchiveSummary> {
+        let source = source.writing_to(Self::outputs(blob_path, blob_index_path, options));
+        if options.shard_size.is_some() {
+            return Self::create_sharded_archive(
+                source,
+                blob_path,
+                blob_index_path,
+                overwrite_existing,
+                options,
+                progress,
+            );
+        }
         Self::check_path(blob_path, overwrite_existing)?;
         Self::check_path(blob_index_path, overwrite_existing)?;
 
-        let mut blob_file = File::create(blob_path)?;
-        let mut blob_index_file = File::create(blob_index_path)?;
-        let mut offset = 0u64;
-        let src_dir = src_dir.strip_suffix('/').unwrap_or(src_dir);
-
-        Self::concat_files(
-            src_dir,
-            src_dir,
+        let (blob_tmp, blob_index_tmp) = (TempPath::new(blob_path), TempPath::new(blob_index_path));
+        let mut blob_file = File::create(blob_tmp.tmp()).at(blob_tmp.tmp())?;
+        let mut blob_index_file = File::create(blob_index_tmp.tmp()).at(blob_index_tmp.tmp())?;
+        let summary = Self::write_archive_from(
+            source,
             &mut blob_file,
             &mut blob_index_file,
-            &mut offset,
+            options,
+            progress,
         )?;
+        blob_file.sync_all()?;
+        blob_index_file.sync_all()?;
+        drop((blob_file, blob_index_file));
 
-        Ok(())
+        blob_tmp.persist()?;
+        blob_index_tmp.persist()?;
+        Ok(summary)
     }
 
-    fn concat_files(
-        base_dir: &str,
-        src_dir: &str,
-        blob_file: &mut File,
-        blob_index_file: &mut File,
-        offset: &mut u64,
+    /// Same as [`AssetArchiver::create_archive_with_options`], but writes the
+    /// blob and the index to the given sinks, e.g. a [`std::io::Cursor`] to
+    /// build the archive in memory. The blob is written from its current
+    /// position, which it is left at the end of; it has to be seekable since
+    /// the header is only written once all the assets are.
+    #[cfg(feature = "std-fs")]
+    pub fn write_archive<B: Write + Seek, I: Write>(
+        src_dir: impl AsRef<Path>,
+        blob: &mut B,
+        blob_index: &mut I,
+        options: &ArchiveOptions,
+    ) -> Result<ArchiveSummary> {
+        let src_dirs = [src_dir.as_ref()];
+        Self::write_archive_from(
+            Source::Dirs(&src_dirs, Outputs::default()),
+            blob,
+            blob_index,
+            options,
+            None,
+        )
+    }
+
+    /// Builds an archive of the bytes of each entry at its path entirely in
+    /// memory, without touching the filesystem, e.g. for WebAssembly tools
+    /// assembling assets in a browser. Returns the blob and the index, which
+    /// [`Archive::from_vec`] opens. Paths are handled like by
+    /// [`AssetArchiver::create_archive_from_entries`].
+    ///
+    /// The index can't be [`IndexFormat::Binary`], and the `manifest` of the
+    /// `serde` feature can't be set, as it names a file.
+    pub fn build_in_memory(
+        entries: impl IntoIterator<Item = (String, Vec<u8>)>,
+        options: &ArchiveOptions,
+    ) -> Result<(Vec<u8>, String)> {
+        if options.index_format == IndexFormat::Binary {
+            return Err(MonolithicaError::Unsupported(
+                "in-memory archives have a text index".to_owned(),
+            ));
+        }
+        #[cfg(feature = "serde")]
+        if options.manifest.is_some() {
+            return Err(MonolithicaError::Unsupported(
+                "in-memory archives can't write a manifest".to_owned(),
+            ));
+        }
+        let mut entries = entries
+            .into_iter()
+            .map(|(path, bytes)| (path, Box::new(Cursor::new(bytes)) as Box<dyn Read>));
+        let (mut blob, mut index) = (Cursor::new(Vec::new()), Vec::new());
+        Self::write_archive_from(
+            Source::Entries(&mut entries),
+            &mut blob,
+            &mut index,
+            options,
+            None,
+        )?;
+        let index =
+            String::from_utf8(index).map_err(|e| MonolithicaError::InvalidIndex(e.to_string()))?;
+        Ok((blob.into_inner(), index))
+    }
+
+    fn write_archive_from<B: Write + Seek, I: Write>(
+        source: Source,
+        blob: &mut B,
+        blob_index: &mut I,
+        options: &ArchiveOptions,
+        mut progress: Option<Progress>,
+    ) -> Result<ArchiveSummary> {
+        if options.shard_size.is_some() {
+            return Err(MonolithicaError::Unsupported(
+                "sharded blobs can only be written to files".to_owned(),
+            ));
+        }
+        let start = blob.stream_position()?;
+        let (entries, mut summary) = if let Some(compression) = options.blob_compression {
+            let packer = Self::pack(
+                Vec::new(),
+                source,
+                options,
+                progress::reborrow(&mut progress),
+                None,
+            )?;
+
+            Header {
+                flags: options.header_flags(),
+                compression: Some(compression),
+                data_len: packer.blob.len() as u64,
+            }
+            .write(blob)?;
+            blob.write_all(&compression.encode(&packer.blob)?)?;
+            packer.finish()
+        } else {
+            blob.seek(SeekFrom::Start(start + HEADER_LEN))?;
+            let packer = Self::pack(
+                &mut *blob,
+                source,
+                options,
+                progress::reborrow(&mut progress),
+                None,
+            )?;
+            let data_len = packer.offset - HEADER_LEN;
+            let finished = packer.finish();
+
+            blob.seek(SeekFrom::Start(start))?;
+            Header {
+                flags: options.header_flags(),
+                compression: None,
+                data_len,
+            }
+            .write(blob)?;
+            blob.seek(SeekFrom::Start(start + HEADER_LEN + data_len))?;
+            finished
+        };
+        summary.blob_size = blob.stream_position()? - start;
+        blob.flush()?;
+
+        Self::write_index_of(&entries, &summary, blob_index, options, progress)?;
+        Ok(summary)
+    }
+
+    /// Writes the index of `entries` and their manifest if one is asked for,
+    /// then reports the archive as done.
+    fn write_index_of<I: Write>(
+        entries: &[(String, Asset)],
+        summary: &ArchiveSummary,
+        blob_index: &mut I,
+        options: &ArchiveOptions,
+        progress: Option<Progress>,
     ) -> Result<()> {
-        let path_start_pos = base_dir.len() + 1;
-        for entry in fs::read_dir(src_dir)? {
-            let entry = entry?;
-
-            let path = entry.path();
-            if path.is_file() {
-                let mut file = File::open(&path)?;
-                let file_len = file.metadata().unwrap().len();
-
-                let mime = match mime_guess::from_path(&path).first() {
-                    Some(mime) => mime.to_string(),
-                    None => "".to_owned(),
-                };
+        format::write_index(blob_index, entries, options.index_format)?;
+        blob_index.flush()?;
+        #[cfg(all(feature = "serde", feature = "std-fs"))]
+        if let Some(path) = &options.manifest {
+            Manifest::from_entries(entries.iter().map(|(path, asset)| (path.as_str(), asset)))
+                .write(path)?;
+        }
+        if let Some(progress) = progress {
+            progress(&ProgressEvent::Done(summary));
+        }
+        Ok(())
+    }
 
-                writeln!(
-                    blob_index_file,
-                    "{}//{}//{}//{}",
-                    &path.to_path_buf().to_str().unwrap()[path_start_pos..],
-                    offset,
-                    file_len,
-                    mime
-                )?;
-
-                *offset += file_len;
-
-                let mut buffer = [0u8; 8192];
-                loop {
-                    let bytes_read = file.read(&mut buffer)?;
-                    if bytes_read == 0 {
-                        break;
-                    }
-                    blob_file.write_all(&buffer[..bytes_read])?;
+    /// Packs `source` into `blob`, rolling over to new shards with
+    /// `next_shard`.
+    fn pack<'o, 's: 'o, W: Write>(
+        blob: W,
+        source: Source<'s>,
+        options: &'o ArchiveOptions,
+        progress: Option<Progress<'o>>,
+        next_shard: Option<NextShard<'o, W>>,
+    ) -> Result<Packer<'o, W>> {
+        let base_dir = match &source {
+            #[cfg(feature = "std-fs")]
+            Source::Dirs(src_dirs, _) => {
+                let Some(last) = src_dirs.last() else {
+                    tracing::error!("no source directory");
+                    return Err(MonolithicaError::InvalidInput(
+                        "no source directory to archive".to_owned(),
+                    ));
+                };
+                *last
+            }
+            Source::Entries(_) => Path::new(""),
+        };
+        let mut packer = Packer::new(blob, base_dir, options)?;
+        packer.progress = progress;
+        packer.next_shard = next_shard;
+        match source {
+            #[cfg(feature = "std-fs")]
+            Source::Dirs(src_dirs, outputs) => {
+                packer.outputs = outputs;
+                packer.pack()?;
+                for src_dir in src_dirs.iter().rev().skip(1) {
+                    packer.next_source(src_dir)?;
+                    packer.pack()?;
                 }
-            } else if path.is_dir() {
-                Self::concat_files(
-                    base_dir,
-                    path.to_str().unwrap(),
-                    blob_file,
-                    blob_index_file,
-                    offset,
-                )?;
             }
+            Source::Entries(entries) => packer.pack_entries(entries)?,
         }
+        Ok(packer)
+    }
 
-        Ok(())
+    /// Same as [`AssetArchiver::create_archive`], but writes the blob and the
+    /// index into a single file that can be opened with
+    /// [`Archive::open_single`]. The file ends with a SHA-256 of its content,
+    /// see [`Archive::verify_checksum`].
+    #[cfg(feature = "std-fs")]
+    pub fn create_single_archive(
+        src_dir: impl AsRef<Path>,
+        archive_path: impl AsRef<Path>,
+        overwrite_existing: bool,
+    ) -> Result<ArchiveSummary> {
+        let (src_dir, archive_path) = (src_dir.as_ref(), archive_path.as_ref());
+        Self::check_path(archive_path, overwrite_existing)?;
+
+        let archive_tmp = TempPath::new(archive_path);
+        // Read back to compute the checksum.
+        let mut archive_file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(archive_tmp.tmp())
+            .at(archive_tmp.tmp())?;
+        archive_file.seek(SeekFrom::Start(HEADER_LEN))?;
+        let options = ArchiveOptions::default();
+        let mut packer = Packer::new(&mut archive_file, src_dir, &options)?;
+        packer.outputs = Outputs::new(&[archive_path]);
+        packer.pack()?;
+        let offset = packer.offset;
+        let (entries, mut summary) = packer.finish();
+
+        let mut index = Vec::new();
+        format::write_index(&mut index, &entries, IndexFormat::Text)?;
+        archive_file.write_all(&index)?;
+
+        archive_file.seek(SeekFrom::Start(0))?;
+        Header {
+            flags: 0,
+            compression: None,
+            data_len: offset - HEADER_LEN,
+        }
+        .write(&mut archive_file)?;
+
+        archive_file.seek(SeekFrom::Start(0))?;
+        let mut hasher = Sha256::new();
+        let index_end = offset + index.len() as u64;
+        io::copy(&mut (&mut archive_file).take(index_end), &mut hasher)?;
+        BlobChecksum::sha256(&hasher.finalize()).write(&mut archive_file)?;
+        Footer {
+            index_offset: offset,
+            index_len: index.len() as u64,
+        }
+        .write(&mut archive_file)?;
+        summary.blob_size = archive_file.metadata()?.len();
+        archive_file.sync_all()?;
+        drop(archive_file);
+
+        archive_tmp.persist()?;
+        Ok(summary)
+    }
+
+    /// The files written for an archive at `blob_path` and `blob_index_path`.
+    #[cfg(feature = "std-fs")]
+    fn outputs(blob_path: &Path, blob_index_path: &Path, options: &ArchiveOptions) -> Outputs {
+        #[cfg_attr(not(feature = "serde"), allow(unused_mut))]
+        let mut paths = vec![blob_path, blob_index_path];
+        #[cfg(feature = "serde")]
+        paths.extend(options.manifest.as_deref());
+        let outputs = Outputs::new(&paths);
+        match options.shard_size {
+            Some(_) => outputs.sharded(blob_path),
+            None => outputs,
+        }
     }
 
+    /// Checks that an output can be written to `blob_path`. An existing file
+    /// is only replaced once the new one is complete.
+    #[cfg(feature = "std-fs")]
     fn check_path(blob_path: &Path, overwrite_existing: bool) -> Result<()> {
         if blob_path.is_file() || blob_path.is_symlink() {
             if !overwrite_existing {
                 tracing::error!("file already exists");
-                bail!("file already exists");
+                return Err(MonolithicaError::FileExists(blob_path.to_owned()));
             }
-            std::fs::remove_file(blob_path)?;
+            return Ok(());
         }
 
         if blob_path.exists() {
             tracing::error!("path exists but not a file");
-            bail!("path exists but not a file");
+            return Err(MonolithicaError::NotAFile(blob_path.to_owned()));
         }
 
         Ok(())
     }
 }
 
+/// Mime to serve assets whose type is unknown with.
+pub const DEFAULT_MIME: &str = "application/octet-stream";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct Asset {
     pub offset: u64,
+    /// Number of bytes stored in the blob.
     pub len: u64,
-    pub mime: String,
+    /// `None` if the type couldn't be guessed when the archive was created.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub mime: Option<String>,
+    /// `None` if the asset is stored raw.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub encoding: Option<Encoding>,
+    /// Length of the asset once decrypted and decoded, same as `len` for raw
+    /// assets that aren't encrypted.
+    pub original_len: u64,
+    /// Precompressed copies of the asset, see [`ArchiveOptions::precompressed`].
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub variants: Vec<AssetVariant>,
+    /// CRC32 of the decoded bytes, `None` for indexes written without it.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub crc32: Option<u32>,
+    /// Last modification time of the file, in seconds since the Unix epoch,
+    /// see [`ArchiveOptions::mtime`].
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub mtime: Option<u64>,
+    /// Unix permission bits of the file, such as `0o755`, see
+    /// [`ArchiveOptions::mode`].
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub mode: Option<u32>,
+    /// The target of a symlink entry, relative to the entry's directory with
+    /// `/` separators, see [`ArchiveOptions::store_symlinks`]. Such entries
+    /// have no bytes, reading them yields nothing.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub symlink: Option<String>,
+    /// First 8 bytes of the SHA-256 of the decoded bytes in hex, see
+    /// [`ArchiveOptions::content_hash`].
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub content_hash: Option<String>,
+    /// Shard of the blob holding the asset and its variants, `0` for the
+    /// first, see [`ArchiveOptions::shard_size`]. Offsets are into that
+    /// shard's file.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "is_first_shard"))]
+    pub shard: u32,
+    /// The AES-GCM nonce the bytes of the asset were encrypted with, followed
+    /// in the blob by their authentication tag, see the `encryption_key` of
+    /// the `encryption` feature. `None` if they aren't encrypted, or for an
+    /// asset without bytes.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub nonce: Option<[u8; 12]>,
+}
+
+#[cfg(feature = "serde")]
+fn is_first_shard(shard: &u32) -> bool {
+    *shard == 0
+}
+
+/// An encoded copy of an asset stored alongside it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AssetVariant {
+    pub offset: u64,
+    pub len: u64,
+    pub encoding: Encoding,
+}
+
+impl Asset {
+    pub fn is_compressed(&self) -> bool {
+        self.encoding.is_some()
+    }
+
+    /// Decodes the bytes of the asset as stored in the blob.
+    pub(crate) fn decode(&self, stored: Vec<u8>) -> Result<Vec<u8>> {
+        match self.encoding {
+            Some(encoding) => encoding.decode(&stored),
+            None => Ok(stored),
+        }
+    }
+
+    pub fn variant(&self, encoding: Encoding) -> Option<&AssetVariant> {
+        self.variants
+            .iter()
+            .find(|variant| variant.encoding == encoding)
+    }
+
+    /// The recorded mime, falling back to [`DEFAULT_MIME`].
+    pub fn mime_or_default(&self) -> &str {
+        self.mime.as_deref().unwrap_or(DEFAULT_MIME)
+    }
+
+    /// An HTTP entity tag for the decoded asset, quotes included. Strong if
+    /// the index has a checksum of the asset, otherwise a weak tag made of its
+    /// position in the blob, which changes when the archive is rebuilt.
+    pub fn etag(&self) -> String {
+        match self.crc32 {
+            Some(crc32) => format!("\"{crc32:08x}-{:x}\"", self.original_len),
+            None => format!("W/\"{:x}-{:x}\"", self.offset, self.original_len),
+        }
+    }
 }
 
-type AssetPath<'a> = &'a str;
-type AssetMap<'a> = HashMap<AssetPath<'a>, Asset>;
+pub(crate) type AssetPath<'a> = Cow<'a, str>;
+pub(crate) type AssetMap<'a> = HashMap<AssetPath<'a>, Asset>;
+
+/// An [`AssetIndexer`] owning its paths, so it can outlive the index content,
+/// see [`AssetIndexer::into_owned`] and [`AssetIndexer::read`].
+pub type OwnedAssetIndexer = AssetIndexer<'static>;
 
 pub struct AssetIndexer<'a> {
     asset_map: AssetMap<'a>,
+    /// Lowercased path to original path, built on the first case-insensitive
+    /// lookup.
+    lowercase: OnceLock<HashMap<String, String>>,
+    /// See [`AssetIndexer::nth`] and [`AssetIndexer::list_prefix`].
+    sorted: OnceLock<Vec<String>>,
 }
 
-impl<'a> AssetIndexer<'a> {
-    pub fn new(content: &'a str) -> Self {
+impl OwnedAssetIndexer {
+    /// Reads the index file at `path`, in any format, see
+    /// [`AssetIndexer::from_bytes`].
+    #[cfg(feature = "std-fs")]
+    pub fn read(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        Self::from_reader(BufReader::new(File::open(path).at(path)?))
+    }
+
+    /// Parses a text index a line at a time as it's read from `reader`, rather
+    /// than reading it whole first, so only one line is held besides the
+    /// entries. Binary and JSON indexes are read whole, then parsed.
+    pub fn from_reader(mut reader: impl BufRead) -> Result<Self> {
+        let start = reader.fill_buf()?;
+        if start.starts_with(format::BINARY_INDEX_MAGIC) || format::is_json_index(start) {
+            let mut content = Vec::new();
+            reader.read_to_end(&mut content)?;
+            return Ok(AssetIndexer::from_bytes(&content)?.into_owned());
+        }
+
         let mut asset_map = HashMap::new();
-        for line in content.lines() {
-            let fields: Vec<&str> = line.split("//").collect();
+        let mut line = String::new();
+        for line_no in 0.. {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                break;
+            }
+            let content = line.strip_suffix('\n').unwrap_or(&line);
+            if content.trim().is_empty() {
+                continue;
+            }
 
-            let path = fields[0];
-            let asset = Asset {
-                offset: fields[1].parse().unwrap(),
-                len: fields[2].parse().unwrap(),
-                mime: fields[3].parse().unwrap(),
-            };
+            let path = format::parse_index_line(content)
+                .and_then(|record| {
+                    let path = Cow::Owned(record.path.into_owned());
+                    format::IndexRecord { path, ..record }.insert_into(&mut asset_map)
+                })
+                .map_err(|e| format::at_line(line_no, e))?;
 
             tracing::debug!("asset: {path}");
+        }
+
+        Ok(Self {
+            asset_map,
+            lowercase: OnceLock::new(),
+            sorted: OnceLock::new(),
+        })
+    }
+}
+
+impl<'a> AssetIndexer<'a> {
+    /// Parses the index written by [`AssetArchiver::create_archive`], failing
+    /// with the offending line number on malformed lines. Blank lines are
+    /// skipped.
+    ///
+    /// Paths are escaped when written, so any path produced by
+    /// [`AssetArchiver::create_archive`] is located by exactly the same string.
+    pub fn parse(content: &'a str) -> Result<Self> {
+        Self::parse_lines(content, Err)
+    }
 
-            asset_map.insert(path, asset);
+    /// Like [`AssetIndexer::parse`], but skips malformed lines rather than
+    /// failing, returning them as [`MonolithicaError::IndexLine`] warnings
+    /// alongside the entries of the others, e.g. to recover what's left of a
+    /// corrupted index.
+    pub fn parse_lenient(content: &'a str) -> (Self, Vec<MonolithicaError>) {
+        let mut warnings = Vec::new();
+        let Ok(indexer) = Self::parse_lines(content, |warning| {
+            tracing::warn!("skipping {warning}");
+            warnings.push(warning);
+            Ok::<_, Infallible>(())
+        });
+        (indexer, warnings)
+    }
+
+    /// Parses a text index, handing the error of each malformed line to
+    /// `on_error`, which decides whether to go on.
+    fn parse_lines<E>(
+        content: &'a str,
+        mut on_error: impl FnMut(MonolithicaError) -> Result<(), E>,
+    ) -> Result<Self, E> {
+        let mut asset_map = HashMap::new();
+        for (line_no, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match format::parse_index_line(line)
+                .and_then(|record| record.insert_into(&mut asset_map))
+            {
+                Ok(path) => tracing::debug!("asset: {path}"),
+                Err(problem) => on_error(format::at_line(line_no, problem))?,
+            }
+        }
+
+        Ok(Self {
+            asset_map,
+            lowercase: OnceLock::new(),
+            sorted: OnceLock::new(),
+        })
+    }
+
+    /// Loads an index written with [`IndexFormat::Binary`], borrowing the paths
+    /// from `content`.
+    pub fn from_binary(content: &'a [u8]) -> Result<Self> {
+        let mut asset_map = HashMap::new();
+        for (i, record) in format::parse_binary_index(content)?.into_iter().enumerate() {
+            record
+                .insert_into(&mut asset_map)
+                .map_err(|e| MonolithicaError::InvalidIndex(format!("entry {i}: {e}")))?;
+        }
+        Ok(Self {
+            asset_map,
+            lowercase: OnceLock::new(),
+            sorted: OnceLock::new(),
+        })
+    }
+
+    /// Loads an index written with [`IndexFormat::Json`].
+    #[cfg(feature = "serde")]
+    pub fn from_json(content: &'a str) -> Result<Self> {
+        let mut asset_map = HashMap::new();
+        let entries = serde_json::from_str::<Vec<format::JsonEntry<'a>>>(content)
+            .map_err(|e| MonolithicaError::InvalidIndex(format!("invalid JSON index: {e}")))?;
+        for entry in entries {
+            asset_map.insert(entry.path, entry.asset);
+        }
+        Ok(Self {
+            asset_map,
+            lowercase: OnceLock::new(),
+            sorted: OnceLock::new(),
+        })
+    }
+
+    /// Loads an index in any format, telling them apart by the binary magic
+    /// and the leading `[` of JSON.
+    pub fn from_bytes(content: &'a [u8]) -> Result<Self> {
+        let text = || {
+            std::str::from_utf8(content).map_err(|e| {
+                MonolithicaError::InvalidIndex(format!("index is not valid UTF-8: {e}"))
+            })
+        };
+        if content.starts_with(format::BINARY_INDEX_MAGIC) {
+            Self::from_binary(content)
+        } else if format::is_json_index(content) {
+            Self::parse_json_index(text()?)
+        } else {
+            Self::parse(text()?)
         }
+    }
 
-        Self { asset_map }
+    #[cfg(feature = "serde")]
+    fn parse_json_index(content: &'a str) -> Result<Self> {
+        Self::from_json(content)
+    }
+
+    #[cfg(not(feature = "serde"))]
+    fn parse_json_index(_: &'a str) -> Result<Self> {
+        Err(MonolithicaError::Unsupported(
+            "JSON index support is not enabled".to_owned(),
+        ))
+    }
+
+    /// Copies the borrowed paths, so the indexer no longer borrows the index
+    /// content and can be kept around, e.g. in application state behind an
+    /// [`std::sync::Arc`].
+    pub fn into_owned(self) -> OwnedAssetIndexer {
+        AssetIndexer {
+            asset_map: self
+                .asset_map
+                .into_iter()
+                .map(|(path, asset)| (Cow::Owned(path.into_owned()), asset))
+                .collect(),
+            lowercase: self.lowercase,
+            sorted: self.sorted,
+        }
     }
 
     pub fn locate_asset(&self, path: &str) -> Option<&Asset> {
-        Some(self.asset_map.get(path)?)
+        self.asset_map.get(path)
+    }
+
+    /// Like [`AssetIndexer::locate_asset`], but normalizes `path` first, so
+    /// `/index.html` or `.\index.html` find `index.html`. Paths that would
+    /// escape the archive root find nothing, see [`normalize_path`].
+    pub fn locate_normalized(&self, path: &str) -> Option<&Asset> {
+        self.asset_map.get(normalize_path(path)?.as_ref())
+    }
+
+    /// Looks up the asset a request path such as `/js/./app.js` or
+    /// `js\app.js` names, normalized as by [`AssetIndexer::locate_normalized`],
+    /// along with the path it's stored under. Paths with a `..` segment find
+    /// nothing, even one that would stay within the archive, so a traversal
+    /// attempt can't reach any asset. The one lookup to use for paths taken
+    /// from requests.
+    pub fn resolve(&self, request_path: &str) -> Option<(&str, &Asset)> {
+        let (path, asset) = self
+            .asset_map
+            .get_key_value(normalize_path(request_path)?.as_ref())?;
+        Some((path.as_ref(), asset))
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.asset_map.contains_key(path)
+    }
+
+    /// Like [`AssetIndexer::locate_asset`], but ignores ASCII and Unicode case.
+    ///
+    /// If several paths differ only in case, the first one in the index (the
+    /// one stored earliest in the blob) wins.
+    pub fn locate_asset_ci(&self, path: &str) -> Option<&Asset> {
+        let lowercase = self.lowercase.get_or_init(|| lowercase_paths(self.iter()));
+        let original = lowercase.get(&path.to_lowercase())?;
+        self.asset_map.get(original.as_str())
+    }
+
+    /// The entry at position `i` in path order, the same for any index of the
+    /// same paths whatever its format, e.g. to page through the archive. The
+    /// sorted paths are kept after the first call.
+    pub fn nth(&self, i: usize) -> Option<(&str, &Asset)> {
+        let (path, asset) = self
+            .asset_map
+            .get_key_value(self.sorted().get(i)?.as_str())?;
+        Some((path.as_ref(), asset))
+    }
+
+    /// The paths, sorted on the first call rather than on parsing so lookups
+    /// don't pay for it.
+    fn sorted(&self) -> &[String] {
+        self.sorted
+            .get_or_init(|| query::sorted_paths(self.paths()))
+    }
+
+    /// Iterates over all entries in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Asset)> {
+        self.asset_map
+            .iter()
+            .map(|(path, asset)| (path.as_ref(), asset))
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.asset_map.keys().map(|path| path.as_ref())
+    }
+
+    /// The paths of the assets under the directory `prefix`, at any depth,
+    /// sorted. `js` and `js/` both list `js/app.js` but not `json/data.json`,
+    /// an empty prefix lists everything. The first listing sorts the paths,
+    /// as [`AssetIndexer::nth`] does, the next ones binary search them.
+    pub fn list_prefix(&self, prefix: &str) -> Vec<&str> {
+        query::list_prefix(self.sorted(), prefix)
+    }
+
+    /// The names of the files and directories right under the directory
+    /// `prefix`, sorted, directories with a trailing `/`: `js` lists `app.js`
+    /// and `lib/` for `js/app.js` and `js/lib/util.js`. Only goes through the
+    /// paths under `prefix`, see [`AssetIndexer::list_prefix`].
+    pub fn list_dir(&self, prefix: &str) -> Vec<&str> {
+        query::list_sorted_dir(self.sorted(), prefix)
+    }
+
+    /// The assets whose path matches the glob `pattern`, sorted by path. As in
+    /// [`ArchiveOptions::include`], patterns are matched against the
+    /// `/`-separated archive paths, `*` within one segment and `**` across
+    /// segments, so `**/*.css` finds every stylesheet.
+    pub fn find(&self, pattern: &str) -> Result<Vec<(&str, &Asset)>> {
+        query::find(self.iter(), pattern)
+    }
+
+    /// The bytes the assets take in the blob, as stored, so compressed assets
+    /// count for their compressed size. Precompressed variants aren't counted.
+    pub fn total_size(&self) -> u64 {
+        query::total_size(self.iter())
+    }
+
+    /// The bytes, as in [`AssetIndexer::total_size`], and number of assets of
+    /// each mime, unknown ones counted under [`DEFAULT_MIME`], e.g. to keep
+    /// the javascript of a bundle under a budget.
+    pub fn size_by_mime(&self) -> HashMap<String, (u64, usize)> {
+        query::size_by_mime(self.iter())
+    }
+
+    pub fn len(&self) -> usize {
+        self.asset_map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.asset_map.is_empty()
+    }
+
+    /// Reads the bytes of `asset` from `blob`, failing if the blob ends before
+    /// the asset does.
+    pub fn read_asset<R: Read + Seek>(&self, blob: &mut R, asset: &Asset) -> Result<Vec<u8>> {
+        read_region(blob, asset.offset, asset.len)
+    }
+
+    /// Like [`AssetIndexer::read_asset`], from a blob that can be read from
+    /// several threads at once.
+    pub fn read_asset_at<R: ReadAt + ?Sized>(&self, blob: &R, asset: &Asset) -> Result<Vec<u8>> {
+        read_region_at(blob, asset.offset, asset.len)
+    }
+
+    /// Reads the bytes `start..end` of `asset` from `blob`, with `end` clamped to
+    /// the asset length and `None` meaning the rest of the asset.
+    pub fn read_asset_range<R: Read + Seek>(
+        &self,
+        blob: &mut R,
+        asset: &Asset,
+        start: u64,
+        end: Option<u64>,
+    ) -> Result<Vec<u8>> {
+        let end = end.map_or(asset.len, |end| end.min(asset.len));
+        if start > asset.len {
+            return Err(MonolithicaError::InvalidInput(format!(
+                "range start {start} is past the asset length {}",
+                asset.len
+            )));
+        }
+        if start > end {
+            return Err(MonolithicaError::InvalidInput(format!(
+                "range start {start} is past the range end {end}"
+            )));
+        }
+
+        read_region(blob, asset.offset + start, end - start)
+    }
+
+    /// Re-reads `asset` from `blob` and checks its bytes against the recorded
+    /// checksum, failing if the asset has none.
+    pub fn verify_asset<R: Read + Seek>(&self, blob: &mut R, asset: &Asset) -> Result<bool> {
+        let Some(crc32) = asset.crc32 else {
+            return Err(MonolithicaError::InvalidInput(
+                "asset has no checksum".to_owned(),
+            ));
+        };
+
+        let bytes = asset.decode(self.read_asset(blob, asset)?)?;
+        Ok(crc32fast::hash(&bytes) == crc32)
+    }
+
+    /// Locates `path` and reads its bytes from `blob`, `Ok(None)` if there is
+    /// no such asset.
+    pub fn read_asset_by_path<R: Read + Seek>(
+        &self,
+        blob: &mut R,
+        path: &str,
+    ) -> Result<Option<Vec<u8>>> {
+        match self.locate_asset(path) {
+            Some(asset) => Ok(Some(self.read_asset(blob, asset)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Maps each lowercased path to its original, preferring the entry stored
+/// earliest in the blob when several collide.
+fn lowercase_paths<'a>(
+    entries: impl Iterator<Item = (&'a str, &'a Asset)>,
+) -> HashMap<String, String> {
+    let mut first: HashMap<String, (u64, &str)> = HashMap::new();
+    for (path, asset) in entries {
+        let slot = first
+            .entry(path.to_lowercase())
+            .or_insert((asset.offset, path));
+        if (asset.offset, path) < *slot {
+            *slot = (asset.offset, path);
+        }
     }
+    first
+        .into_iter()
+        .map(|(lower, (_, path))| (lower, path.to_owned()))
+        .collect()
 }
 
-#[cfg(test)]
+fn read_region<R: Read + Seek>(blob: &mut R, offset: u64, len: u64) -> Result<Vec<u8>> {
+    blob.seek(SeekFrom::Start(offset))?;
+
+    let mut buffer = Vec::new();
+    blob.take(len).read_to_end(&mut buffer)?;
+    if (buffer.len() as u64) < len {
+        tracing::error!("blob too short, expected {len} bytes at offset {offset}");
+        return Err(MonolithicaError::BlobTooShort {
+            offset,
+            len,
+            available: buffer.len() as u64,
+        });
+    }
+
+    Ok(buffer)
+}
+
+#[cfg(all(test, feature = "std-fs"))]
 mod tests {
     use super::*;
+    use std::{cell::Cell, fs};
+    use test_util::build_fixture;
 
     #[test]
     fn it_works() {
-        let archive_file = Path::new("target/test.blob");
-        let archive_file_index = Path::new("target/test.blob.idx");
-        AssetArchiver::create_archive("target", archive_file, archive_file_index, true).unwrap();
+        let (archive_file, archive_file_index) = build_fixture("it_works");
 
         let mut file = File::open(archive_file_index).unwrap();
         let mut s = String::new();
         file.read_to_string(&mut s).unwrap();
 
-        let indexer = AssetIndexer::new(&s);
+        let indexer = AssetIndexer::parse(&s).unwrap();
         let asset = indexer.locate_asset(".rustc_info.json");
 
         assert!(asset.is_some());
         assert!(asset.unwrap().len > 0);
-        assert!(asset.unwrap().mime == "application/json");
+        assert!(asset.unwrap().mime.as_deref() == Some("application/json"));
+
+        let mut blob = File::open(archive_file).unwrap();
+        let bytes = indexer.read_asset(&mut blob, asset.unwrap()).unwrap();
+        assert_eq!(bytes, br#"{"rustc":"1.0"}"#);
+    }
+
+    #[test]
+    fn outputs_inside_source() {
+        let root = test_util::fixture_dir("outputs_inside_source");
+        test_util::write_file(&root.join("src/index.html"), "<html></html>");
+        let (blob, index) = (root.join("src/test.blob"), root.join("src/./test.blob.idx"));
+        AssetArchiver::create_archive(root.join("src"), &blob, &index, false).unwrap();
+        // The second build also finds the outputs of the first one, next to
+        // the temporary files it writes.
+        let summary = AssetArchiver::create_archive(root.join("src"), &blob, &index, true).unwrap();
+        assert_eq!((summary.file_count, summary.skipped), (1, 4));
+        let archive = Archive::open(&blob, &index).unwrap();
+        assert_eq!(archive.paths().collect::<Vec<_>>(), ["index.html"]);
+
+        let options = ArchiveOptions {
+            shard_size: Some(HEADER_LEN + 8),
+            ..Default::default()
+        };
+        test_util::write_file(&root.join("src/app.js"), "console.log(1);");
+        test_util::write_file(&root.join("src/test.blob.1.txt"), "not a shard");
+        let summary = AssetArchiver::create_archive_with_options(
+            root.join("src"),
+            &blob,
+            &index,
+            true,
+            &options,
+        )
+        .unwrap();
+        // The blob, the index and whichever shards existed when walked past.
+        assert_eq!(summary.file_count, 3);
+        assert!(summary.skipped >= 2);
+        let archive = Archive::open(&blob, &index).unwrap();
+        assert!(archive.locate_asset("test.blob.1.txt").is_some());
+        assert!(!archive
+            .paths()
+            .any(|path| path.starts_with("test.blob.1") && path != "test.blob.1.txt"));
+        let single = root.join("src/single.blob");
+        AssetArchiver::create_single_archive(root.join("src"), &single, false).unwrap();
+        let archive = Archive::open_single(&single).unwrap();
+        assert!(archive.locate_asset("single.blob").is_none());
+        assert!(archive.locate_asset("single.blob.tmp").is_none());
+    }
+
+    #[test]
+    fn archive_summary() {
+        let root = test_util::fixture_dir("archive_summary");
+        test_util::write_file(&root.join("src/index.html"), "<html></html>");
+        test_util::write_file(&root.join("src/js/app.js"), "console.log(1);");
+        let blob = root.join("test.blob");
+        let summary = AssetArchiver::create_archive(
+            root.join("src"),
+            &blob,
+            root.join("test.blob.idx"),
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            summary,
+            ArchiveSummary {
+                file_count: 2,
+                total_bytes: 13 + 15,
+                blob_size: HEADER_LEN + 13 + 15,
+                skipped: 0,
+                oversized: vec![],
+                symlinks: vec![],
+                overridden: vec![],
+                path_collisions: vec![],
+                bytes_reclaimed: 0,
+                duplicates: 0,
+            }
+        );
+        assert_eq!(fs::metadata(&blob).unwrap().len(), summary.blob_size);
+
+        let single = root.join("test.mono");
+        let summary =
+            AssetArchiver::create_single_archive(root.join("src"), &single, false).unwrap();
+        assert_eq!(summary.file_count, 2);
+        assert_eq!(fs::metadata(&single).unwrap().len(), summary.blob_size);
+    }
+
+    #[test]
+    fn write_archive_in_memory() {
+        let root = test_util::fixture_dir("write_archive_in_memory");
+        test_util::write_file(&root.join("src/index.html"), "<html></html>");
+        let (mut blob, mut index) = (std::io::Cursor::new(Vec::new()), Vec::new());
+        let summary = AssetArchiver::write_archive(
+            root.join("src"),
+            &mut blob,
+            &mut index,
+            &ArchiveOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(summary.blob_size, HEADER_LEN + 13);
+        assert_eq!(blob.position(), summary.blob_size);
+
+        let archive = Archive::from_bytes(blob.into_inner().leak(), index).unwrap();
+        assert_eq!(
+            archive.get("index.html").unwrap().unwrap(),
+            b"<html></html>"
+        );
+    }
+
+    #[test]
+    fn etag() {
+        let mut asset = Asset {
+            offset: 16,
+            len: 10,
+            original_len: 300,
+            ..Default::default()
+        };
+        assert_eq!(asset.etag(), r#"W/"10-12c""#);
+        asset.crc32 = Some(0xbeef);
+        assert_eq!(asset.etag(), r#""0000beef-12c""#);
+    }
+
+    #[test]
+    fn iterate_entries() {
+        let (_, index) = build_fixture("iterate_entries");
+        let content = fs::read_to_string(index).unwrap();
+        let indexer = AssetIndexer::parse(&content).unwrap();
+        assert_eq!(indexer.len(), 3);
+        assert!(!indexer.is_empty());
+
+        let mut paths: Vec<_> = indexer.paths().collect();
+        paths.sort();
+        assert_eq!(paths, [".rustc_info.json", "index.html", "js/app.js"]);
+        let total: u64 = indexer.iter().map(|(_, asset)| asset.len).sum();
+        assert_eq!(total, 15 + 13 + 15);
+
+        assert!(AssetIndexer::parse("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn nth_entry() {
+        let (blob, index) = build_fixture("nth_entry");
+        let content = fs::read_to_string(&index).unwrap();
+        let indexer = AssetIndexer::parse(&content).unwrap();
+        let archive = Archive::open(&blob, &index).unwrap();
+        let sorted = SortedIndex::parse(&content).unwrap();
+
+        let paths: Vec<_> = (0..3).map(|i| indexer.nth(i).unwrap().0).collect();
+        assert_eq!(paths, [".rustc_info.json", "index.html", "js/app.js"]);
+        for i in 0..3 {
+            assert_eq!(indexer.nth(i), archive.nth(i));
+            assert_eq!(indexer.nth(i), sorted.nth(i));
+        }
+        assert_eq!(indexer.nth(1).unwrap().1.len, 13);
+        assert!(indexer.nth(3).is_none() && archive.nth(3).is_none());
+    }
+
+    #[test]
+    fn in_memory_archive() {
+        let entries = [
+            ("index.html".to_owned(), b"<html></html>".to_vec()),
+            ("./js/app.js".to_owned(), b"console.log(1);".to_vec()),
+        ];
+        let (blob, index) =
+            AssetArchiver::build_in_memory(entries.clone(), &ArchiveOptions::default()).unwrap();
+        assert_eq!(blob.len() as u64, HEADER_LEN + 13 + 15);
+        let archive = Archive::from_vec(blob, &index).unwrap();
+        assert_eq!(
+            archive.get("js/app.js").unwrap().unwrap(),
+            b"console.log(1);"
+        );
+        assert_eq!(
+            archive.locate_asset("index.html").unwrap().mime.as_deref(),
+            Some("text/html")
+        );
+        assert!(archive.verify().unwrap().is_ok());
+
+        let options = ArchiveOptions {
+            index_format: IndexFormat::Binary,
+            ..Default::default()
+        };
+        let err = AssetArchiver::build_in_memory(entries, &options).unwrap_err();
+        assert!(matches!(err, MonolithicaError::Unsupported(_)), "{err}");
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn in_memory_compressed_blob() {
+        let options = ArchiveOptions {
+            blob_compression: Some(Encoding::Gzip),
+            ..Default::default()
+        };
+        let entries = [("a.txt".to_owned(), vec![b'a'; 1000])];
+        let (blob, index) = AssetArchiver::build_in_memory(entries, &options).unwrap();
+        assert!(blob.len() < 1000);
+        let archive = Archive::from_vec(blob, index).unwrap();
+        assert_eq!(archive.get("a.txt").unwrap().unwrap(), vec![b'a'; 1000]);
+    }
+
+    #[test]
+    fn case_insensitive_lookup() {
+        let index = "Index.HTML//16//1//-\nindex.html//17//1//-\nJS/App.js//18//1//-\n";
+        let indexer = AssetIndexer::parse(index).unwrap();
+        assert!(indexer.contains("JS/App.js"));
+        assert!(!indexer.contains("js/app.js"));
+        assert!(indexer.locate_asset("js/app.js").is_none());
+        assert_eq!(indexer.locate_asset_ci("js/APP.JS").unwrap().offset, 18);
+        assert_eq!(indexer.locate_asset_ci("INDEX.html").unwrap().offset, 16);
+        assert!(indexer.locate_asset_ci("missing").is_none());
+    }
+
+    #[test]
+    fn locate_normalized() {
+        let indexer = AssetIndexer::parse("js/app.js//16//1//-\n").unwrap();
+        assert!(indexer.locate_asset("/js/app.js").is_none());
+        assert!(indexer.locate_normalized("/js/app.js").is_some());
+        assert!(indexer.locate_normalized("js\\app.js").is_some());
+        assert!(indexer.locate_normalized("/css/../js/app.js").is_none());
+
+        for request_path in ["/js/app.js", "js\\app.js", "./js//./app.js", "\\js/app.js"] {
+            let (path, asset) = indexer.resolve(request_path).unwrap();
+            assert_eq!((path, asset.offset), ("js/app.js", 16), "{request_path}");
+        }
+        for request_path in [
+            "/css/../js/app.js",
+            "../js/app.js",
+            "js/app.js/..",
+            "/",
+            "js",
+        ] {
+            assert!(indexer.resolve(request_path).is_none(), "{request_path}");
+        }
+    }
+
+    #[test]
+    fn read_asset_by_path() {
+        let (blob, index) = build_fixture("read_asset_by_path");
+        let content = fs::read_to_string(index).unwrap();
+        let indexer = AssetIndexer::parse(&content).unwrap();
+
+        let mut blob = File::open(blob).unwrap();
+        let bytes = indexer.read_asset_by_path(&mut blob, "js/app.js").unwrap();
+        assert_eq!(bytes.unwrap(), b"console.log(1);");
+        assert!(indexer
+            .read_asset_by_path(&mut blob, "missing.js")
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn read_asset_range() {
+        let (blob, index) = build_fixture("read_asset_range");
+        let content = fs::read_to_string(index).unwrap();
+        let indexer = AssetIndexer::parse(&content).unwrap();
+        let asset = indexer.locate_asset("js/app.js").unwrap();
+        let mut blob = File::open(blob).unwrap();
+
+        let read = |blob: &mut File, start, end| indexer.read_asset_range(blob, asset, start, end);
+        assert_eq!(read(&mut blob, 8, Some(11)).unwrap(), b"log");
+        assert_eq!(read(&mut blob, 8, None).unwrap(), b"log(1);");
+        assert_eq!(read(&mut blob, 8, Some(100)).unwrap(), b"log(1);");
+        assert_eq!(read(&mut blob, 3, Some(3)).unwrap(), b"");
+        assert_eq!(read(&mut blob, asset.len, None).unwrap(), b"");
+        assert!(read(&mut blob, asset.len + 1, None).is_err());
+        assert!(read(&mut blob, 5, Some(4)).is_err());
+    }
+
+    #[test]
+    fn parse_index() {
+        let content = "a.txt//0//3//text/plain\n\n   \nb.bin//3//4//-\nc//7//1//\n";
+        let indexer = AssetIndexer::parse(content).unwrap();
+        assert_eq!(indexer.locate_asset("a.txt").unwrap().len, 3);
+        assert_eq!(indexer.locate_asset("b.bin").unwrap().offset, 3);
+        assert!(indexer.locate_asset("b.bin").unwrap().mime.is_none());
+        assert!(indexer.locate_asset("c").unwrap().mime.is_none());
+        assert_eq!(
+            indexer.locate_asset("c").unwrap().mime_or_default(),
+            DEFAULT_MIME
+        );
+    }
+
+    #[test]
+    fn parse_crlf_index() {
+        let content =
+            "\r\na.txt//0//3//text/plain\r\n\r\n \t\r\nb.bin//3//4//-//crc32=0000002a\r\n\
+            c//7//1//text/css\nd//8//1//-\r";
+        let indexer = AssetIndexer::parse(content).unwrap();
+        assert_eq!(indexer.len(), 4);
+        let mime = |path| indexer.locate_asset(path).unwrap().mime.as_deref();
+        assert_eq!(mime("a.txt"), Some("text/plain"));
+        assert_eq!(mime("c"), Some("text/css"));
+        assert_eq!(mime("d"), None);
+        assert_eq!(indexer.locate_asset("b.bin").unwrap().crc32, Some(42));
+
+        let err = AssetIndexer::parse("a.txt//0//3//-\r\n\r\nb.txt//0\r\n")
+            .err()
+            .unwrap();
+        assert!(matches!(err, MonolithicaError::IndexLine { line: 3, .. }));
+    }
+
+    #[test]
+    fn parse_malformed_index() {
+        let err = AssetIndexer::parse("a.txt//0//3//text/plain\nb.txt//x//4//text/plain")
+            .err()
+            .unwrap();
+        assert!(format!("{err:#}").contains("index line 2"));
+        assert!(format!("{err:#}").contains("invalid offset"));
+
+        let err = AssetIndexer::parse("a.txt//0").err().unwrap();
+        assert!(format!("{err:#}").contains("expected at least 4 fields, found 2"));
+    }
+
+    #[test]
+    fn parse_lenient_index() {
+        let content =
+            "a.txt//0//3//text/plain\n\nb.txt//0\nc.txt//3//x//-\nd.txt//3//1//-//crc32=zz\n";
+        let err = AssetIndexer::parse(content).err().unwrap();
+        assert!(matches!(
+            err,
+            MonolithicaError::IndexLine {
+                line: 3,
+                problem: IndexLineProblem::FieldCount { found: 2 },
+            }
+        ));
+
+        let (indexer, warnings) = AssetIndexer::parse_lenient(content);
+        assert_eq!(indexer.len(), 1);
+        assert!(indexer.contains("a.txt"));
+        let problems: Vec<_> = warnings
+            .into_iter()
+            .map(|warning| match warning {
+                MonolithicaError::IndexLine { line, problem } => (line, problem),
+                other => panic!("{other}"),
+            })
+            .collect();
+        assert_eq!(
+            problems,
+            [
+                (3, IndexLineProblem::FieldCount { found: 2 }),
+                (
+                    4,
+                    IndexLineProblem::InvalidNumber {
+                        field: "len",
+                        value: "x".to_owned()
+                    }
+                ),
+                (
+                    5,
+                    IndexLineProblem::InvalidNumber {
+                        field: "crc32",
+                        value: "zz".to_owned()
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_index() {
+        let asset = Asset {
+            offset: 16,
+            len: 5,
+            mime: Some("text/html".into()),
+            original_len: 5,
+            variants: vec![AssetVariant {
+                offset: 21,
+                len: 3,
+                encoding: Encoding::Brotli,
+            }],
+            crc32: Some(0xdeadbeef),
+            ..Default::default()
+        };
+        let entries = vec![
+            ("[id].html".to_owned(), asset.clone()),
+            ("raw".to_owned(), Asset::default()),
+        ];
+        let mut json = Vec::new();
+        format::write_index(&mut json, &entries, IndexFormat::Json).unwrap();
+        assert!(format::is_json_index(&json));
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.contains(r#""encoding": "br""#));
+        assert!(!json.contains("null"));
+
+        let indexer = AssetIndexer::from_json(&json).unwrap();
+        assert_eq!(indexer.locate_asset("[id].html"), Some(&asset));
+        assert_eq!(indexer.locate_asset("raw"), Some(&Asset::default()));
+
+        assert!(format::is_json_index(b" [ ]\n"));
+        assert!(!format::is_json_index(b"[id].html//16//5//-"));
+    }
+
+    #[test]
+    fn binary_index() {
+        let root = test_util::fixture_dir("binary_index");
+        test_util::write_file(&root.join("src/index.html"), "<html></html>");
+        test_util::write_file(&root.join("src/js/app.js"), "console.log(1);");
+        test_util::write_file(&root.join("src/js/vendor.js"), "var v;");
+        test_util::write_file(&root.join("src/data.unknown-ext"), "?");
+        let blob = root.join("test.blob");
+        let index = root.join("test.blob.idx");
+        let options = ArchiveOptions {
+            index_format: IndexFormat::Binary,
+            ..Default::default()
+        };
+        AssetArchiver::create_archive_with_options(
+            root.join("src"),
+            &blob,
+            &index,
+            false,
+            &options,
+        )
+        .unwrap();
+
+        let content = fs::read(&index).unwrap();
+        let indexer = AssetIndexer::from_binary(&content).unwrap();
+        let mut blob = File::open(blob).unwrap();
+        let bytes = indexer
+            .read_asset_by_path(&mut blob, "js/vendor.js")
+            .unwrap();
+        assert_eq!(bytes.unwrap(), b"var v;");
+        let asset = indexer.locate_asset("index.html").unwrap();
+        assert_eq!((asset.len, asset.mime.as_deref()), (13, Some("text/html")));
+        assert!(indexer
+            .locate_asset("data.unknown-ext")
+            .unwrap()
+            .mime
+            .is_none());
+
+        assert!(AssetIndexer::from_binary(&content[..content.len() - 1]).is_err());
+        assert!(AssetIndexer::from_binary(b"index.html//0//13//text/html").is_err());
+    }
+
+    #[test]
+    fn owned_indexer() {
+        let (_, index) = test_util::build_fixture("owned_indexer");
+        let indexer = {
+            let content = fs::read_to_string(&index).unwrap();
+            AssetIndexer::parse(&content).unwrap().into_owned()
+        };
+        let shared: std::sync::Arc<OwnedAssetIndexer> = indexer.into();
+        assert_eq!(shared.locate_asset("index.html").unwrap().len, 13);
+        assert_eq!(shared.locate_asset_ci("JS/APP.JS").unwrap().len, 15);
+
+        let read = AssetIndexer::read(&index).unwrap();
+        assert_eq!(read.len(), shared.len());
+        assert_eq!(
+            read.locate_asset("js/app.js"),
+            shared.locate_asset("js/app.js")
+        );
+        assert!(matches!(
+            AssetIndexer::read(index.with_extension("missing")),
+            Err(MonolithicaError::PathIo { .. })
+        ));
+    }
+
+    #[test]
+    #[ignore = "benchmark, run with --ignored --nocapture"]
+    fn bench_binary_index() {
+        let entries: Vec<_> = (0..100_000u64)
+            .map(|i| {
+                let asset = Asset {
+                    offset: i * 100,
+                    len: 100,
+                    mime: ["text/html", "text/css", "application/javascript"]
+                        .get(i as usize % 4)
+                        .map(|mime| mime.to_string()),
+                    ..Default::default()
+                };
+                (format!("dir{}/sub{}/file{i}.js", i % 100, i % 7), asset)
+            })
+            .collect();
+        let mut text = Vec::new();
+        format::write_index(&mut text, &entries, IndexFormat::Text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        let mut binary = Vec::new();
+        format::write_index(&mut binary, &entries, IndexFormat::Binary).unwrap();
+
+        let start = std::time::Instant::now();
+        assert_eq!(AssetIndexer::parse(&text).unwrap().asset_map.len(), 100_000);
+        let text_elapsed = start.elapsed();
+        let start = std::time::Instant::now();
+        assert_eq!(
+            AssetIndexer::from_binary(&binary).unwrap().asset_map.len(),
+            100_000
+        );
+        let binary_elapsed = start.elapsed();
+
+        println!(
+            "text: {} bytes in {text_elapsed:?}, binary: {} bytes in {binary_elapsed:?}",
+            text.len(),
+            binary.len()
+        );
+    }
+
+    /// Compares listing directories with scanning every path, run with
+    /// `cargo test --release -- --ignored --nocapture bench_list_prefix`.
+    #[test]
+    #[ignore = "benchmark, run with --ignored --nocapture"]
+    fn bench_list_prefix() {
+        let entries: Vec<_> = (0..500_000u64)
+            .map(|i| {
+                let path = format!("dir{}/sub{}/file{i}.js", i % 1000, i % 7);
+                (path, Asset::default())
+            })
+            .collect();
+        let mut text = Vec::new();
+        format::write_index(&mut text, &entries, IndexFormat::Text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        let indexer = AssetIndexer::parse(&text).unwrap();
+        let prefixes: Vec<_> = (0..100).map(|i| format!("dir{i}/sub3")).collect();
+
+        let start = std::time::Instant::now();
+        for prefix in &prefixes {
+            let prefix = query::dir_prefix(prefix);
+            let mut found: Vec<_> = indexer.paths().filter(|p| p.starts_with(&prefix)).collect();
+            found.sort_unstable();
+            assert!(!found.is_empty());
+        }
+        let scan = start.elapsed();
+        let start = std::time::Instant::now();
+        assert!(!indexer.list_prefix(&prefixes[0]).is_empty());
+        let first = start.elapsed();
+        let start = std::time::Instant::now();
+        for prefix in &prefixes {
+            assert!(!indexer.list_prefix(prefix).is_empty());
+            assert!(!indexer.list_dir(prefix).is_empty());
+        }
+        let sorted = start.elapsed();
+
+        println!(
+            "{} queries over {} paths: scan {scan:?}, first sorted query {first:?}, \
+             sorted (prefix and dir) {sorted:?}",
+            prefixes.len(),
+            entries.len()
+        );
+    }
+
+    #[test]
+    fn streaming_index_parse() {
+        let text = "index.html//16//13//text/html\r\n\
+            \n\
+            js/app.js//29//15//application/javascript\n\
+            js/app.js//44//10//-//enc=gzip//variant=1";
+        let indexer = AssetIndexer::from_reader(text.as_bytes()).unwrap();
+        assert_eq!(indexer.len(), 2);
+        assert_eq!(
+            indexer.locate_asset("index.html"),
+            AssetIndexer::parse(text)
+                .unwrap()
+                .locate_asset("index.html")
+        );
+        assert_eq!(indexer.locate_asset("js/app.js").unwrap().variants.len(), 1);
+
+        let err = AssetIndexer::from_reader(&b"index.html//16//13//-\nbad\n"[..])
+            .err()
+            .unwrap();
+        assert!(
+            matches!(err, MonolithicaError::IndexLine { line: 2, .. }),
+            "{err}"
+        );
+
+        let entries = [("a.js".to_owned(), Asset::default())];
+        let mut binary = Vec::new();
+        format::write_index(&mut binary, &entries, IndexFormat::Binary).unwrap();
+        let indexer = AssetIndexer::from_reader(std::io::BufReader::with_capacity(8, &binary[..]));
+        assert!(indexer.unwrap().contains("a.js"));
+    }
+
+    /// Compares the peak resident memory of reading an index whole, then
+    /// parsing it into an owned indexer, with parsing it as it's read, each in
+    /// a fresh process: `cargo test --release -- --ignored --nocapture
+    /// bench_streaming_index`.
+    #[cfg(target_os = "linux")]
+    #[test]
+    #[ignore = "benchmark, run with --ignored --nocapture"]
+    fn bench_streaming_index() {
+        const VARIANT: &str = "MONOLITHICA_BENCH_VARIANT";
+        let root = std::env::temp_dir().join("monolithica-tests/bench_streaming_index");
+        let index = root.join("test.blob.idx");
+        if let Ok(variant) = std::env::var(VARIANT) {
+            let peak_kib = || {
+                let status = fs::read_to_string("/proc/self/status").unwrap();
+                let line = status.lines().find(|l| l.starts_with("VmHWM:")).unwrap();
+                line.split_whitespace()
+                    .nth(1)
+                    .unwrap()
+                    .parse::<u64>()
+                    .unwrap()
+            };
+            let before = peak_kib();
+            let indexer = match variant.as_str() {
+                "from_reader" => AssetIndexer::read(&index).unwrap(),
+                _ => {
+                    let content = fs::read_to_string(&index).unwrap();
+                    AssetIndexer::parse(&content).unwrap().into_owned()
+                }
+            };
+            println!("{variant}: peak +{} KiB", peak_kib() - before);
+            assert_eq!(indexer.len(), 500_000);
+            return;
+        }
+
+        test_util::fixture_dir("bench_streaming_index");
+        let mut out = std::io::BufWriter::new(File::create(&index).unwrap());
+        for i in 0..500_000u64 {
+            writeln!(
+                out,
+                "dir{}/sub{}/some/longer/path/file{i}.js//{}//100//application/javascript",
+                i % 100,
+                i % 7,
+                i * 100
+            )
+            .unwrap();
+        }
+        drop(out);
+        println!("index: {} KiB", fs::metadata(&index).unwrap().len() / 1024);
+        for variant in ["read_whole", "from_reader"] {
+            let output = std::process::Command::new(std::env::current_exe().unwrap())
+                .args(["--ignored", "--exact", "--nocapture"])
+                .arg("tests::bench_streaming_index")
+                .env(VARIANT, variant)
+                .output()
+                .unwrap();
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let line = stdout.lines().find(|l| l.contains(variant)).unwrap();
+            println!(
+                "{}",
+                line.trim_start_matches("test tests::bench_streaming_index ... ")
+            );
+        }
+    }
+
+    #[test]
+    fn unusual_paths_round_trip() {
+        let root = test_util::fixture_dir("unusual_paths_round_trip");
+        test_util::write_file(&root.join("src/100% done.txt"), "done");
+        let blob = root.join("test.blob");
+        let index = root.join("test.blob.idx");
+        AssetArchiver::create_archive(root.join("src"), &blob, &index, false).unwrap();
+
+        let content = fs::read_to_string(index).unwrap();
+        let indexer = AssetIndexer::parse(&content).unwrap();
+        assert_eq!(indexer.locate_asset("100% done.txt").unwrap().len, 4);
+
+        let mut content = Vec::new();
+        for path in ["a//b", "trailing/", "//", "%2F", "line\nbreak"] {
+            let asset = Asset {
+                offset: 1,
+                len: 2,
+                mime: Some("text/plain".to_owned()),
+                original_len: 2,
+                ..Default::default()
+            };
+            format::write_index_line(&mut content, path, &asset, None).unwrap();
+        }
+        let content = String::from_utf8(content).unwrap();
+        let indexer = AssetIndexer::parse(&content).unwrap();
+        for path in ["a//b", "trailing/", "//", "%2F", "line\nbreak"] {
+            let asset = indexer.locate_asset(path).unwrap();
+            assert_eq!((asset.offset, asset.len), (1, 2));
+            assert_eq!(asset.mime.as_deref(), Some("text/plain"));
+        }
+    }
+
+    #[test]
+    fn parse_extension_fields() {
+        let content = "a.js//0//10//application/javascript//enc=gzip//size=42\n\
+                       b.js//10//5//application/javascript//future=1\n";
+        let indexer = AssetIndexer::parse(content).unwrap();
+        let a = indexer.locate_asset("a.js").unwrap();
+        assert_eq!(
+            (a.encoding, a.len, a.original_len),
+            (Some(Encoding::Gzip), 10, 42)
+        );
+        let b = indexer.locate_asset("b.js").unwrap();
+        assert_eq!((b.encoding, b.len, b.original_len), (None, 5, 5));
+
+        assert!(AssetIndexer::parse("a.js//0//10//-//enc=lzma").is_err());
+        assert!(AssetIndexer::parse("a.js//0//10//-//size").is_err());
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn gzip_compressed_archive() {
+        let root = test_util::fixture_dir("gzip_compressed_archive");
+        let html = "<p>compressible</p>".repeat(100);
+        test_util::write_file(&root.join("src/index.html"), &html);
+        test_util::write_file(&root.join("src/tiny.txt"), "x");
+        let blob = root.join("test.blob");
+        let index = root.join("test.blob.idx");
+        for index_format in [IndexFormat::Text, IndexFormat::Binary] {
+            let options = ArchiveOptions {
+                index_format,
+                compression: Some(Encoding::Gzip),
+                ..Default::default()
+            };
+            AssetArchiver::create_archive_with_options(
+                root.join("src"),
+                &blob,
+                &index,
+                true,
+                &options,
+            )
+            .unwrap();
+
+            let archive = Archive::open(&blob, &index).unwrap();
+            let asset = archive.locate_asset("index.html").unwrap();
+            assert!(asset.is_compressed());
+            assert!(asset.len < asset.original_len);
+            assert_eq!(asset.original_len, html.len() as u64);
+            assert_eq!(archive.get("index.html").unwrap().unwrap(), html.as_bytes());
+
+            let (raw, encoding) = archive.get_raw("index.html").unwrap().unwrap();
+            assert_eq!(encoding, Some(Encoding::Gzip));
+            assert_eq!(Encoding::Gzip.decode(&raw).unwrap(), html.as_bytes());
+
+            let tiny = archive.locate_asset("tiny.txt").unwrap();
+            assert!(!tiny.is_compressed());
+            assert_eq!(archive.get("tiny.txt").unwrap().unwrap(), b"x");
+        }
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn compress_only_compressible_mimes() {
+        let root = test_util::fixture_dir("compress_only_compressible_mimes");
+        let text = "compressible ".repeat(100);
+        test_util::write_file(&root.join("src/index.html"), &text);
+        test_util::write_file(&root.join("src/image.png"), &text);
+        test_util::write_file(&root.join("src/data.unknownext"), &text);
+        let (blob, index) = (root.join("test.blob"), root.join("test.blob.idx"));
+        let mut options = ArchiveOptions {
+            compression: Some(Encoding::Gzip),
+            ..Default::default()
+        };
+        let compressed = |options: &ArchiveOptions| {
+            AssetArchiver::create_archive_with_options(
+                root.join("src"),
+                &blob,
+                &index,
+                true,
+                options,
+            )
+            .unwrap();
+            let archive = Archive::open(&blob, &index).unwrap();
+            assert_eq!(archive.get("image.png").unwrap().unwrap(), text.as_bytes());
+            let mut paths: Vec<_> = archive
+                .iter()
+                .filter(|(_, asset)| asset.is_compressed())
+                .map(|(path, _)| path.to_owned())
+                .collect();
+            paths.sort_unstable();
+            paths
+        };
+        assert_eq!(compressed(&options), ["index.html"]);
+
+        options.compressible_mimes = Some(vec!["image/png; q=1".to_owned()]);
+        assert_eq!(compressed(&options), ["image.png"]);
+        assert!(is_compressible_mime("text/plain; charset=utf-8"));
+    }
+
+    #[test]
+    fn verify_asset_checksum() {
+        let (blob, index) = build_fixture("verify_asset_checksum");
+        let content = fs::read_to_string(index).unwrap();
+        let indexer = AssetIndexer::parse(&content).unwrap();
+        let asset = indexer.locate_asset("js/app.js").unwrap();
+        assert_eq!(asset.crc32, Some(crc32fast::hash(b"console.log(1);")));
+
+        let mut bytes = fs::read(&blob).unwrap();
+        assert!(indexer
+            .verify_asset(&mut std::io::Cursor::new(&bytes), asset)
+            .unwrap());
+        bytes[asset.offset as usize] ^= 0xff;
+        assert!(!indexer
+            .verify_asset(&mut std::io::Cursor::new(&bytes), asset)
+            .unwrap());
+
+        let legacy = AssetIndexer::parse("a.js//0//3//-").unwrap();
+        let asset = legacy.locate_asset("a.js").unwrap();
+        assert_eq!(asset.crc32, None);
+        assert!(legacy
+            .verify_asset(&mut std::io::Cursor::new(&bytes), asset)
+            .is_err());
+    }
+
+    #[test]
+    fn blob_starts_with_header() {
+        let (blob, index) = build_fixture("blob_starts_with_header");
+        let content = fs::read_to_string(&index).unwrap();
+        let indexer = AssetIndexer::parse(&content).unwrap();
+        let first = indexer.asset_map.values().map(|a| a.offset).min();
+        assert_eq!(first, Some(HEADER_LEN));
+
+        let mut bytes = fs::read(&blob).unwrap();
+        assert_eq!(&bytes[..4], format::MAGIC);
+        assert!(Archive::open(&blob, &index).is_ok());
+
+        bytes[4] = 0xff;
+        fs::write(&blob, &bytes).unwrap();
+        let err = Archive::open(&blob, &index).err().unwrap();
+        assert!(err.to_string().contains("unsupported archive version"));
+
+        bytes[4] = 1;
+        bytes[6] = format::FLAG_ENCRYPTED;
+        fs::write(&blob, &bytes).unwrap();
+        let err = Archive::open(&blob, &index).err().unwrap();
+        assert!(matches!(err, MonolithicaError::KeyRequired), "{err}");
+        bytes[6] = 0x02;
+        fs::write(&blob, &bytes).unwrap();
+        let err = Archive::open(&blob, &index).err().unwrap();
+        assert!(matches!(&err, MonolithicaError::Unsupported(_)), "{err}");
+        bytes[6] = 0;
+
+        fs::write(&blob, &bytes[HEADER_LEN as usize..]).unwrap();
+        let err = Archive::open(&blob, &index).err().unwrap();
+        assert!(matches!(err, MonolithicaError::NotAnArchive));
+    }
+
+    #[test]
+    fn io_errors_name_the_path() {
+        let root = test_util::fixture_dir("io_errors_name_the_path");
+        let missing = root.join("missing");
+        let err = AssetArchiver::create_archive(
+            &missing,
+            root.join("test.blob"),
+            root.join("test.blob.idx"),
+            false,
+        )
+        .err()
+        .unwrap();
+        assert!(matches!(&err, MonolithicaError::PathIo { path, .. } if *path == missing));
+        assert!(err.to_string().contains("missing"), "{err}");
+
+        let err = Archive::open(&missing, root.join("test.blob.idx"))
+            .err()
+            .unwrap();
+        assert!(matches!(err, MonolithicaError::PathIo { path, .. } if path == missing));
+    }
+
+    #[test]
+    fn merge_source_dirs() {
+        let root = test_util::fixture_dir("merge_source_dirs");
+        let (dist, overlay) = (root.join("dist"), root.join("static"));
+        test_util::write_file(&dist.join("index.html"), "<html></html>");
+        test_util::write_file(&dist.join("js/app.js"), "console.log(1);");
+        test_util::write_file(&overlay.join("index.html"), "<html>static</html>");
+        test_util::write_file(&overlay.join("img/logo.svg"), "<svg/>");
+        let (blob, index) = (root.join("test.blob"), root.join("test.blob.idx"));
+
+        let summary = AssetArchiver::create_archive_from_dirs(
+            &[&dist, &overlay],
+            &blob,
+            &index,
+            false,
+            &ArchiveOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(summary.file_count, 3);
+        assert_eq!(summary.overridden, ["index.html"]);
+        assert_eq!(summary.skipped, 1);
+        assert_eq!(summary.total_bytes, 19 + 15 + 6);
+
+        let archive = Archive::open(&blob, &index).unwrap();
+        assert_eq!(
+            archive.get("index.html").unwrap().unwrap(),
+            b"<html>static</html>"
+        );
+        assert_eq!(
+            archive.get("js/app.js").unwrap().unwrap(),
+            b"console.log(1);"
+        );
+        assert!(archive.contains("img/logo.svg"));
+
+        let err =
+            AssetArchiver::create_archive_from_dirs(&[], &blob, &index, true, &Default::default())
+                .unwrap_err();
+        assert!(matches!(err, MonolithicaError::InvalidInput(_)));
+    }
+
+    #[test]
+    fn archive_from_entries() {
+        let root = test_util::fixture_dir("archive_from_entries");
+        let (blob, index) = (root.join("test.blob"), root.join("test.blob.idx"));
+        let generated = (0..3).map(|i| (format!("gen/{i}.js"), format!("var v = {i};")));
+        let entries = [("index.html".to_owned(), "<html></html>".to_owned())]
+            .into_iter()
+            .chain(generated)
+            .chain([("\\css\\app.css".to_owned(), "a{}".to_owned())])
+            .map(|(path, content)| (path, std::io::Cursor::new(content)));
+        let options = ArchiveOptions {
+            dedup: true,
+            ..Default::default()
+        };
+        let summary =
+            AssetArchiver::create_archive_from_entries(entries, &blob, &index, false, &options)
+                .unwrap();
+        assert_eq!(summary.file_count, 5);
+
+        let archive = Archive::open(&blob, &index).unwrap();
+        assert_eq!(archive.get("gen/1.js").unwrap().unwrap(), b"var v = 1;");
+        assert_eq!(
+            archive.get_with_mime("css/app.css").unwrap().unwrap(),
+            (b"a{}".to_vec(), Some("text/css".to_owned()))
+        );
+        assert!(archive.verify().is_ok());
+
+        let create = |entries: Vec<(&str, &[u8])>| {
+            let entries = entries
+                .into_iter()
+                .map(|(path, data)| (path.to_owned(), data));
+            AssetArchiver::create_archive_from_entries(entries, &blob, &index, true, &options)
+        };
+        assert!(matches!(
+            create(vec![("a.js", b"1"), ("./a.js", b"2")]),
+            Err(MonolithicaError::AssetExists(path)) if path == "a.js"
+        ));
+        for path in ["", "/", "../a.js"] {
+            let err = create(vec![(path, b"1")]).unwrap_err();
+            assert!(
+                matches!(err, MonolithicaError::InvalidPath(_)),
+                "{path}: {err}"
+            );
+        }
+        assert_eq!(Archive::open(&blob, &index).unwrap().len(), 5);
+    }
+
+    #[test]
+    fn entries_read_once() {
+        struct CountingReader<'c> {
+            inner: &'static [u8],
+            read: &'c Cell<u64>,
+        }
+        impl Read for CountingReader<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let n = self.inner.read(buf)?;
+                self.read.set(self.read.get() + n as u64);
+                Ok(n)
+            }
+        }
+
+        let root = test_util::fixture_dir("entries_read_once");
+        let (blob, index) = (root.join("test.blob"), root.join("test.blob.idx"));
+        let hashed = ArchiveOptions {
+            content_hash: true,
+            ..Default::default()
+        };
+        let deduplicated = ArchiveOptions {
+            dedup: true,
+            ..hashed.clone()
+        };
+        let sharded = ArchiveOptions {
+            shard_size: Some(1 << 20),
+            ..deduplicated.clone()
+        };
+        for options in [hashed, deduplicated, sharded] {
+            let read = Cell::new(0);
+            let entries = [
+                ("a.bin", &[b'a'; 1000][..]),
+                ("b.bin", &[b'a'; 1000]),
+                ("c.bin", b"c"),
+            ]
+            .map(|(path, inner)| {
+                let reader = CountingReader { inner, read: &read };
+                (path.to_owned(), reader)
+            });
+            AssetArchiver::create_archive_from_entries(entries, &blob, &index, true, &options)
+                .unwrap();
+            assert_eq!(read.get(), 2001);
+
+            let archive = Archive::open(&blob, &index).unwrap();
+            let (a, b) = (
+                archive.locate_asset("a.bin").unwrap(),
+                archive.locate_asset("b.bin").unwrap(),
+            );
+            assert_eq!(a.offset == b.offset, options.dedup);
+            assert_eq!(a.content_hash, b.content_hash);
+            assert!(a.content_hash.is_some());
+            assert_eq!(archive.get("b.bin").unwrap().unwrap(), [b'a'; 1000]);
+            assert!(archive.verify().is_ok());
+        }
+    }
+
+    #[test]
+    fn dedup_crc32_collision() {
+        // CRC32 is linear, so the counter is scrambled to find a collision
+        // among 2^16 or so inputs rather than 2^32.
+        let mut seen = HashMap::new();
+        let (a, b) = (0u64..)
+            .map(|i| i.wrapping_mul(0x9e37_79b9_7f4a_7c15).to_le_bytes())
+            .find_map(|data| {
+                let other = seen.insert(crc32fast::hash(&data), data)?;
+                Some((other, data))
+            })
+            .unwrap();
+        assert_eq!(crc32fast::hash(&a), crc32fast::hash(&b));
+        let key = |data: &[u8]| {
+            let mut hasher = packer::ContentHasher::default();
+            hasher.update(data);
+            hasher.finish()
+        };
+        assert_ne!(key(&a), key(&b));
+
+        let options = ArchiveOptions {
+            dedup: true,
+            ..Default::default()
+        };
+        let entries =
+            [("a.bin", a), ("b.bin", b)].map(|(path, data)| (path.to_owned(), data.to_vec()));
+        let (blob, index) = AssetArchiver::build_in_memory(entries, &options).unwrap();
+        let archive = Archive::from_vec(blob, index).unwrap();
+        assert_eq!(archive.get("a.bin").unwrap().unwrap(), a);
+        assert_eq!(archive.get("b.bin").unwrap().unwrap(), b);
+        assert!(archive.verify().unwrap().is_ok());
+    }
+
+    #[test]
+    fn failed_build_keeps_existing_archive() {
+        let (blob, index) = test_util::build_fixture("failed_build_keeps_existing_archive");
+        let root = blob.parent().unwrap();
+        let (old_blob, old_index) = (fs::read(&blob).unwrap(), fs::read(&index).unwrap());
+        let missing = root.join("missing");
+        assert!(AssetArchiver::create_archive(&missing, &blob, &index, true).is_err());
+        assert_eq!(fs::read(&blob).unwrap(), old_blob);
+        assert_eq!(fs::read(&index).unwrap(), old_index);
+        assert!(!root.join("test.blob.tmp").exists());
+        assert!(!root.join("test.blob.idx.tmp").exists());
+
+        AssetArchiver::create_archive(root.join("src"), &blob, &index, true).unwrap();
+        assert!(Archive::open(&blob, &index).unwrap().contains("index.html"));
+        assert!(!root.join("test.blob.tmp").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_path_is_an_error() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let root = test_util::fixture_dir("non_utf8_path_is_an_error");
+        test_util::write_file(&root.join("src").join(OsStr::from_bytes(b"\xff.txt")), "x");
+        let err = AssetArchiver::create_archive(
+            root.join("src"),
+            root.join("test.blob"),
+            root.join("test.blob.idx"),
+            false,
+        )
+        .err()
+        .unwrap();
+        assert!(err.to_string().contains("not valid UTF-8"));
+    }
+
+    #[test]
+    fn read_asset_from_truncated_blob() {
+        let (blob, index) = build_fixture("read_asset_from_truncated_blob");
+        let content = fs::read_to_string(index).unwrap();
+        let indexer = AssetIndexer::parse(&content).unwrap();
+
+        let bytes = fs::read(&blob).unwrap();
+        let mut truncated = std::io::Cursor::new(&bytes[..bytes.len() - 1]);
+        let last = indexer
+            .asset_map
+            .values()
+            .max_by_key(|asset| asset.offset)
+            .unwrap();
+        assert!(indexer.read_asset(&mut truncated, last).is_err());
     }
 }
@@ -0,0 +1,115 @@
+#[cfg(feature = "std-fs")]
+use crate::error::IoContext;
+use crate::{Asset, MonolithicaError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+#[cfg(feature = "std-fs")]
+use std::{fs, path::Path};
+
+/// The content hash and length of each asset of an archive, as JSON, for
+/// tooling outside the archive such as CDN uploaders or HTML rewriters
+/// fingerprinting references. Written with [`crate::ArchiveOptions::manifest`].
+///
+/// ```json
+/// {
+///   "assets": {
+///     "index.html": { "hash": "b633a587c652d023", "len": 13 }
+///   }
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Entries by archive path, sorted.
+    pub assets: BTreeMap<String, ManifestEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// See [`Asset::content_hash`].
+    pub hash: String,
+    /// Length of the decoded asset.
+    pub len: u64,
+}
+
+impl Manifest {
+    /// The manifest of `entries`, leaving out those without a content hash.
+    pub fn from_entries<'a>(entries: impl IntoIterator<Item = (&'a str, &'a Asset)>) -> Self {
+        let assets = entries
+            .into_iter()
+            .filter_map(|(path, asset)| {
+                let entry = ManifestEntry {
+                    hash: asset.content_hash.clone()?,
+                    len: asset.original_len,
+                };
+                Some((path.to_owned(), entry))
+            })
+            .collect();
+        Self { assets }
+    }
+
+    pub fn get(&self, path: &str) -> Option<&ManifestEntry> {
+        self.assets.get(path)
+    }
+
+    pub fn from_json(content: &str) -> Result<Self> {
+        serde_json::from_str(content).map_err(|e| {
+            tracing::error!("invalid manifest: {e}");
+            MonolithicaError::InvalidInput(format!("invalid manifest: {e}"))
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut json = serde_json::to_string_pretty(self).unwrap_or_default();
+        json.push('\n');
+        json
+    }
+
+    #[cfg(feature = "std-fs")]
+    pub fn read(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        Self::from_json(&fs::read_to_string(path).at(path)?)
+    }
+
+    #[cfg(feature = "std-fs")]
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        fs::write(path, self.to_json()).at(path)
+    }
+}
+
+#[cfg(all(test, feature = "std-fs"))]
+mod tests {
+    use super::*;
+    use crate::{
+        test_util::{fixture_dir, write_file},
+        Archive, ArchiveBuilder,
+    };
+
+    #[test]
+    fn write_manifest() {
+        let root = fixture_dir("manifest_write_manifest");
+        write_file(&root.join("src/index.html"), "<html></html>");
+        write_file(&root.join("src/js/app.js"), "console.log(1);");
+        let manifest_path = root.join("manifest.json");
+        ArchiveBuilder::new()
+            .source(root.join("src"))
+            .blob(root.join("test.blob"))
+            .index(root.join("test.blob.idx"))
+            .manifest(&manifest_path)
+            .build()
+            .unwrap();
+
+        let manifest = Manifest::read(&manifest_path).unwrap();
+        assert_eq!(
+            manifest.get("index.html"),
+            Some(&ManifestEntry {
+                hash: "b633a587c652d023".to_owned(),
+                len: 13
+            })
+        );
+        assert_eq!(manifest.assets.len(), 2);
+        let archive = Archive::open(root.join("test.blob"), root.join("test.blob.idx")).unwrap();
+        assert_eq!(Manifest::from_entries(archive.iter()), manifest);
+        assert!(Manifest::from_json("[]").is_err());
+    }
+}
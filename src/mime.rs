@@ -0,0 +1,188 @@
+use crate::{filter::glob, ArchiveOptions, Result};
+use globset::{GlobSet, GlobSetBuilder};
+use std::{collections::HashMap, fmt, path::Path, sync::Arc};
+
+/// Guesses the mime of a file from its archive path in place of the
+/// extension table of `mime_guess`, see [`ArchiveOptions::mime_guesser`].
+/// Clones share the function.
+///
+/// ```
+/// use monolithica::{ArchiveOptions, MimeGuesser};
+///
+/// let options = ArchiveOptions {
+///     mime_guesser: Some(MimeGuesser::new(|path| match path.extension()?.to_str()? {
+///         "tpl" => Some("text/html".to_owned()),
+///         _ => None,
+///     })),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Clone)]
+pub struct MimeGuesser(Arc<GuessFn>);
+
+type GuessFn = dyn Fn(&Path) -> Option<String> + Send + Sync;
+
+impl MimeGuesser {
+    pub fn new(guess: impl Fn(&Path) -> Option<String> + Send + Sync + 'static) -> Self {
+        Self(Arc::new(guess))
+    }
+
+    pub fn guess(&self, path: &Path) -> Option<String> {
+        (self.0)(path)
+    }
+}
+
+impl fmt::Debug for MimeGuesser {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MimeGuesser")
+    }
+}
+
+/// Decides the mime recorded for each file by its archive-relative path, see
+/// [`ArchiveOptions::mime_by_glob`], [`ArchiveOptions::mime_by_extension`] and
+/// [`ArchiveOptions::default_mime`].
+pub(crate) struct MimeResolver {
+    globs: GlobSet,
+    glob_mimes: Vec<String>,
+    extensions: HashMap<String, String>,
+    guesser: Option<MimeGuesser>,
+    default_mime: Option<String>,
+}
+
+impl MimeResolver {
+    pub fn new(options: &ArchiveOptions) -> Result<Self> {
+        let mut globs = GlobSetBuilder::new();
+        for (pattern, _) in &options.mime_by_glob {
+            globs.add(glob(pattern)?);
+        }
+        Ok(Self {
+            globs: globs.build()?,
+            glob_mimes: options
+                .mime_by_glob
+                .iter()
+                .map(|(_, mime)| mime.clone())
+                .collect(),
+            extensions: options
+                .mime_by_extension
+                .iter()
+                .map(|(extension, mime)| (extension.to_ascii_lowercase(), mime.clone()))
+                .collect(),
+            guesser: options.mime_guesser.clone(),
+            default_mime: options.default_mime.clone(),
+        })
+    }
+
+    pub fn resolve(&self, rel_path: &str) -> Option<String> {
+        if let Some(i) = self.globs.matches(rel_path).into_iter().min() {
+            return Some(self.glob_mimes[i].clone());
+        }
+        let extension = Path::new(rel_path)
+            .extension()
+            .and_then(|extension| extension.to_str());
+        if let Some(mime) =
+            extension.and_then(|extension| self.extensions.get(&extension.to_ascii_lowercase()))
+        {
+            return Some(mime.clone());
+        }
+        let guessed = match &self.guesser {
+            Some(guesser) => guesser.guess(Path::new(rel_path)),
+            None => guess_mime(rel_path),
+        };
+        guessed.or_else(|| self.default_mime.clone())
+    }
+}
+
+/// Guesses the mime of `path` from its extension.
+pub(crate) fn guess_mime(path: impl AsRef<Path>) -> Option<String> {
+    mime_guess::from_path(path)
+        .first()
+        .map(|mime| mime.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides() {
+        let options = ArchiveOptions {
+            mime_by_extension: HashMap::from([
+                (
+                    "WebManifest".to_owned(),
+                    "application/manifest+json".to_owned(),
+                ),
+                ("js".to_owned(), "text/javascript".to_owned()),
+            ]),
+            mime_by_glob: vec![
+                ("data/*.bin".to_owned(), "application/x-app-data".to_owned()),
+                ("data/**".to_owned(), "application/x-other".to_owned()),
+                ("**/*.js".to_owned(), "application/x-never".to_owned()),
+            ],
+            ..Default::default()
+        };
+        let resolver = MimeResolver::new(&options).unwrap();
+        let resolve = |path| resolver.resolve(path);
+        assert_eq!(
+            resolve("site.webmanifest").as_deref(),
+            Some("application/manifest+json")
+        );
+        assert_eq!(resolve("js/app.JS").as_deref(), Some("text/javascript"));
+        assert_eq!(
+            resolve("data/a.bin").as_deref(),
+            Some("application/x-app-data")
+        );
+        assert_eq!(
+            resolve("data/x/a.bin").as_deref(),
+            Some("application/x-other")
+        );
+        assert_eq!(
+            resolve("data/app.js").as_deref(),
+            Some("application/x-other")
+        );
+        assert_eq!(resolve("index.html").as_deref(), Some("text/html"));
+        assert_eq!(resolve("README"), None);
+
+        let options = ArchiveOptions {
+            mime_by_glob: vec![("[".to_owned(), "text/plain".to_owned())],
+            ..Default::default()
+        };
+        assert!(MimeResolver::new(&options).is_err());
+    }
+
+    #[test]
+    fn custom_guesser() {
+        let options = ArchiveOptions {
+            mime_by_extension: HashMap::from([("js".to_owned(), "text/javascript".to_owned())]),
+            mime_guesser: Some(MimeGuesser::new(|path| {
+                path.starts_with("templates")
+                    .then(|| "text/x-template".to_owned())
+            })),
+            ..Default::default()
+        };
+        let resolver = MimeResolver::new(&options).unwrap();
+        assert_eq!(
+            resolver.resolve("templates/a.html").as_deref(),
+            Some("text/x-template")
+        );
+        // Overrides still come first, and the guesser replaces `mime_guess`.
+        assert_eq!(
+            resolver.resolve("templates/a.js").as_deref(),
+            Some("text/javascript")
+        );
+        assert_eq!(resolver.resolve("index.html"), None);
+
+        let options = ArchiveOptions {
+            default_mime: Some("application/octet-stream".to_owned()),
+            ..options
+        };
+        let resolver = MimeResolver::new(&options).unwrap();
+        assert_eq!(
+            resolver.resolve("index.html").as_deref(),
+            Some("application/octet-stream")
+        );
+        assert_eq!(
+            resolver.resolve("templates/a.html").as_deref(),
+            Some("text/x-template")
+        );
+    }
+}
@@ -0,0 +1,143 @@
+use crate::{Archive, Asset, Result};
+use std::collections::HashSet;
+
+/// Archives layered on top of each other, e.g. a small per-deployment
+/// archive of overrides over a base bundle.
+///
+/// Layers are searched in order, the first one taking precedence: a path
+/// resolves to the asset of the first layer that has it, hiding the assets
+/// at the same path in later layers. Layers can only add or replace assets,
+/// not remove them.
+pub struct OverlayArchive {
+    layers: Vec<Archive>,
+}
+
+impl OverlayArchive {
+    /// Overlays `layers`, highest precedence first.
+    pub fn new(layers: Vec<Archive>) -> Self {
+        Self { layers }
+    }
+
+    /// Adds `archive` as the layer of lowest precedence.
+    pub fn push(&mut self, archive: Archive) {
+        self.layers.push(archive);
+    }
+
+    /// The layers, highest precedence first.
+    pub fn layers(&self) -> &[Archive] {
+        &self.layers
+    }
+
+    /// The first layer that has an asset at `path`.
+    pub fn layer_of(&self, path: &str) -> Option<&Archive> {
+        self.layers.iter().find(|layer| layer.contains(path))
+    }
+
+    pub fn locate_asset(&self, path: &str) -> Option<&Asset> {
+        self.layers
+            .iter()
+            .find_map(|layer| layer.locate_asset(path))
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.layer_of(path).is_some()
+    }
+
+    /// Returns the bytes of the asset at `path` in the first layer that has
+    /// one, `Ok(None)` if none does.
+    pub fn get(&self, path: &str) -> Result<Option<Vec<u8>>> {
+        match self.layer_of(path) {
+            Some(layer) => layer.get(path),
+            None => Ok(None),
+        }
+    }
+
+    /// Like [`OverlayArchive::get`], also returning the recorded mime.
+    pub fn get_with_mime(&self, path: &str) -> Result<Option<(Vec<u8>, Option<String>)>> {
+        match self.layer_of(path) {
+            Some(layer) => layer.get_with_mime(path),
+            None => Ok(None),
+        }
+    }
+
+    /// Iterates over the entries visible through the overlay, each path once
+    /// with the asset of the layer it resolves to, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Asset)> {
+        let mut seen = HashSet::new();
+        self.layers
+            .iter()
+            .flat_map(Archive::iter)
+            .filter(move |(path, _)| seen.insert(*path))
+    }
+
+    /// The union of the paths of all layers, without duplicates.
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.iter().map(|(path, _)| path)
+    }
+
+    /// Number of distinct paths across the layers.
+    pub fn len(&self) -> usize {
+        self.paths().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.iter().all(Archive::is_empty)
+    }
+}
+
+#[cfg(all(test, feature = "std-fs"))]
+mod tests {
+    use super::*;
+    use crate::{
+        test_util::{build_fixture, fixture_dir, write_file},
+        AssetArchiver,
+    };
+
+    #[test]
+    fn precedence() {
+        let (blob, index) = build_fixture("overlay_precedence_base");
+        let base = Archive::open(blob, index).unwrap();
+
+        let root = fixture_dir("overlay_precedence_patch");
+        write_file(&root.join("src/index.html"), "<html>patched</html>");
+        write_file(&root.join("src/robots.txt"), "User-agent: *");
+        let (blob, index) = (root.join("test.blob"), root.join("test.blob.idx"));
+        AssetArchiver::create_archive(root.join("src"), &blob, &index, true).unwrap();
+        let patch = Archive::open(blob, index).unwrap();
+
+        let overlay = OverlayArchive::new(vec![patch, base]);
+        assert_eq!(
+            overlay.get("index.html").unwrap().unwrap(),
+            b"<html>patched</html>"
+        );
+        assert_eq!(
+            overlay.get("js/app.js").unwrap().unwrap(),
+            b"console.log(1);"
+        );
+        assert_eq!(
+            overlay
+                .get_with_mime("robots.txt")
+                .unwrap()
+                .unwrap()
+                .1
+                .as_deref(),
+            Some("text/plain")
+        );
+        assert_eq!(overlay.get("missing").unwrap(), None);
+        assert!(std::ptr::eq(
+            overlay.layer_of("js/app.js").unwrap(),
+            &overlay.layers()[1]
+        ));
+        assert_eq!(overlay.locate_asset("index.html").unwrap().len, 20);
+
+        let mut paths: Vec<_> = overlay.paths().collect();
+        paths.sort_unstable();
+        assert_eq!(
+            paths,
+            [".rustc_info.json", "index.html", "js/app.js", "robots.txt"]
+        );
+        assert_eq!(overlay.len(), 4);
+        assert!(!overlay.is_empty());
+        assert!(OverlayArchive::new(Vec::new()).is_empty());
+    }
+}
@@ -0,0 +1,1325 @@
+#[cfg(feature = "std-fs")]
+use crate::{
+    atomic::Outputs,
+    error::IoContext,
+    filter::{IgnoreStack, PathFilter},
+    path::{archive_path, link_target, resolve_link},
+    query, OversizedFiles,
+};
+use crate::{
+    format::HEADER_LEN,
+    is_compressible_mime,
+    mime::MimeResolver,
+    mime_essence, normalize_path,
+    progress::{Progress, ProgressEvent},
+    ArchiveOptions, ArchiveSummary, Asset, AssetVariant, DuplicatePaths, Encoding,
+    MonolithicaError, Result,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    io::{self, Read, Write},
+    path::Path,
+};
+#[cfg(feature = "std-fs")]
+use std::{
+    ffi::OsStr,
+    fs::{self, File},
+    io::BufReader,
+    path::PathBuf,
+    time::UNIX_EPOCH,
+};
+
+/// Called with the blob and the length of its data once it's full, to finish
+/// it and replace it with the next shard.
+pub(crate) type NextShard<'a, W> = &'a mut (dyn FnMut(&mut W, u64) -> Result<()> + Send + 'a);
+
+/// Appends assets to the data part of a blob, recording their index entries.
+pub(crate) struct Packer<'o, W> {
+    pub blob: W,
+    /// The source directory archive paths are relative to.
+    #[cfg_attr(not(feature = "std-fs"), allow(dead_code))]
+    base_dir: &'o Path,
+    pub options: &'o ArchiveOptions,
+    #[cfg(feature = "std-fs")]
+    filter: PathFilter,
+    /// [`ArchiveOptions::strip_prefix`] and [`ArchiveOptions::add_prefix`] as
+    /// directory prefixes, empty if unset.
+    #[cfg(feature = "std-fs")]
+    strip_prefix: String,
+    #[cfg(feature = "std-fs")]
+    add_prefix: String,
+    #[cfg(feature = "std-fs")]
+    pub ignores: IgnoreStack,
+    pub mimes: MimeResolver,
+    entries: Vec<(String, Asset)>,
+    /// Offset the next asset is written at, in the current shard.
+    pub offset: u64,
+    /// See [`Asset::shard`].
+    shard: u32,
+    /// Data bytes of the shards finished before the current one.
+    previous_shards: u64,
+    /// Finishes the current shard and starts the next one, see
+    /// [`ArchiveOptions::shard_size`].
+    pub next_shard: Option<NextShard<'o, W>>,
+    skipped: usize,
+    oversized: Vec<String>,
+    symlinks: Vec<String>,
+    /// Paths archived from the sources walked before the current one, or
+    /// already in the archive appended to, which take precedence over it.
+    #[cfg(feature = "std-fs")]
+    pub claimed: HashSet<String>,
+    overridden: Vec<String>,
+    /// Normalized paths archived so far, see [`ArchiveOptions::duplicate_paths`].
+    paths: HashSet<String>,
+    path_collisions: Vec<String>,
+    /// Entry holding each content stored so far, see [`ArchiveOptions::dedup`].
+    stored: HashMap<ContentKey, usize>,
+    duplicates: usize,
+    /// Canonical paths of the directories being walked, to detect symlink
+    /// cycles.
+    #[cfg(feature = "std-fs")]
+    ancestors: Vec<PathBuf>,
+    /// Told about each file added, from the thread driving the packer.
+    pub progress: Option<Progress<'o>>,
+    /// Files the archive is written to, skipped if found in a source.
+    #[cfg(feature = "std-fs")]
+    pub outputs: Outputs,
+    /// Entries waiting for the files among them to be read in parallel, with
+    /// their archive paths, see [`ArchiveOptions::parallel`].
+    #[cfg(feature = "rayon")]
+    pending: Vec<(String, Pending)>,
+}
+
+impl<'o, W: Write> Packer<'o, W> {
+    pub fn new(blob: W, base_dir: &'o Path, options: &'o ArchiveOptions) -> Result<Self> {
+        #[cfg(feature = "encryption")]
+        if options.encryption_key.is_some() && !options.precompressed.is_empty() {
+            return Err(MonolithicaError::Unsupported(
+                "precompressed variants can't be encrypted".to_owned(),
+            ));
+        }
+        #[cfg(feature = "encryption")]
+        if options.encryption_key.is_some() && options.dedup {
+            return Err(MonolithicaError::Unsupported(
+                "encrypted assets can't be deduplicated, being bound to their paths".to_owned(),
+            ));
+        }
+        Ok(Self {
+            blob,
+            base_dir,
+            options,
+            #[cfg(feature = "std-fs")]
+            filter: PathFilter::new(&options.include, &options.exclude)?,
+            #[cfg(feature = "std-fs")]
+            strip_prefix: key_prefix(options.strip_prefix.as_deref())?,
+            #[cfg(feature = "std-fs")]
+            add_prefix: key_prefix(options.add_prefix.as_deref())?,
+            #[cfg(feature = "std-fs")]
+            ignores: IgnoreStack::new(base_dir, &options.ignore_patterns, options.ignore_files)?,
+            mimes: MimeResolver::new(options)?,
+            entries: Vec::new(),
+            offset: HEADER_LEN,
+            shard: 0,
+            previous_shards: 0,
+            next_shard: None,
+            skipped: 0,
+            oversized: Vec::new(),
+            symlinks: Vec::new(),
+            #[cfg(feature = "std-fs")]
+            claimed: HashSet::new(),
+            overridden: Vec::new(),
+            paths: HashSet::new(),
+            path_collisions: Vec::new(),
+            stored: HashMap::new(),
+            duplicates: 0,
+            #[cfg(feature = "std-fs")]
+            ancestors: Vec::new(),
+            progress: None,
+            #[cfg(feature = "std-fs")]
+            outputs: Outputs::default(),
+            #[cfg(feature = "rayon")]
+            pending: Vec::new(),
+        })
+    }
+
+    /// Archives the files under the source directory.
+    #[cfg(feature = "std-fs")]
+    pub fn pack(&mut self) -> Result<()> {
+        self.add_dir(self.base_dir)?;
+        #[cfg(feature = "rayon")]
+        self.flush_pending()?;
+        Ok(())
+    }
+
+    /// Archives the entries of `entries`, see
+    /// [`AssetArchiver::create_archive_from_entries`].
+    ///
+    /// [`AssetArchiver::create_archive_from_entries`]: crate::AssetArchiver::create_archive_from_entries
+    pub fn pack_entries(&mut self, entries: &mut dyn Iterator<Item = Entry<'_>>) -> Result<()> {
+        for (path, reader) in entries {
+            let rel_path = match normalize_path(&path) {
+                Some(rel_path) if !rel_path.is_empty() => rel_path.into_owned(),
+                _ => {
+                    tracing::error!("invalid entry path: {path:?}");
+                    return Err(MonolithicaError::InvalidPath(format!(
+                        "invalid entry path: {path:?}"
+                    )));
+                }
+            };
+            if self.claim_path(&rel_path)? {
+                self.add_reader(rel_path, reader)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Records that a file is archived at `rel_path`, handling it as set by
+    /// [`ArchiveOptions::duplicate_paths`] if one already is once both paths
+    /// are normalized. Returns whether to archive it.
+    fn claim_path(&mut self, rel_path: &str) -> Result<bool> {
+        let normalized =
+            normalize_path(rel_path).map_or_else(|| rel_path.to_owned(), Cow::into_owned);
+        if self.paths.insert(normalized) {
+            return Ok(true);
+        }
+        match self.options.duplicate_paths {
+            DuplicatePaths::Error => {
+                tracing::error!("duplicate path: {rel_path}");
+                Err(MonolithicaError::AssetExists(rel_path.to_owned()))
+            }
+            DuplicatePaths::Skip => {
+                tracing::warn!("skipping duplicate path: {rel_path}");
+                self.skipped += 1;
+                self.path_collisions.push(rel_path.to_owned());
+                Ok(false)
+            }
+        }
+    }
+
+    /// Archives the bytes of `reader` at `rel_path`, streaming them into the
+    /// blob unless they're encoded or deduplicated.
+    fn add_reader(&mut self, rel_path: String, mut reader: impl Read) -> Result<()> {
+        let mime = self.mimes.resolve(&rel_path);
+        if self.encodes(mime.as_deref()) {
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data)?;
+            let prepared = prepare_data(data, mime, FileMeta::default(), self.options)?;
+            return self.write_prepared(rel_path, prepared);
+        }
+        // Sharding needs the length before writing, which deduplicating reads
+        // the whole of anyway.
+        if self.options.shard_size.is_some() && !self.options.dedup {
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data)?;
+            self.reserve(data.len() as u64)?;
+            return self.copy_raw(rel_path, mime, FileMeta::default(), &data[..], None, None);
+        }
+        self.copy_raw(rel_path, mime, FileMeta::default(), reader, None, None)
+    }
+
+    /// Archives the bytes of `reader` as is in a single pass that also
+    /// computes their checksums. When deduplicating without a `key` computed
+    /// beforehand, they're read into memory, hashed on the way, and only
+    /// written if not a duplicate.
+    ///
+    /// `file` is the path and length of the file `reader` reads, if it does:
+    /// I/O errors are reported at that path, and the copy fails if the file
+    /// no longer has that length.
+    fn copy_raw(
+        &mut self,
+        rel_path: String,
+        mime: Option<String>,
+        meta: FileMeta,
+        mut reader: impl Read,
+        file: Option<(&Path, u64)>,
+        key: Option<ContentKey>,
+    ) -> Result<()> {
+        let len = file.map(|(_, len)| len);
+        let io_error = |source: io::Error| match file {
+            Some((path, _)) => MonolithicaError::PathIo {
+                path: path.to_owned(),
+                source,
+            },
+            None => source.into(),
+        };
+        let checksummer = Checksummer::new(self.options);
+        let (copied, checksummer, key) = if self.options.dedup && key.is_none() {
+            let capacity = len.unwrap_or_default().min(MAX_BUFFERED_LEN) as usize;
+            let mut buffered = Tee::new(
+                Vec::with_capacity(capacity),
+                Tee::new(checksummer, ContentHasher::default()),
+            );
+            let copied = io::copy(&mut reader, &mut buffered).map_err(io_error)?;
+            let (data, hashers) = buffered.into_parts();
+            let (checksummer, hasher) = hashers.into_parts();
+            let key = hasher.finish();
+            check_len(&rel_path, copied, len)?;
+            if self.add_duplicate(&rel_path, &mime, meta, key) {
+                return Ok(());
+            }
+            self.reserve(copied)?;
+            self.blob.write_all(&data)?;
+            (copied, checksummer, Some(key))
+        } else {
+            if let Some(len) = len {
+                self.reserve(len)?;
+            }
+            let mut writer = Tee::new(&mut self.blob, checksummer);
+            let copied = io::copy(&mut reader, &mut writer).map_err(io_error)?;
+            check_len(&rel_path, copied, len)?;
+            (copied, writer.into_parts().1, key)
+        };
+        self.push_raw(rel_path, mime, meta, copied, checksummer.finish());
+        if let Some(key) = key {
+            self.remember(key);
+        }
+        Ok(())
+    }
+
+    /// Switches to archiving the files under `base_dir`, leaving out those at
+    /// the paths archived so far from the previous sources.
+    #[cfg(feature = "std-fs")]
+    pub fn next_source(&mut self, base_dir: &'o Path) -> Result<()> {
+        let options = self.options;
+        self.claimed
+            .extend(self.entries.iter().map(|(path, _)| path.clone()));
+        self.ignores = IgnoreStack::new(base_dir, &options.ignore_patterns, options.ignore_files)?;
+        self.base_dir = base_dir;
+        Ok(())
+    }
+
+    /// Returns the index entries and a summary without the blob size.
+    pub fn finish(self) -> (Vec<(String, Asset)>, ArchiveSummary) {
+        let summary = ArchiveSummary {
+            file_count: self.entries.len(),
+            total_bytes: self.entries.iter().map(|(_, a)| a.original_len).sum(),
+            blob_size: 0,
+            skipped: self.skipped,
+            oversized: self.oversized,
+            symlinks: self.symlinks,
+            overridden: self.overridden,
+            path_collisions: self.path_collisions,
+            duplicates: self.duplicates,
+            bytes_reclaimed: 0,
+        };
+        (self.entries, summary)
+    }
+
+    #[cfg(feature = "std-fs")]
+    fn add_dir(&mut self, src_dir: &Path) -> Result<()> {
+        let canonical = match self.options.follow_symlinks {
+            true => Some(fs::canonicalize(src_dir).at(src_dir)?),
+            false => None,
+        };
+        if !self.enter_dir(src_dir, canonical)? {
+            return Ok(());
+        }
+        self.ignores.enter(src_dir)?;
+
+        let mut entries = fs::read_dir(src_dir)
+            .and_then(|entries| entries.collect::<io::Result<Vec<_>>>())
+            .at(src_dir)?;
+        if self.options.deterministic {
+            entries.sort_by_key(|entry| entry.file_name());
+        }
+        for entry in entries {
+            let path = entry.path();
+            let kind = if entry.file_type().at(&path)?.is_symlink() && !self.options.follow_symlinks
+            {
+                EntryKind::Symlink
+            } else if path.is_file() {
+                EntryKind::File(path.metadata().at(&path)?.len())
+            } else if path.is_dir() {
+                EntryKind::Dir
+            } else {
+                continue;
+            };
+            match self.visit(&path, &entry.file_name(), kind)? {
+                Visit::File(rel_path) => self.add_file(&path, rel_path)?,
+                Visit::Symlink(rel_path) => {
+                    let target = fs::read_link(&path).at(&path)?;
+                    #[cfg(feature = "rayon")]
+                    if self.options.parallel {
+                        self.queue(rel_path, Pending::Symlink(target))?;
+                        continue;
+                    }
+                    self.add_symlink(rel_path, &target)?
+                }
+                Visit::Dir => self.add_dir(&path)?,
+                Visit::Skip => {}
+            }
+        }
+        self.leave_dir();
+
+        Ok(())
+    }
+
+    /// Starts walking `dir`, given its canonical path if symlinks are followed,
+    /// the caller then loading its ignore files into [`Packer::ignores`].
+    /// Returns `false` if `dir` is to be skipped, then [`Packer::leave_dir`]
+    /// must not be called for it.
+    #[cfg(feature = "std-fs")]
+    pub fn enter_dir(&mut self, dir: &Path, canonical: Option<PathBuf>) -> Result<bool> {
+        if let Some(canonical) = canonical {
+            if self.ancestors.contains(&canonical) {
+                tracing::warn!("skipping symlink cycle: {dir:?}");
+                self.skipped += 1;
+                return Ok(false);
+            }
+            self.ancestors.push(canonical);
+        }
+        Ok(true)
+    }
+
+    #[cfg(feature = "std-fs")]
+    pub fn leave_dir(&mut self) {
+        self.ignores.leave();
+        if self.options.follow_symlinks {
+            self.ancestors.pop();
+        }
+    }
+
+    #[cfg(feature = "std-fs")]
+    fn rel_path<'p>(&self, path: &'p Path) -> Result<&'p Path> {
+        path.strip_prefix(self.base_dir).map_err(|_| {
+            MonolithicaError::InvalidPath(format!("{path:?} is outside {:?}", self.base_dir))
+        })
+    }
+
+    /// Decides what to do with the directory entry at `path` named `name`,
+    /// counting it as skipped if it isn't archived or walked.
+    #[cfg(feature = "std-fs")]
+    pub fn visit(&mut self, path: &Path, name: &OsStr, kind: EntryKind) -> Result<Visit> {
+        if kind == EntryKind::Symlink && !self.options.store_symlinks {
+            let rel_path = self.rel_path(path)?;
+            tracing::debug!("symlink: {path:?}");
+            self.skipped += 1;
+            let key = archive_path(rel_path);
+            self.symlinks
+                .push(key.unwrap_or_else(|| rel_path.to_string_lossy().into_owned()));
+            return Ok(Visit::Skip);
+        }
+        if self.options.skip_hidden && name.as_encoded_bytes().starts_with(b".") {
+            tracing::debug!("hidden: {path:?}");
+            self.skipped += 1;
+            return Ok(Visit::Skip);
+        }
+        if self.ignores.is_ignored(path, kind == EntryKind::Dir) {
+            tracing::debug!("ignored: {path:?}");
+            self.skipped += 1;
+            return Ok(Visit::Skip);
+        }
+        let file_len = match kind {
+            EntryKind::File(len) => len,
+            EntryKind::Symlink => 0,
+            EntryKind::Dir => return Ok(Visit::Dir),
+        };
+        if self.outputs.contains(path) {
+            tracing::warn!("skipping an output of the archive: {path:?}");
+            self.skipped += 1;
+            return Ok(Visit::Skip);
+        }
+
+        let rel_path = self.rel_path(path)?;
+        let Some(rel_path) = archive_path(rel_path) else {
+            tracing::error!("path is not valid UTF-8: {rel_path:?}");
+            return Err(MonolithicaError::InvalidPath(format!(
+                "path is not valid UTF-8: {rel_path:?}"
+            )));
+        };
+        if !self.filter.matches(&rel_path) {
+            tracing::debug!("filtered out: {rel_path}");
+            self.skipped += 1;
+            return Ok(Visit::Skip);
+        }
+        let rel_path = self.key(&rel_path)?;
+        if self.claimed.contains(&rel_path) {
+            tracing::debug!("overridden: {rel_path}");
+            self.skipped += 1;
+            self.overridden.push(rel_path);
+            return Ok(Visit::Skip);
+        }
+        if let Some(max_file_size) = self.options.max_file_size.filter(|&max| file_len > max) {
+            match self.options.oversized {
+                OversizedFiles::Skip => {
+                    tracing::warn!("skipping {rel_path}, {file_len} bytes");
+                    self.skipped += 1;
+                    self.oversized.push(rel_path);
+                    return Ok(Visit::Skip);
+                }
+                OversizedFiles::Error => {
+                    tracing::error!("file too large: {rel_path}");
+                    return Err(MonolithicaError::FileTooLarge {
+                        path: rel_path,
+                        len: file_len,
+                        max: max_file_size,
+                    });
+                }
+            }
+        }
+        if !self.claim_path(&rel_path)? {
+            return Ok(Visit::Skip);
+        }
+        match kind {
+            EntryKind::Symlink => Ok(Visit::Symlink(rel_path)),
+            _ => Ok(Visit::File(rel_path)),
+        }
+    }
+
+    /// Records the symlink at `rel_path` pointing at `target`, see
+    /// [`ArchiveOptions::store_symlinks`].
+    #[cfg(feature = "std-fs")]
+    pub fn add_symlink(&mut self, rel_path: String, target: &Path) -> Result<()> {
+        let stored = link_target(target).filter(|stored| resolve_link(&rel_path, stored).is_some());
+        let Some(stored) = stored else {
+            tracing::warn!("skipping symlink {rel_path} to {target:?}, outside the archive");
+            self.skipped += 1;
+            self.symlinks.push(rel_path);
+            return Ok(());
+        };
+        let asset = Asset {
+            offset: self.offset,
+            symlink: Some(stored),
+            shard: self.shard,
+            ..Default::default()
+        };
+        self.push_entry(rel_path, asset);
+        Ok(())
+    }
+
+    /// The archive path of the file at `rel_path` in the source, see
+    /// [`ArchiveOptions::strip_prefix`].
+    #[cfg(feature = "std-fs")]
+    fn key(&self, rel_path: &str) -> Result<String> {
+        let Some(stripped) = rel_path.strip_prefix(self.strip_prefix.as_str()) else {
+            tracing::error!("{rel_path} is not under {}", self.strip_prefix);
+            return Err(MonolithicaError::InvalidPath(format!(
+                "{rel_path} is not under the stripped prefix {}",
+                self.strip_prefix
+            )));
+        };
+        Ok(format!("{}{stripped}", self.add_prefix))
+    }
+
+    #[cfg(feature = "std-fs")]
+    fn add_file(&mut self, path: &Path, rel_path: String) -> Result<()> {
+        #[cfg(feature = "rayon")]
+        if self.options.parallel {
+            let mime = self.mimes.resolve(&rel_path);
+            return self.queue(rel_path, Pending::File(path.to_owned(), mime));
+        }
+
+        let mime = self.mimes.resolve(&rel_path);
+        if self.encodes(mime.as_deref()) {
+            let prepared = prepare_file(path, &rel_path, mime, self.options)?;
+            return self.write_prepared(rel_path, prepared);
+        }
+
+        let file = File::open(path).at(path)?;
+        let metadata = file.metadata().at(path)?;
+        let meta = FileMeta::of(self.options, &metadata);
+        let file_len = metadata.len();
+        let buffer_size = self.options.buffer_size.unwrap_or(DEFAULT_BUFFER_SIZE);
+        // Too large to hold in memory while finding out whether it's a
+        // duplicate, so it's hashed in a pass of its own.
+        let key = match self.options.dedup && file_len > MAX_BUFFERED_LEN {
+            true => {
+                let mut hasher = ContentHasher::default();
+                File::open(path)
+                    .and_then(|file| {
+                        io::copy(
+                            &mut BufReader::with_capacity(buffer_size, file),
+                            &mut hasher,
+                        )
+                    })
+                    .at(path)?;
+                Some(hasher.finish())
+            }
+            false => None,
+        };
+        if key.is_some_and(|key| self.add_duplicate(&rel_path, &mime, meta, key)) {
+            return Ok(());
+        }
+
+        let reader = BufReader::with_capacity(buffer_size, file);
+        self.copy_raw(rel_path, mime, meta, reader, Some((path, file_len)), key)
+    }
+
+    /// Points `rel_path` at the asset already stored with the content of
+    /// `key`, if there is one.
+    pub fn add_duplicate(
+        &mut self,
+        rel_path: &str,
+        mime: &Option<String>,
+        meta: FileMeta,
+        key: ContentKey,
+    ) -> bool {
+        let Some(&stored) = self.stored.get(&key) else {
+            return false;
+        };
+        let (stored_path, asset) = &self.entries[stored];
+        tracing::debug!("{rel_path} duplicates {stored_path}");
+        let asset = Asset {
+            mime: mime.clone(),
+            mtime: meta.mtime,
+            mode: meta.mode,
+            ..asset.clone()
+        };
+        self.push_entry(rel_path.to_owned(), asset);
+        self.duplicates += 1;
+        true
+    }
+
+    fn push_entry(&mut self, rel_path: String, asset: Asset) {
+        self.entries.push((rel_path, asset));
+        if let Some(progress) = &mut self.progress {
+            progress(&ProgressEvent::File {
+                path: &self.entries[self.entries.len() - 1].0,
+                bytes_written: self.previous_shards + self.offset - HEADER_LEN,
+                file_count: self.entries.len(),
+            });
+        }
+    }
+
+    /// Starts the next shard if `len` more bytes don't fit in the current one,
+    /// unless it's still empty.
+    pub fn reserve(&mut self, len: u64) -> Result<()> {
+        let Some(shard_size) = self.options.shard_size else {
+            return Ok(());
+        };
+        if self.offset == HEADER_LEN || self.offset.saturating_add(len) <= shard_size {
+            return Ok(());
+        }
+        let Some(next_shard) = &mut self.next_shard else {
+            tracing::error!("sharding not supported here");
+            return Err(MonolithicaError::Unsupported(
+                "sharded blobs can only be written to files".to_owned(),
+            ));
+        };
+        next_shard(&mut self.blob, self.offset - HEADER_LEN)?;
+        self.shard += 1;
+        self.previous_shards += self.offset - HEADER_LEN;
+        self.offset = HEADER_LEN;
+        Ok(())
+    }
+
+    /// Records the asset added last as holding the content of `key`.
+    pub fn remember(&mut self, key: ContentKey) {
+        self.stored.entry(key).or_insert(self.entries.len() - 1);
+    }
+
+    /// Whether files of `mime` are encoded or encrypted, so have to be read
+    /// whole rather than streamed into the blob.
+    pub fn encodes(&self, mime: Option<&str>) -> bool {
+        encodes(self.options, mime)
+    }
+
+    /// Records a file of `len` bytes streamed into the blob as is, with the
+    /// CRC32 and content hash [`Checksummer::finish`] computed for it.
+    pub fn push_raw(
+        &mut self,
+        rel_path: String,
+        mime: Option<String>,
+        meta: FileMeta,
+        len: u64,
+        (crc32, content_hash): (u32, Option<String>),
+    ) {
+        let asset = Asset {
+            offset: self.offset,
+            len,
+            mime,
+            original_len: len,
+            crc32: Some(crc32),
+            mtime: meta.mtime,
+            mode: meta.mode,
+            content_hash,
+            shard: self.shard,
+            ..Default::default()
+        };
+        self.offset += len;
+        self.push_entry(rel_path, asset);
+    }
+
+    /// Adds `entry` to the pending ones, reading them once there's a batch.
+    #[cfg(feature = "rayon")]
+    fn queue(&mut self, rel_path: String, entry: Pending) -> Result<()> {
+        self.pending.push((rel_path, entry));
+        if self.pending.len() >= PARALLEL_BATCH {
+            self.flush_pending()?;
+        }
+        Ok(())
+    }
+
+    /// Reads and encodes, or hashes, the pending files on the rayon thread
+    /// pool, then records the pending entries in the order they were walked,
+    /// so the output is the same as in serial mode.
+    #[cfg(feature = "rayon")]
+    fn flush_pending(&mut self) -> Result<()> {
+        use rayon::prelude::*;
+
+        let pending = std::mem::take(&mut self.pending);
+        let options = self.options;
+        let prepared: Vec<_> = pending
+            .par_iter()
+            .map(|(rel_path, entry)| match entry {
+                Pending::File(path, mime) if encodes(options, mime.as_deref()) => {
+                    Some(prepare_file(path, rel_path, mime.clone(), options).map(Prepared::Encoded))
+                }
+                Pending::File(path, _) => {
+                    Some(hash_file(path, rel_path, options).map(Prepared::Hashed))
+                }
+                Pending::Symlink(_) => None,
+            })
+            .collect();
+        for ((rel_path, entry), prepared) in pending.into_iter().zip(prepared) {
+            match (entry, prepared) {
+                (Pending::Symlink(target), _) => self.add_symlink(rel_path, &target)?,
+                (Pending::File(path, mime), Some(prepared)) => match prepared? {
+                    Prepared::Encoded(prepared) => self.write_prepared(rel_path, prepared)?,
+                    Prepared::Hashed(hashed) => self.write_hashed(&path, rel_path, mime, hashed)?,
+                },
+                (Pending::File(..), None) => unreachable!("every pending file is prepared"),
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies the file at `path` into the blob as is, or points `rel_path` at
+    /// the asset already holding its content, with the checksums computed
+    /// for it by [`hash_file`].
+    #[cfg(feature = "rayon")]
+    fn write_hashed(
+        &mut self,
+        path: &Path,
+        rel_path: String,
+        mime: Option<String>,
+        hashed: HashedFile,
+    ) -> Result<()> {
+        if let Some(key) = hashed.key {
+            if self.add_duplicate(&rel_path, &mime, hashed.meta, key) {
+                return Ok(());
+            }
+        }
+
+        self.reserve(hashed.len)?;
+        let buffer_size = self.options.buffer_size.unwrap_or(DEFAULT_BUFFER_SIZE);
+        let copied = File::open(path)
+            .and_then(|file| {
+                io::copy(
+                    &mut BufReader::with_capacity(buffer_size, file),
+                    &mut self.blob,
+                )
+            })
+            .at(path)?;
+        check_len(&rel_path, copied, Some(hashed.len))?;
+        let checksums = (hashed.crc32, hashed.content_hash);
+        self.push_raw(rel_path, mime, hashed.meta, copied, checksums);
+        if let Some(key) = hashed.key {
+            self.remember(key);
+        }
+        Ok(())
+    }
+
+    /// Archives `data`, already read from the file at `path` with `meta`.
+    #[cfg(feature = "tokio")]
+    pub fn add_data(
+        &mut self,
+        rel_path: String,
+        data: Vec<u8>,
+        mime: Option<String>,
+        meta: FileMeta,
+    ) -> Result<()> {
+        let prepared = prepare_data(data, mime, meta, self.options)?;
+        self.write_prepared(rel_path, prepared)
+    }
+
+    fn write_prepared(&mut self, rel_path: String, prepared: PreparedFile) -> Result<()> {
+        if let Some(key) = prepared.key {
+            if self.add_duplicate(&rel_path, &prepared.mime, prepared.meta, key) {
+                return Ok(());
+            }
+        }
+
+        #[cfg(feature = "encryption")]
+        let (stored, nonce) = match &self.options.encryption_key {
+            Some(key) => {
+                let (nonce, sealed) = key.encrypt(&rel_path, &prepared.stored)?;
+                (sealed, Some(nonce))
+            }
+            None => (prepared.stored, None),
+        };
+        #[cfg(not(feature = "encryption"))]
+        let (stored, nonce) = (prepared.stored, None);
+
+        let variants_len: usize = prepared.variants.iter().map(|(_, v)| v.len()).sum();
+        self.reserve((stored.len() + variants_len) as u64)?;
+        let mut asset = Asset {
+            offset: self.offset,
+            len: stored.len() as u64,
+            mime: prepared.mime,
+            encoding: prepared.encoding,
+            original_len: prepared.original_len,
+            crc32: Some(prepared.crc32),
+            mtime: prepared.meta.mtime,
+            mode: prepared.meta.mode,
+            content_hash: prepared.content_hash,
+            shard: self.shard,
+            nonce,
+            ..Default::default()
+        };
+        self.blob.write_all(&stored)?;
+        self.offset += asset.len;
+
+        for (encoding, encoded) in prepared.variants {
+            asset.variants.push(AssetVariant {
+                offset: self.offset,
+                len: encoded.len() as u64,
+                encoding,
+            });
+            self.offset += encoded.len() as u64;
+            self.blob.write_all(&encoded)?;
+        }
+
+        self.push_entry(rel_path, asset);
+        if let Some(key) = prepared.key {
+            self.remember(key);
+        }
+        Ok(())
+    }
+}
+
+/// `prefix` normalized to a directory prefix with a trailing `/`, empty for
+/// none.
+#[cfg(feature = "std-fs")]
+fn key_prefix(prefix: Option<&str>) -> Result<String> {
+    let prefix = prefix.unwrap_or_default();
+    let Some(normalized) = normalize_path(prefix) else {
+        tracing::error!("invalid prefix: {prefix}");
+        return Err(MonolithicaError::InvalidInput(format!(
+            "prefix {prefix:?} has a `..` segment"
+        )));
+    };
+    Ok(query::dir_prefix(&normalized))
+}
+
+/// An asset to archive from memory rather than from a file: its archive path
+/// and a reader of its bytes.
+pub(crate) type Entry<'r> = (String, Box<dyn Read + 'r>);
+
+/// What to archive.
+pub(crate) enum Source<'s> {
+    /// The files under these directories, the last one first, leaving out the
+    /// outputs found in them.
+    #[cfg(feature = "std-fs")]
+    Dirs(&'s [&'s Path], Outputs),
+    Entries(&'s mut dyn Iterator<Item = Entry<'s>>),
+}
+
+impl Source<'_> {
+    /// Leaves `outputs` out of the directories walked.
+    #[cfg(feature = "std-fs")]
+    pub fn writing_to(self, outputs: Outputs) -> Self {
+        match self {
+            Source::Dirs(src_dirs, _) => Source::Dirs(src_dirs, outputs),
+            entries => entries,
+        }
+    }
+}
+
+/// Identifies the content of a file for [`ArchiveOptions::dedup`] by its
+/// length and SHA-256, so only files with the same bytes share a region of
+/// the blob.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct ContentKey {
+    len: u64,
+    sha256: [u8; 32],
+}
+
+#[derive(Default)]
+pub(crate) struct ContentHasher {
+    len: u64,
+    sha256: Sha256,
+}
+
+impl ContentHasher {
+    pub fn update(&mut self, buf: &[u8]) {
+        self.len += buf.len() as u64;
+        self.sha256.update(buf);
+    }
+
+    pub fn finish(self) -> ContentKey {
+        ContentKey {
+            len: self.len,
+            sha256: self.sha256.finalize().into(),
+        }
+    }
+}
+
+impl Write for ContentHasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A directory entry as [`Packer::visit`] needs to know it.
+#[cfg(feature = "std-fs")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum EntryKind {
+    /// A file, or a symlink to one when symlinks are followed, and its size.
+    File(u64),
+    Dir,
+    /// A symlink that isn't followed, archived as such with
+    /// [`ArchiveOptions::store_symlinks`].
+    Symlink,
+}
+
+#[cfg(feature = "std-fs")]
+pub(crate) enum Visit {
+    Skip,
+    /// Archive the file under this path.
+    File(String),
+    /// Record the symlink under this path.
+    Symlink(String),
+    /// Walk the directory.
+    Dir,
+}
+
+/// Buffer size for [`ArchiveOptions::buffer_size`] left unset.
+#[cfg(feature = "std-fs")]
+pub(crate) const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Computes the checksums recorded for each asset as its bytes go by: the
+/// CRC32, and the content hash if [`ArchiveOptions::content_hash`] is set.
+pub(crate) struct Checksummer {
+    crc32: crc32fast::Hasher,
+    sha256: Option<Sha256>,
+}
+
+impl Checksummer {
+    pub fn new(options: &ArchiveOptions) -> Self {
+        Self {
+            crc32: crc32fast::Hasher::new(),
+            sha256: options.hashes_content().then(Sha256::new),
+        }
+    }
+
+    pub fn update(&mut self, buf: &[u8]) {
+        self.crc32.update(buf);
+        if let Some(sha256) = &mut self.sha256 {
+            sha256.update(buf);
+        }
+    }
+
+    /// The CRC32 and the content hash.
+    pub fn finish(self) -> (u32, Option<String>) {
+        let content_hash = self.sha256.map(|sha256| {
+            sha256.finalize()[..8]
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect()
+        });
+        (self.crc32.finalize(), content_hash)
+    }
+}
+
+impl Write for Checksummer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes to `inner` and passes the bytes it took on to `tap`, so a single
+/// read of a file feeds the blob and the hashers. Taps chain, as in
+/// `Tee::new(blob, Tee::new(checksummer, content_hasher))`.
+pub(crate) struct Tee<W, T> {
+    inner: W,
+    tap: T,
+}
+
+impl<W, T> Tee<W, T> {
+    pub fn new(inner: W, tap: T) -> Self {
+        Self { inner, tap }
+    }
+
+    pub fn into_parts(self) -> (W, T) {
+        (self.inner, self.tap)
+    }
+}
+
+impl<W: Write, T: Write> Write for Tee<W, T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.tap.write_all(&buf[..n])?;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()?;
+        self.tap.flush()
+    }
+}
+
+/// Files up to this size are held in memory while deduplicating rather than
+/// read twice, see [`Packer::copy_raw`].
+pub(crate) const MAX_BUFFERED_LEN: u64 = 16 << 20;
+
+/// Fails if `copied` bytes were archived for `rel_path` when `expected` were.
+fn check_len(rel_path: &str, copied: u64, expected: Option<u64>) -> Result<()> {
+    match expected {
+        Some(expected) if copied != expected => {
+            tracing::error!("file changed while archiving: {rel_path}");
+            Err(MonolithicaError::FileChanged {
+                path: rel_path.to_owned(),
+                read: copied,
+                expected,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Number of files read into memory at once in parallel mode.
+#[cfg(feature = "rayon")]
+const PARALLEL_BATCH: usize = 256;
+
+/// An entry walked in parallel mode, recorded once the files before it are
+/// read.
+#[cfg(feature = "rayon")]
+enum Pending {
+    /// A file to read from this path, of this mime.
+    File(PathBuf, Option<String>),
+    /// A symlink to this target, see [`Packer::add_symlink`].
+    Symlink(PathBuf),
+}
+
+/// What the rayon thread pool did with a pending file ahead of writing it.
+#[cfg(feature = "rayon")]
+enum Prepared {
+    /// Read it whole and encoded it.
+    Encoded(PreparedFile),
+    /// Streamed it to hash it, to be copied into the blob as is.
+    Hashed(HashedFile),
+}
+
+/// The checksums of a file stored as is, computed in parallel mode without
+/// holding the file in memory, see [`hash_file`].
+#[cfg(feature = "rayon")]
+struct HashedFile {
+    len: u64,
+    crc32: u32,
+    content_hash: Option<String>,
+    /// Set when deduplicating.
+    key: Option<ContentKey>,
+    meta: FileMeta,
+}
+
+/// A file read and encoded, waiting to be written to the blob.
+struct PreparedFile {
+    mime: Option<String>,
+    original_len: u64,
+    crc32: u32,
+    content_hash: Option<String>,
+    /// The bytes to store, encoded with `encoding`.
+    stored: Vec<u8>,
+    encoding: Option<Encoding>,
+    variants: Vec<(Encoding, Vec<u8>)>,
+    /// Set when deduplicating.
+    key: Option<ContentKey>,
+    meta: FileMeta,
+}
+
+/// Whether assets of `mime` are compressed, see
+/// [`ArchiveOptions::compressible_mimes`].
+fn is_compressible(options: &ArchiveOptions, mime: Option<&str>) -> bool {
+    let Some(mime) = mime else {
+        return false;
+    };
+    match &options.compressible_mimes {
+        Some(mimes) => mimes.iter().any(|m| mime_essence(m) == mime_essence(mime)),
+        None => is_compressible_mime(mime),
+    }
+}
+
+/// See [`Packer::encodes`].
+fn encodes(options: &ArchiveOptions, mime: Option<&str>) -> bool {
+    #[cfg(feature = "encryption")]
+    if options.encryption_key.is_some() {
+        return true;
+    }
+    compression(options, mime).is_some() || !precompressed_encodings(options, mime).is_empty()
+}
+
+/// The encoding to store an asset of `mime` with.
+fn compression(options: &ArchiveOptions, mime: Option<&str>) -> Option<Encoding> {
+    options
+        .compression
+        .filter(|_| is_compressible(options, mime))
+}
+
+/// The encodings to store variants of an asset of `mime` with.
+fn precompressed_encodings<'o>(options: &'o ArchiveOptions, mime: Option<&str>) -> &'o [Encoding] {
+    match is_compressible(options, mime) {
+        true => &options.precompressed,
+        false => &[],
+    }
+}
+
+/// Reads the file at `path`, archived at `rel_path`, and encodes it as set by
+/// `options`. Fails if the file's length changes while it's read.
+#[cfg(feature = "std-fs")]
+fn prepare_file(
+    path: &Path,
+    rel_path: &str,
+    mime: Option<String>,
+    options: &ArchiveOptions,
+) -> Result<PreparedFile> {
+    let mut file = File::open(path).at(path)?;
+    let metadata = file.metadata().at(path)?;
+    let meta = FileMeta::of(options, &metadata);
+    let mut data = Vec::new();
+    file.read_to_end(&mut data).at(path)?;
+    check_len(rel_path, data.len() as u64, Some(metadata.len()))?;
+    prepare_data(data, mime, meta, options)
+}
+
+/// Streams the file at `path`, archived at `rel_path`, through the hashers
+/// `options` ask for. Fails if the file's length changes while it's read.
+#[cfg(feature = "rayon")]
+fn hash_file(path: &Path, rel_path: &str, options: &ArchiveOptions) -> Result<HashedFile> {
+    use std::io::BufRead;
+
+    let file = File::open(path).at(path)?;
+    let metadata = file.metadata().at(path)?;
+    let buffer_size = options.buffer_size.unwrap_or(DEFAULT_BUFFER_SIZE);
+    let mut reader = BufReader::with_capacity(buffer_size, file);
+    let mut checksummer = Checksummer::new(options);
+    let mut hasher = options.dedup.then(ContentHasher::default);
+    let mut len = 0;
+    loop {
+        let buf = reader.fill_buf().at(path)?;
+        if buf.is_empty() {
+            break;
+        }
+        checksummer.update(buf);
+        if let Some(hasher) = &mut hasher {
+            hasher.update(buf);
+        }
+        let read = buf.len();
+        reader.consume(read);
+        len += read as u64;
+    }
+    check_len(rel_path, len, Some(metadata.len()))?;
+    let (crc32, content_hash) = checksummer.finish();
+    Ok(HashedFile {
+        len,
+        crc32,
+        content_hash,
+        key: hasher.map(ContentHasher::finish),
+        meta: FileMeta::of(options, &metadata),
+    })
+}
+
+/// Encodes `data`, the content of a file of `mime`, as set by `options`.
+/// Encodings that don't shrink it are dropped.
+fn prepare_data(
+    data: Vec<u8>,
+    mime: Option<String>,
+    meta: FileMeta,
+    options: &ArchiveOptions,
+) -> Result<PreparedFile> {
+    let compression = compression(options, mime.as_deref());
+    let encoded = match compression {
+        Some(encoding) => Some(encoding.encode(&data)?).filter(|e| e.len() < data.len()),
+        None => None,
+    };
+    let encoding = encoded.is_some().then_some(compression).flatten();
+
+    let mut variants = Vec::new();
+    for &variant in precompressed_encodings(options, mime.as_deref()) {
+        if encoding == Some(variant) {
+            continue;
+        }
+        let encoded = variant.encode(&data)?;
+        if encoded.len() < data.len() {
+            variants.push((variant, encoded));
+        }
+    }
+
+    let key = options.dedup.then(|| {
+        let mut hasher = ContentHasher::default();
+        hasher.update(&data);
+        hasher.finish()
+    });
+    let mut hasher = Checksummer::new(options);
+    hasher.update(&data);
+    let (crc32, content_hash) = hasher.finish();
+    Ok(PreparedFile {
+        mime,
+        original_len: data.len() as u64,
+        crc32,
+        content_hash,
+        encoding,
+        stored: encoded.unwrap_or(data),
+        variants,
+        key,
+        meta,
+    })
+}
+
+/// File system metadata of an archived file recorded in its index entry.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct FileMeta {
+    pub mtime: Option<u64>,
+    pub mode: Option<u32>,
+}
+
+impl FileMeta {
+    /// What to record for a file with `metadata`, as set by `options`.
+    #[cfg(feature = "std-fs")]
+    pub fn of(options: &ArchiveOptions, metadata: &fs::Metadata) -> Self {
+        Self {
+            mtime: file_mtime(options, metadata),
+            mode: file_mode(options, metadata),
+        }
+    }
+}
+
+/// The permission bits to record for a file with `metadata`, see
+/// [`ArchiveOptions::mode`].
+#[cfg(all(feature = "std-fs", unix))]
+fn file_mode(options: &ArchiveOptions, metadata: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+
+    options.mode.then(|| metadata.permissions().mode() & 0o7777)
+}
+
+#[cfg(all(feature = "std-fs", not(unix)))]
+fn file_mode(_: &ArchiveOptions, _: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+/// The modification time to record for a file with `metadata`, see
+/// [`ArchiveOptions::mtime`].
+#[cfg(feature = "std-fs")]
+fn file_mtime(options: &ArchiveOptions, metadata: &fs::Metadata) -> Option<u64> {
+    if !options.mtime {
+        return None;
+    }
+    let modified = metadata.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_secs())
+}
+
+#[cfg(all(test, feature = "rayon"))]
+mod tests {
+    #[cfg(any(feature = "gzip", feature = "brotli"))]
+    use crate::Encoding;
+    use crate::{
+        test_util::{fixture_dir, write_file},
+        ArchiveBuilder,
+    };
+    use std::{fs, path::Path, time::Instant};
+
+    fn write_tree(src: &Path, count: usize, size: usize) {
+        for i in 0..count {
+            let content = format!("console.log({i});\n").repeat(size / 16 + 1);
+            write_file(&src.join(format!("{}/{i}.js", i % 16)), &content[..size]);
+        }
+    }
+
+    /// Sets up the build of a test case.
+    type Configure = fn(ArchiveBuilder) -> ArchiveBuilder;
+
+    /// Encodes the files with whichever encodings are enabled.
+    #[cfg(any(feature = "gzip", feature = "brotli"))]
+    fn compressed(builder: ArchiveBuilder) -> ArchiveBuilder {
+        #[cfg(feature = "gzip")]
+        let builder = builder.compression(Encoding::Gzip);
+        #[cfg(feature = "brotli")]
+        let builder = builder.precompressed([Encoding::Brotli]);
+        builder
+    }
+
+    /// Stores the files raw, but hashes them, so hashing is most of the work.
+    fn hashed(builder: ArchiveBuilder) -> ArchiveBuilder {
+        builder.content_hash(true).dedup(true)
+    }
+
+    fn build(root: &Path, name: &str, parallel: bool, configure: Configure) -> (Vec<u8>, Vec<u8>) {
+        let (blob, index) = (
+            root.join(format!("{name}.blob")),
+            root.join(format!("{name}.idx")),
+        );
+        let builder = ArchiveBuilder::new()
+            .source(root.join("src"))
+            .blob(&blob)
+            .index(&index)
+            .overwrite(true)
+            .deterministic(true)
+            .store_symlinks(true)
+            .parallel(parallel);
+        configure(builder).build().unwrap();
+        (fs::read(blob).unwrap(), fs::read(index).unwrap())
+    }
+
+    #[test]
+    fn parallel_matches_serial() {
+        let root = fixture_dir("packer_parallel_matches_serial");
+        write_tree(&root.join("src"), 300, 100);
+        write_file(&root.join("src/empty.bin"), "");
+        write_file(
+            &root.join("src/copy/0.js"),
+            fs::read(root.join("src/0/0.js")).unwrap(),
+        );
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("0.js", root.join("src/0/link.js")).unwrap();
+        let configs: &[Configure] = &[
+            #[cfg(any(feature = "gzip", feature = "brotli"))]
+            compressed,
+            hashed,
+        ];
+        for &configure in configs {
+            assert_eq!(
+                build(&root, "serial", false, configure),
+                build(&root, "parallel", true, configure)
+            );
+        }
+        let (_, index) = build(&root, "parallel", true, hashed);
+        let index = String::from_utf8(index).unwrap();
+        assert!(index.contains("//hash="));
+        #[cfg(unix)]
+        assert!(index.contains("//link="), "{index}");
+    }
+
+    /// Run with `cargo test --release --all-features -- --ignored
+    /// bench_parallel --nocapture`.
+    #[test]
+    #[ignore = "benchmark, run with --ignored --nocapture"]
+    fn bench_parallel() {
+        let root = fixture_dir("packer_bench_parallel");
+        write_tree(&root.join("src"), 5_000, 16 * 1024);
+        let configs: &[(_, Configure)] = &[
+            #[cfg(any(feature = "gzip", feature = "brotli"))]
+            ("compressed", compressed),
+            ("hashed", hashed),
+        ];
+        for &(name, configure) in configs {
+            for parallel in [false, true] {
+                let start = Instant::now();
+                build(&root, "bench", parallel, configure);
+                println!("{name}, parallel={parallel}: {:?}", start.elapsed());
+            }
+        }
+    }
+}
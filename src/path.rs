@@ -0,0 +1,196 @@
+use std::borrow::Cow;
+#[cfg(feature = "std-fs")]
+use std::path::{Component, Path};
+
+/// Normalizes a lookup path to the form assets are stored under: backslashes
+/// become slashes, leading `/` and `./`, `.` segments and repeated slashes are
+/// dropped. Returns `None` if the path contains a `..` segment, as it could
+/// escape the archive root.
+///
+/// ```
+/// use monolithica::normalize_path;
+///
+/// assert_eq!(normalize_path("/js/./app.js").as_deref(), Some("js/app.js"));
+/// assert_eq!(normalize_path(".\\index.html").as_deref(), Some("index.html"));
+/// assert_eq!(normalize_path("/../etc/passwd"), None);
+/// ```
+pub fn normalize_path(path: &str) -> Option<Cow<'_, str>> {
+    let is_plain = |segment: &str| !matches!(segment, "" | "." | "..");
+    if !path.contains('\\') && path.split('/').all(is_plain) {
+        return Some(Cow::Borrowed(path));
+    }
+
+    let mut normalized = String::with_capacity(path.len());
+    for segment in path.split(['/', '\\']) {
+        match segment {
+            "" | "." => continue,
+            ".." => return None,
+            _ => {
+                if !normalized.is_empty() {
+                    normalized.push('/');
+                }
+                normalized.push_str(segment);
+            }
+        }
+    }
+    Some(Cow::Owned(normalized))
+}
+
+/// Whether `path` looks like it names a static file rather than a route of a
+/// single-page app: its last segment has an extension, like `app.js` or
+/// `logo.v2.png`, unlike `users/42`, `.well-known` or `docs/`. Requests for
+/// such paths don't get an SPA fallback, so a missing script is a 404 rather
+/// than the app's HTML.
+///
+/// ```
+/// use monolithica::looks_like_file;
+///
+/// assert!(looks_like_file("/js/app.js"));
+/// assert!(!looks_like_file("/users/42"));
+/// ```
+pub fn looks_like_file(path: &str) -> bool {
+    let name = path.rsplit(['/', '\\']).next().unwrap_or_default();
+    matches!(name.rfind('.'), Some(dot) if dot > 0 && dot + 1 < name.len())
+}
+
+/// The archive path of `rel_path`, a path relative to the source directory:
+/// its components joined by `/` whatever the host's separator, so archives
+/// built on Windows and elsewhere store the same keys. `None` if a component
+/// isn't valid UTF-8.
+#[cfg(feature = "std-fs")]
+pub(crate) fn archive_path(rel_path: &Path) -> Option<String> {
+    let mut key = String::with_capacity(rel_path.as_os_str().len());
+    for component in rel_path.components() {
+        let Component::Normal(name) = component else {
+            continue;
+        };
+        if !key.is_empty() {
+            key.push('/');
+        }
+        key.push_str(name.to_str()?);
+    }
+    Some(key)
+}
+
+/// The target of a symlink as stored in its entry, see
+/// [`crate::Asset::symlink`]: its components joined by `/`. `None` if it's
+/// absolute or a component isn't valid UTF-8.
+#[cfg(feature = "std-fs")]
+pub(crate) fn link_target(target: &Path) -> Option<String> {
+    let mut stored = Vec::new();
+    for component in target.components() {
+        match component {
+            Component::Normal(name) => stored.push(name.to_str()?),
+            Component::ParentDir => stored.push(".."),
+            Component::CurDir => {}
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    (!stored.is_empty()).then(|| stored.join("/"))
+}
+
+/// The archive path that the symlink entry at `link` pointing at `target`
+/// refers to, `""` for the archive root. `None` if `target` is absolute,
+/// leads out of the archive root, or has a `..` after a name: that name could
+/// itself be a link, so the `..` wouldn't undo it.
+#[cfg(feature = "std-fs")]
+pub(crate) fn resolve_link(link: &str, target: &str) -> Option<String> {
+    if target.is_empty() || target.starts_with('/') || target.contains(['\\', ':']) {
+        return None;
+    }
+    let mut resolved: Vec<&str> = link.split('/').collect();
+    resolved.pop();
+    let mut descending = false;
+    for segment in target.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." if descending => return None,
+            ".." => {
+                resolved.pop()?;
+            }
+            _ => {
+                descending = true;
+                resolved.push(segment);
+            }
+        }
+    }
+    Some(resolved.join("/"))
+}
+
+#[cfg(all(test, feature = "std-fs"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize() {
+        for (path, expected) in [
+            ("index.html", Some("index.html")),
+            ("/index.html", Some("index.html")),
+            ("./index.html", Some("index.html")),
+            ("\\js\\app.js", Some("js/app.js")),
+            ("js//./app.js", Some("js/app.js")),
+            ("js/app.js/", Some("js/app.js")),
+            ("/", Some("")),
+            ("js/../index.html", None),
+            ("..", None),
+            ("..\\secret", None),
+            ("js/..app.js", Some("js/..app.js")),
+        ] {
+            assert_eq!(normalize_path(path).as_deref(), expected, "{path}");
+        }
+        assert!(matches!(normalize_path("a/b"), Some(Cow::Borrowed(_))));
+    }
+
+    #[test]
+    fn forward_slash_keys() {
+        let rel_path: std::path::PathBuf = ["css", "themes", "app.css"].iter().collect();
+        assert_eq!(archive_path(&rel_path).unwrap(), "css/themes/app.css");
+        assert_eq!(archive_path(Path::new("./js/app.js")).unwrap(), "js/app.js");
+        #[cfg(windows)]
+        assert_eq!(
+            archive_path(Path::new(r"css\themes\app.css")).unwrap(),
+            "css/themes/app.css"
+        );
+    }
+
+    #[test]
+    fn symlink_targets() {
+        for (link, target, expected) in [
+            ("latest", "v2/app.js", Some("v2/app.js")),
+            ("js/app.js", "../v2/./app.js", Some("v2/app.js")),
+            ("a/b/c", "../..", Some("")),
+            ("a/b", "../../x", None),
+            ("a/b", "x/../y", None),
+            ("a", "/etc/passwd", None),
+            ("a", "C:/x", None),
+            ("a", "..\\x", None),
+            ("a", "", None),
+        ] {
+            assert_eq!(resolve_link(link, target).as_deref(), expected, "{target}");
+        }
+        assert_eq!(
+            link_target(Path::new("../v2/./app.js")).as_deref(),
+            Some("../v2/app.js")
+        );
+        assert_eq!(link_target(Path::new("/etc/passwd")), None);
+    }
+
+    #[test]
+    fn file_like_paths() {
+        for path in ["app.js", "/js/app.min.js", "a/b.c/d.png", "docs\\x.txt"] {
+            assert!(looks_like_file(path), "{path}");
+        }
+        for path in [
+            "",
+            "/",
+            "users/42",
+            "docs/",
+            "a.b/c",
+            ".well-known",
+            "v1.",
+            "x/.env",
+        ] {
+            assert!(!looks_like_file(path), "{path}");
+        }
+    }
+}
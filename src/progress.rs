@@ -0,0 +1,65 @@
+use crate::ArchiveSummary;
+#[cfg(feature = "std-fs")]
+use std::{
+    fmt,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+/// What [`crate::ArchiveBuilder::progress`] callbacks are told while an
+/// archive is created.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ProgressEvent<'a> {
+    /// A file was added to the archive.
+    File {
+        /// Its archive path.
+        path: &'a str,
+        /// Bytes of asset data written to the blob so far, before any
+        /// [`crate::ArchiveOptions::blob_compression`].
+        bytes_written: u64,
+        /// Number of files archived so far, this one included.
+        file_count: usize,
+    },
+    /// The blob and the index are written.
+    Done(&'a ArchiveSummary),
+}
+
+/// The callback the packer reports to, borrowed for the duration of a build.
+pub(crate) type Progress<'a> = &'a mut (dyn FnMut(&ProgressEvent) + Send + 'a);
+
+/// Reborrows `progress` for a shorter build step.
+pub(crate) fn reborrow<'s>(progress: &'s mut Option<Progress<'_>>) -> Option<Progress<'s>> {
+    match progress {
+        Some(progress) => Some(&mut **progress),
+        None => None,
+    }
+}
+
+/// A progress callback shared by the clones of a builder.
+#[cfg(feature = "std-fs")]
+#[derive(Clone)]
+pub(crate) struct ProgressCallback(Arc<Mutex<ProgressFn>>);
+
+#[cfg(feature = "std-fs")]
+type ProgressFn = Box<dyn FnMut(&ProgressEvent) + Send>;
+
+#[cfg(feature = "std-fs")]
+impl ProgressCallback {
+    pub fn new(callback: impl FnMut(&ProgressEvent) + Send + 'static) -> Self {
+        Self(Arc::new(Mutex::new(Box::new(callback))))
+    }
+
+    /// Calls the callback, locking it only for the call so builds may run
+    /// on other threads or across awaits.
+    pub fn report(&self, event: &ProgressEvent) {
+        let mut callback = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        callback(event);
+    }
+}
+
+#[cfg(feature = "std-fs")]
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
@@ -0,0 +1,158 @@
+//! Queries over the paths of an index, shared by [`crate::Archive`] and
+//! [`crate::AssetIndexer`].
+
+use crate::{filter::glob, Asset, Result};
+use std::collections::HashMap;
+
+/// The directory `prefix` names, with a trailing `/` unless it's the root.
+pub(crate) fn dir_prefix(prefix: &str) -> String {
+    let prefix = prefix.trim_matches('/');
+    match prefix.is_empty() {
+        true => String::new(),
+        false => format!("{prefix}/"),
+    }
+}
+
+/// The range of the `sorted` paths starting with `prefix`, found by binary
+/// search.
+fn starting_with<'a>(sorted: &'a [String], prefix: &str) -> &'a [String] {
+    let start = sorted.partition_point(|path| path.as_str() < prefix);
+    let len = sorted[start..].partition_point(|path| path.starts_with(prefix));
+    &sorted[start..start + len]
+}
+
+/// The paths under the directory `prefix`, at any depth, of the `sorted`
+/// paths, without scanning the others.
+pub(crate) fn list_prefix<'a>(sorted: &'a [String], prefix: &str) -> Vec<&'a str> {
+    starting_with(sorted, &dir_prefix(prefix))
+        .iter()
+        .map(String::as_str)
+        .collect()
+}
+
+/// All of `paths`, sorted, for positional access.
+pub(crate) fn sorted_paths<'a>(paths: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut sorted: Vec<_> = paths.map(str::to_owned).collect();
+    sorted.sort_unstable();
+    sorted
+}
+
+/// [`list_dir`] of the `sorted` paths, only going through those under
+/// `prefix`.
+pub(crate) fn list_sorted_dir<'a>(sorted: &'a [String], prefix: &str) -> Vec<&'a str> {
+    let under = starting_with(sorted, &dir_prefix(prefix));
+    list_dir(under.iter().map(String::as_str), prefix)
+}
+
+/// The files and directories right under the directory `prefix`, sorted,
+/// directories with a trailing `/`.
+pub(crate) fn list_dir<'a>(paths: impl Iterator<Item = &'a str>, prefix: &str) -> Vec<&'a str> {
+    let prefix = dir_prefix(prefix);
+    let mut found: Vec<_> = paths
+        .filter_map(|path| {
+            let rest = path.strip_prefix(&prefix)?;
+            let child_len = rest.find('/').map_or(rest.len(), |i| i + 1);
+            Some(&path[prefix.len()..prefix.len() + child_len])
+        })
+        .collect();
+    found.sort_unstable();
+    found.dedup();
+    found
+}
+
+/// The entries whose path matches the glob `pattern`, sorted by path.
+pub(crate) fn find<'a>(
+    entries: impl Iterator<Item = (&'a str, &'a Asset)>,
+    pattern: &str,
+) -> Result<Vec<(&'a str, &'a Asset)>> {
+    let matcher = glob(pattern)?.compile_matcher();
+    let mut found: Vec<_> = entries.filter(|(path, _)| matcher.is_match(path)).collect();
+    found.sort_unstable_by_key(|(path, _)| *path);
+    Ok(found)
+}
+
+/// The bytes the entries take in the blob, not counting precompressed
+/// variants.
+pub(crate) fn total_size<'a>(entries: impl Iterator<Item = (&'a str, &'a Asset)>) -> u64 {
+    entries.map(|(_, asset)| asset.len).sum()
+}
+
+/// The bytes and number of entries of each mime, see [`total_size`].
+pub(crate) fn size_by_mime<'a>(
+    entries: impl Iterator<Item = (&'a str, &'a Asset)>,
+) -> HashMap<String, (u64, usize)> {
+    let mut sizes = HashMap::new();
+    for (_, asset) in entries {
+        let (bytes, count) = sizes
+            .entry(asset.mime_or_default().to_owned())
+            .or_insert((0, 0));
+        *bytes += asset.len;
+        *count += 1;
+    }
+    sizes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATHS: [&str; 6] = [
+        "index.html",
+        "js/app.js",
+        "js/lib/util.js",
+        "js/lib/more/x.js",
+        "json/data.json",
+        "css/site.css",
+    ];
+
+    #[test]
+    fn prefix() {
+        let sorted = sorted_paths(PATHS.into_iter());
+        let list = |prefix| list_prefix(&sorted, prefix);
+        let js = vec!["js/app.js", "js/lib/more/x.js", "js/lib/util.js"];
+        assert_eq!(list("js"), js);
+        assert_eq!(list("js/"), js);
+        assert_eq!(list("/js/"), js);
+        assert_eq!(list("js/lib"), ["js/lib/more/x.js", "js/lib/util.js"]);
+        assert_eq!(list("").len(), PATHS.len());
+        assert!(list("j").is_empty());
+        assert!(list("index.html").is_empty());
+    }
+
+    #[test]
+    fn find_glob() {
+        let asset = Asset::default();
+        let find = |pattern| {
+            find(PATHS.into_iter().map(|path| (path, &asset)), pattern)
+                .unwrap()
+                .into_iter()
+                .map(|(path, _)| path)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            find("**/*.js"),
+            ["js/app.js", "js/lib/more/x.js", "js/lib/util.js"]
+        );
+        assert_eq!(find("js/*.js"), ["js/app.js"]);
+        assert_eq!(find("*.html"), ["index.html"]);
+        assert_eq!(find("{css,json}/**"), ["css/site.css", "json/data.json"]);
+        assert!(find("*.png").is_empty());
+        assert!(super::find(std::iter::empty(), "[").is_err());
+    }
+
+    #[test]
+    fn dir() {
+        let list = |prefix| list_dir(PATHS.into_iter(), prefix);
+        assert_eq!(list(""), ["css/", "index.html", "js/", "json/"]);
+        assert_eq!(list("/"), list(""));
+        assert_eq!(list("js"), ["app.js", "lib/"]);
+        assert_eq!(list("js/"), ["app.js", "lib/"]);
+        assert_eq!(list("js/lib"), ["more/", "util.js"]);
+        assert!(list("missing").is_empty());
+
+        let sorted = sorted_paths(PATHS.into_iter());
+        for prefix in ["", "js", "js/lib/", "j", "missing"] {
+            assert_eq!(list_sorted_dir(&sorted, prefix), list(prefix), "{prefix}");
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use crate::{MonolithicaError, Result};
+#[cfg(feature = "std-fs")]
+use std::fs::File;
+use std::{
+    io::{self, Read, Seek, SeekFrom},
+    sync::{Mutex, PoisonError},
+};
+
+/// A blob that can be read at any offset through a shared reference, so
+/// several threads can read from it at once without a shared cursor.
+pub trait ReadAt: Send + Sync {
+    /// Reads up to `buf.len()` bytes at `offset`, returning how many were read,
+    /// `0` at or past the end.
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize>;
+}
+
+impl ReadAt for [u8] {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let start = usize::try_from(offset)
+            .unwrap_or(usize::MAX)
+            .min(self.len());
+        let n = buf.len().min(self.len() - start);
+        buf[..n].copy_from_slice(&self[start..start + n]);
+        Ok(n)
+    }
+}
+
+impl ReadAt for Vec<u8> {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.as_slice().read_at(offset, buf)
+    }
+}
+
+/// Positioned reads, `pread` on unix, which leave the file cursor alone.
+#[cfg(all(unix, feature = "std-fs"))]
+impl ReadAt for File {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        std::os::unix::fs::FileExt::read_at(self, buf, offset)
+    }
+}
+
+/// Positioned reads, which on Windows also move the file cursor, harmless as
+/// every read names its offset.
+#[cfg(all(windows, feature = "std-fs"))]
+impl ReadAt for File {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        std::os::windows::fs::FileExt::seek_read(self, buf, offset)
+    }
+}
+
+/// A file readers can share: the file itself where it supports positioned
+/// reads, otherwise behind a lock.
+#[cfg(all(any(unix, windows), feature = "std-fs"))]
+pub(crate) type SharedFile = File;
+#[cfg(all(not(any(unix, windows)), feature = "std-fs"))]
+pub(crate) type SharedFile = Mutex<File>;
+
+#[cfg(feature = "std-fs")]
+pub(crate) fn share_file(file: File) -> SharedFile {
+    #[cfg(any(unix, windows))]
+    return file;
+    #[cfg(not(any(unix, windows)))]
+    return Mutex::new(file);
+}
+
+/// Any seekable reader, locked for each read since reading moves its cursor.
+impl<R: Read + Seek + Send> ReadAt for Mutex<R> {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let mut reader = self.lock().unwrap_or_else(PoisonError::into_inner);
+        reader.seek(SeekFrom::Start(offset))?;
+        reader.read(buf)
+    }
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for &T {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        (**self).read_at(offset, buf)
+    }
+}
+
+/// Size [`read_region_at`] starts reading a region into, so that the length of
+/// a corrupt index entry fails once the blob ends rather than on allocating it.
+const INITIAL_REGION_CAPACITY: usize = 64 * 1024;
+
+/// Reads the `len` bytes at `offset` of `blob`, failing if it ends before.
+pub(crate) fn read_region_at<R: ReadAt + ?Sized>(
+    blob: &R,
+    offset: u64,
+    len: u64,
+) -> Result<Vec<u8>> {
+    let len_usize = usize::try_from(len).unwrap_or(usize::MAX);
+    let mut buffer = vec![0; len_usize.min(INITIAL_REGION_CAPACITY)];
+    let mut filled = 0;
+    while filled < len_usize {
+        if filled == buffer.len() {
+            buffer.resize(len_usize.min(buffer.len().saturating_mul(2)), 0);
+        }
+        match blob.read_at(offset + filled as u64, &mut buffer[filled..]) {
+            Ok(0) => {
+                tracing::error!("blob too short, expected {len} bytes at offset {offset}");
+                return Err(MonolithicaError::BlobTooShort {
+                    offset,
+                    len,
+                    available: filled as u64,
+                });
+            }
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(buffer)
+}
+
+#[cfg(all(test, feature = "std-fs"))]
+mod tests {
+    use super::*;
+    use crate::{test_util::build_fixture, Archive, AssetIndexer};
+    use std::{fs, io::Cursor, sync::Arc, thread};
+
+    #[test]
+    fn read_regions() {
+        let data = b"0123456789".to_vec();
+        assert_eq!(read_region_at(&data, 2, 3).unwrap(), b"234");
+        assert_eq!(read_region_at(&data[..], 10, 0).unwrap(), b"");
+        let err = read_region_at(&Mutex::new(Cursor::new(&data)), 8, 4).unwrap_err();
+        assert!(matches!(
+            err,
+            MonolithicaError::BlobTooShort {
+                offset: 8,
+                len: 4,
+                available: 2
+            }
+        ));
+
+        let data = vec![7; 3 * INITIAL_REGION_CAPACITY];
+        assert_eq!(
+            read_region_at(&data, 1, data.len() as u64 - 1).unwrap(),
+            &data[1..]
+        );
+        let err = read_region_at(&data, 2, u64::MAX / 2).unwrap_err();
+        assert!(matches!(
+            err,
+            MonolithicaError::BlobTooShort { offset: 2, available, .. } if available == data.len() as u64 - 2
+        ));
+    }
+
+    #[cfg(any(unix, windows))]
+    #[test]
+    fn positioned_file_reads() {
+        let (blob, _) = build_fixture("read_at_positioned_file_reads");
+        let mut file = File::open(&blob).unwrap();
+        file.seek(SeekFrom::Start(3)).unwrap();
+        let magic = read_region_at(&file, 0, 4).unwrap();
+        assert_eq!(magic, crate::format::MAGIC);
+        #[cfg(unix)]
+        assert_eq!(file.stream_position().unwrap(), 3);
+    }
+
+    #[test]
+    fn shared_across_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Archive>();
+        assert_send_sync::<AssetIndexer<'static>>();
+
+        let (blob, index) = build_fixture("read_at_shared_across_threads");
+        let archive = Arc::new(Archive::open(&blob, &index).unwrap());
+        let content = fs::read_to_string(&index).unwrap();
+        let indexer = Arc::new(AssetIndexer::parse(&content).unwrap().into_owned());
+        let file = Arc::new(fs::File::open(&blob).unwrap());
+
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let (archive, indexer, file) = (archive.clone(), indexer.clone(), file.clone());
+                thread::spawn(move || {
+                    let (path, expected) = match i % 2 {
+                        0 => ("index.html", &b"<html></html>"[..]),
+                        _ => ("js/app.js", &b"console.log(1);"[..]),
+                    };
+                    for _ in 0..100 {
+                        assert_eq!(archive.get(path).unwrap().unwrap(), expected);
+                        let asset = indexer.locate_asset(path).unwrap();
+                        assert_eq!(indexer.read_asset_at(&*file, asset).unwrap(), expected);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use crate::{
+    atomic::TempPath,
+    error::IoContext,
+    format::{Header, HEADER_LEN},
+    packer::Source,
+    progress::{self, Progress},
+    ArchiveOptions, ArchiveSummary, AssetArchiver, MonolithicaError, Result,
+};
+use std::{
+    fs::{self, File},
+    io::{Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+/// The path of shard `shard` of the blob at `blob_path`, see
+/// [`ArchiveOptions::shard_size`]: `blob_path` itself for the first shard,
+/// `blob_path` with a `.1`, `.2`, ... suffix for the next ones.
+pub fn shard_path(blob_path: &Path, shard: u32) -> PathBuf {
+    if shard == 0 {
+        return blob_path.to_owned();
+    }
+    let mut name = blob_path.file_name().unwrap_or_default().to_owned();
+    name.push(format!(".{shard}"));
+    blob_path.with_file_name(name)
+}
+
+impl AssetArchiver {
+    /// [`AssetArchiver::create_archive_reporting`] with
+    /// [`ArchiveOptions::shard_size`] set. Every shard is written to a
+    /// temporary file and renamed into place once the index is complete.
+    pub(crate) fn create_sharded_archive(
+        source: Source,
+        blob_path: &Path,
+        blob_index_path: &Path,
+        overwrite_existing: bool,
+        options: &ArchiveOptions,
+        mut progress: Option<Progress>,
+    ) -> Result<ArchiveSummary> {
+        if options.shard_size.is_some_and(|size| size <= HEADER_LEN) {
+            tracing::error!("shard size too small");
+            return Err(MonolithicaError::InvalidInput(format!(
+                "shard size must exceed the {HEADER_LEN} bytes of the header"
+            )));
+        }
+        if options.blob_compression.is_some() {
+            return Err(MonolithicaError::Unsupported(
+                "compressed blobs can't be sharded".to_owned(),
+            ));
+        }
+        Self::check_path(blob_path, overwrite_existing)?;
+        Self::check_path(blob_index_path, overwrite_existing)?;
+
+        let mut shards = vec![TempPath::new(blob_path)];
+        let blob_file = create_shard(&shards[0])?;
+        let mut next_shard = |file: &mut File, data_len: u64| -> Result<()> {
+            finish_shard(file, data_len, options)?;
+            let path = shard_path(blob_path, shards.len() as u32);
+            Self::check_path(&path, overwrite_existing)?;
+            let tmp = TempPath::new(&path);
+            *file = create_shard(&tmp)?;
+            shards.push(tmp);
+            Ok(())
+        };
+        let mut packer = Self::pack(
+            blob_file,
+            source,
+            options,
+            progress::reborrow(&mut progress),
+            Some(&mut next_shard),
+        )?;
+        finish_shard(&mut packer.blob, packer.offset - HEADER_LEN, options)?;
+        let (entries, mut summary) = packer.finish();
+        for shard in &shards {
+            summary.blob_size += fs::metadata(shard.tmp()).at(shard.tmp())?.len();
+        }
+
+        let blob_index_tmp = TempPath::new(blob_index_path);
+        let mut blob_index_file = File::create(blob_index_tmp.tmp()).at(blob_index_tmp.tmp())?;
+        Self::write_index_of(&entries, &summary, &mut blob_index_file, options, progress)?;
+        blob_index_file.sync_all()?;
+        drop(blob_index_file);
+
+        let shard_count = shards.len() as u32;
+        for shard in shards {
+            shard.persist()?;
+        }
+        blob_index_tmp.persist()?;
+
+        // Leftovers of a larger archive previously written to `blob_path`.
+        for shard in shard_count.. {
+            let path = shard_path(blob_path, shard);
+            if !path.is_file() {
+                break;
+            }
+            fs::remove_file(&path).at(&path)?;
+        }
+        Ok(summary)
+    }
+}
+
+fn create_shard(tmp: &TempPath) -> Result<File> {
+    let mut file = File::create(tmp.tmp()).at(tmp.tmp())?;
+    file.seek(SeekFrom::Start(HEADER_LEN))?;
+    Ok(file)
+}
+
+/// Writes the header of a shard holding `data_len` bytes of assets.
+fn finish_shard(file: &mut File, data_len: u64, options: &ArchiveOptions) -> Result<()> {
+    file.seek(SeekFrom::Start(0))?;
+    Header {
+        flags: options.header_flags(),
+        compression: None,
+        data_len,
+    }
+    .write(file)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_util::{fixture_dir, write_file},
+        Archive,
+    };
+
+    #[test]
+    fn sharded_archive() {
+        let root = fixture_dir("shard_sharded_archive");
+        write_file(&root.join("src/a.bin"), [b'a'; 40]);
+        write_file(&root.join("src/b.bin"), [b'b'; 40]);
+        write_file(&root.join("src/c.bin"), [b'c'; 100]);
+        write_file(&root.join("src/d.bin"), [b'd'; 10]);
+        let (blob, index) = (root.join("test.blob"), root.join("test.blob.idx"));
+        let options = ArchiveOptions {
+            shard_size: Some(HEADER_LEN + 90),
+            deterministic: true,
+            ..Default::default()
+        };
+        let summary = AssetArchiver::create_archive_with_options(
+            root.join("src"),
+            &blob,
+            &index,
+            false,
+            &options,
+        )
+        .unwrap();
+
+        // a and b fit together, c is too large for any shard, d starts a new
+        // one after it.
+        let archive = Archive::open(&blob, &index).unwrap();
+        let shard_of = |path| archive.locate_asset(path).unwrap().shard;
+        assert_eq!(
+            ["a.bin", "b.bin", "c.bin", "d.bin"].map(shard_of),
+            [0, 0, 1, 2]
+        );
+        let lens =
+            [0, 1, 2, 3].map(|shard| fs::metadata(shard_path(&blob, shard)).map(|m| m.len()).ok());
+        assert_eq!(lens, [Some(96), Some(116), Some(26), None]);
+        assert_eq!(summary.blob_size, 96 + 116 + 26);
+        assert_eq!(archive.get("c.bin").unwrap().unwrap(), [b'c'; 100]);
+        assert_eq!(archive.get("d.bin").unwrap().unwrap(), [b'd'; 10]);
+        assert!(fs::read_to_string(&index).unwrap().contains("//shard=2"));
+
+        // Rebuilding with fewer shards removes the extra ones.
+        let options = ArchiveOptions {
+            shard_size: Some(1 << 20),
+            ..options
+        };
+        AssetArchiver::create_archive_with_options(root.join("src"), &blob, &index, true, &options)
+            .unwrap();
+        assert!(!shard_path(&blob, 1).exists());
+        let archive = Archive::open(&blob, &index).unwrap();
+        assert_eq!(archive.get("d.bin").unwrap().unwrap(), [b'd'; 10]);
+
+        let options = ArchiveOptions {
+            shard_size: Some(HEADER_LEN),
+            ..Default::default()
+        };
+        let err = AssetArchiver::create_archive_with_options(
+            root.join("src"),
+            &blob,
+            &index,
+            true,
+            &options,
+        )
+        .unwrap_err();
+        assert!(matches!(err, MonolithicaError::InvalidInput(_)), "{err}");
+    }
+}
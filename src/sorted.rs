@@ -0,0 +1,217 @@
+use crate::{normalize_path, query, Asset, AssetIndexer, Result};
+
+/// An index kept as a `Vec` of owned entries sorted by path, looked up by
+/// binary search.
+///
+/// Compared to [`AssetIndexer`] it doesn't borrow the index content, takes
+/// less memory per entry and lists directories without scanning every path,
+/// at the cost of `O(log n)` rather than `O(1)` lookups. Prefer it for large
+/// archives, or when the index has to outlive the buffer it was read from.
+#[derive(Debug, Clone, Default)]
+pub struct SortedIndex {
+    entries: Vec<(String, Asset)>,
+}
+
+impl SortedIndex {
+    /// Parses a text index, see [`AssetIndexer::parse`].
+    pub fn parse(content: &str) -> Result<Self> {
+        Ok(AssetIndexer::parse(content)?.into())
+    }
+
+    /// Loads a binary index, see [`AssetIndexer::from_binary`].
+    pub fn from_binary(content: &[u8]) -> Result<Self> {
+        Ok(AssetIndexer::from_binary(content)?.into())
+    }
+
+    /// Loads a JSON index, see [`AssetIndexer::from_json`].
+    #[cfg(feature = "serde")]
+    pub fn from_json(content: &str) -> Result<Self> {
+        Ok(AssetIndexer::from_json(content)?.into())
+    }
+
+    fn position(&self, path: &str) -> Option<usize> {
+        self.entries
+            .binary_search_by(|(entry, _)| entry.as_str().cmp(path))
+            .ok()
+    }
+
+    pub fn locate_asset(&self, path: &str) -> Option<&Asset> {
+        Some(&self.entries[self.position(path)?].1)
+    }
+
+    /// Like [`SortedIndex::locate_asset`], but normalizes `path` first, see
+    /// [`AssetIndexer::locate_normalized`].
+    pub fn locate_normalized(&self, path: &str) -> Option<&Asset> {
+        self.locate_asset(&normalize_path(path)?)
+    }
+
+    /// See [`AssetIndexer::resolve`].
+    pub fn resolve(&self, request_path: &str) -> Option<(&str, &Asset)> {
+        self.nth(self.position(&normalize_path(request_path)?)?)
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.position(path).is_some()
+    }
+
+    /// The entry at position `i` in path order, see [`AssetIndexer::nth`].
+    pub fn nth(&self, i: usize) -> Option<(&str, &Asset)> {
+        let (path, asset) = self.entries.get(i)?;
+        Some((path.as_str(), asset))
+    }
+
+    /// Iterates over all entries, sorted by path.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Asset)> {
+        self.entries
+            .iter()
+            .map(|(path, asset)| (path.as_str(), asset))
+    }
+
+    /// The paths, sorted.
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|(path, _)| path.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The entries whose path starts with `prefix`, found by binary search.
+    fn starting_with(&self, prefix: &str) -> &[(String, Asset)] {
+        let start = self
+            .entries
+            .partition_point(|(path, _)| path.as_str() < prefix);
+        let len = self.entries[start..].partition_point(|(path, _)| path.starts_with(prefix));
+        &self.entries[start..start + len]
+    }
+
+    /// See [`AssetIndexer::list_prefix`], without scanning every path.
+    pub fn list_prefix(&self, prefix: &str) -> Vec<&str> {
+        self.starting_with(&query::dir_prefix(prefix))
+            .iter()
+            .map(|(path, _)| path.as_str())
+            .collect()
+    }
+
+    /// See [`AssetIndexer::list_dir`].
+    pub fn list_dir(&self, prefix: &str) -> Vec<&str> {
+        let under = self.starting_with(&query::dir_prefix(prefix));
+        query::list_dir(under.iter().map(|(path, _)| path.as_str()), prefix)
+    }
+
+    /// See [`AssetIndexer::find`].
+    pub fn find(&self, pattern: &str) -> Result<Vec<(&str, &Asset)>> {
+        query::find(self.iter(), pattern)
+    }
+}
+
+impl From<AssetIndexer<'_>> for SortedIndex {
+    fn from(indexer: AssetIndexer<'_>) -> Self {
+        let mut entries: Vec<_> = indexer
+            .asset_map
+            .into_iter()
+            .map(|(path, asset)| (path.into_owned(), asset))
+            .collect();
+        entries.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        entries.shrink_to_fit();
+        Self { entries }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{format, IndexFormat};
+
+    fn entries(count: u64) -> Vec<(String, Asset)> {
+        (0..count)
+            .map(|i| {
+                let asset = Asset {
+                    offset: i * 100,
+                    len: 100,
+                    original_len: 100,
+                    ..Default::default()
+                };
+                (format!("dir{}/sub{}/file{i}.js", i % 100, i % 7), asset)
+            })
+            .collect()
+    }
+
+    fn text_index(entries: &[(String, Asset)]) -> String {
+        let mut text = Vec::new();
+        format::write_index(&mut text, entries, IndexFormat::Text).unwrap();
+        String::from_utf8(text).unwrap()
+    }
+
+    #[test]
+    fn lookup() {
+        let text = "index.html//16//13//text/html\n\
+            js/app.js//29//15//application/javascript\n\
+            js/lib/util.js//44//10//-\n\
+            json/data.json//54//2//application/json\n";
+        let index = SortedIndex::parse(text).unwrap();
+        assert_eq!(index.len(), 4);
+        assert_eq!(index.locate_asset("js/app.js").unwrap().offset, 29);
+        assert_eq!(index.locate_normalized("/js/./app.js").unwrap().len, 15);
+        let (path, asset) = index.resolve("\\json\\data.json").unwrap();
+        assert_eq!((path, asset.offset), ("json/data.json", 54));
+        assert!(index.resolve("js/lib/../app.js").is_none());
+        assert!(index.locate_asset("js").is_none());
+        assert!(!index.contains("missing"));
+        assert_eq!(
+            index.paths().collect::<Vec<_>>(),
+            [
+                "index.html",
+                "js/app.js",
+                "js/lib/util.js",
+                "json/data.json"
+            ]
+        );
+        assert_eq!(index.list_prefix("js"), ["js/app.js", "js/lib/util.js"]);
+        assert_eq!(index.list_prefix("js/"), index.list_prefix("js"));
+        assert_eq!(index.list_dir(""), ["index.html", "js/", "json/"]);
+        assert_eq!(index.list_dir("js/"), ["app.js", "lib/"]);
+        assert_eq!(index.find("**/*.js").unwrap().len(), 2);
+
+        let all = entries(1000);
+        let index = SortedIndex::parse(&text_index(&all)).unwrap();
+        for (path, asset) in &all {
+            assert_eq!(index.locate_asset(path), Some(asset));
+        }
+        assert_eq!(index.list_prefix("dir7").len(), 10);
+    }
+
+    /// Compares building and looking up with [`AssetIndexer`], run with
+    /// `cargo test --release -- --ignored --nocapture bench_sorted_index`.
+    #[test]
+    #[ignore = "benchmark, run with --ignored --nocapture"]
+    fn bench_sorted_index() {
+        let all = entries(100_000);
+        let text = text_index(&all);
+
+        let start = std::time::Instant::now();
+        let hashed = AssetIndexer::parse(&text).unwrap();
+        let hashed_build = start.elapsed();
+        let start = std::time::Instant::now();
+        let sorted = SortedIndex::parse(&text).unwrap();
+        let sorted_build = start.elapsed();
+
+        let start = std::time::Instant::now();
+        for (path, _) in &all {
+            assert!(hashed.locate_asset(path).is_some());
+        }
+        let hashed_lookup = start.elapsed();
+        let start = std::time::Instant::now();
+        for (path, _) in &all {
+            assert!(sorted.locate_asset(path).is_some());
+        }
+        let sorted_lookup = start.elapsed();
+
+        println!("AssetIndexer: build {hashed_build:?}, 100k lookups {hashed_lookup:?}");
+        println!("SortedIndex: build {sorted_build:?}, 100k lookups {sorted_lookup:?}");
+    }
+}
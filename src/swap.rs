@@ -0,0 +1,154 @@
+use crate::Archive;
+#[cfg(feature = "std-fs")]
+use crate::Result;
+#[cfg(feature = "std-fs")]
+use std::path::Path;
+use std::sync::{Arc, PoisonError, RwLock};
+
+/// An archive that can be replaced while it's being read, so a long-running
+/// server can deploy new assets without restarting.
+///
+/// Readers take the current archive with [`SwappableArchive::load`] and read
+/// from that [`Arc`] for as long as they hold it, e.g. for the whole of one
+/// response, so a swap never changes the archive under a read in progress.
+/// Installing a new archive doesn't wait for them either: the previous one
+/// is dropped, closing its files, once its last reader lets go of it. The
+/// lock is only held to clone or replace the `Arc`, never while reading.
+///
+/// ```no_run
+/// # #[cfg(not(feature = "std-fs"))]
+/// # fn main() {}
+/// # #[cfg(feature = "std-fs")]
+/// # fn main() -> monolithica::Result<()> {
+/// use monolithica::SwappableArchive;
+///
+/// let archive = SwappableArchive::open("assets.blob", "assets.blob.idx")?;
+/// let page = archive.load().get("index.html")?;
+/// // After a deploy wrote new files:
+/// archive.reload("assets.v2.blob", "assets.v2.blob.idx")?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct SwappableArchive {
+    current: RwLock<Arc<Archive>>,
+}
+
+impl SwappableArchive {
+    pub fn new(archive: Archive) -> Self {
+        Self::from_shared(Arc::new(archive))
+    }
+
+    pub fn from_shared(archive: Arc<Archive>) -> Self {
+        Self {
+            current: RwLock::new(archive),
+        }
+    }
+
+    /// Opens the archive with [`Archive::open_checked`].
+    #[cfg(feature = "std-fs")]
+    pub fn open(blob_path: impl AsRef<Path>, blob_index_path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::new(Archive::open_checked(
+            blob_path,
+            blob_index_path,
+        )?))
+    }
+
+    /// The archive installed last.
+    pub fn load(&self) -> Arc<Archive> {
+        let current = self.current.read().unwrap_or_else(PoisonError::into_inner);
+        Arc::clone(&current)
+    }
+
+    /// Installs `archive` for the loads to come, returning the previous one.
+    pub fn store(&self, archive: Archive) -> Arc<Archive> {
+        self.store_shared(Arc::new(archive))
+    }
+
+    pub fn store_shared(&self, archive: Arc<Archive>) -> Arc<Archive> {
+        let mut current = self.current.write().unwrap_or_else(PoisonError::into_inner);
+        std::mem::replace(&mut current, archive)
+    }
+
+    /// Opens an archive with [`Archive::open_checked`] and installs it,
+    /// returning the previous one. The current archive stays in place if the
+    /// new one fails to open, so a broken deploy keeps serving the old assets.
+    #[cfg(feature = "std-fs")]
+    pub fn reload(
+        &self,
+        blob_path: impl AsRef<Path>,
+        blob_index_path: impl AsRef<Path>,
+    ) -> Result<Arc<Archive>> {
+        let archive = Archive::open_checked(blob_path, blob_index_path)?;
+        Ok(self.store(archive))
+    }
+}
+
+impl From<Archive> for SwappableArchive {
+    fn from(archive: Archive) -> Self {
+        Self::new(archive)
+    }
+}
+
+#[cfg(all(test, feature = "std-fs"))]
+mod tests {
+    use super::*;
+    use crate::{
+        test_util::{fixture_dir, write_file},
+        AssetArchiver,
+    };
+    use std::thread;
+
+    #[test]
+    fn swap_while_reading() {
+        let root = fixture_dir("swap_swap_while_reading");
+        let build = |name: &str, content: &str| {
+            write_file(&root.join(name).join("index.html"), content);
+            let (blob, index) = (
+                root.join(format!("{name}.blob")),
+                root.join(format!("{name}.idx")),
+            );
+            AssetArchiver::create_archive(root.join(name), &blob, &index, false).unwrap();
+            (blob, index)
+        };
+        let (v1_blob, v1_index) = build("v1", "one");
+        let (v2_blob, v2_index) = build("v2", "two");
+
+        let swappable = SwappableArchive::open(&v1_blob, &v1_index).unwrap();
+        let in_flight = swappable.load();
+        let previous = swappable.reload(&v2_blob, &v2_index).unwrap();
+        assert!(Arc::ptr_eq(&previous, &in_flight));
+        assert_eq!(in_flight.get_string("index.html").unwrap().unwrap(), "one");
+        assert_eq!(
+            swappable.load().get_string("index.html").unwrap().unwrap(),
+            "two"
+        );
+
+        // A failed reload keeps the current archive.
+        assert!(swappable
+            .reload(root.join("missing.blob"), &v2_index)
+            .is_err());
+        assert_eq!(
+            swappable.load().get_string("index.html").unwrap().unwrap(),
+            "two"
+        );
+
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..100 {
+                        let page = swappable.load().get_string("index.html").unwrap().unwrap();
+                        assert!(page == "one" || page == "two");
+                    }
+                });
+            }
+            for i in 0..100 {
+                let (blob, index) = if i % 2 == 0 {
+                    (&v1_blob, &v1_index)
+                } else {
+                    (&v2_blob, &v2_index)
+                };
+                swappable.reload(blob, index).unwrap();
+            }
+        });
+    }
+}
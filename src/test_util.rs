@@ -0,0 +1,31 @@
+use crate::AssetArchiver;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+pub(crate) fn fixture_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join("monolithica-tests").join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+pub(crate) fn write_file(path: &Path, content: impl AsRef<[u8]>) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, content).unwrap();
+}
+
+/// Archives a small tree of known files, returning the blob and index paths.
+pub(crate) fn build_fixture(name: &str) -> (PathBuf, PathBuf) {
+    let root = fixture_dir(name);
+    let src = root.join("src");
+    write_file(&src.join(".rustc_info.json"), r#"{"rustc":"1.0"}"#);
+    write_file(&src.join("index.html"), "<html></html>");
+    write_file(&src.join("js/app.js"), "console.log(1);");
+
+    let blob = root.join("test.blob");
+    let index = root.join("test.blob.idx");
+    AssetArchiver::create_archive(&src, &blob, &index, true).unwrap();
+    (blob, index)
+}
@@ -0,0 +1,290 @@
+#[cfg(feature = "std-fs")]
+use crate::AssetArchiver;
+use crate::{format::CHECKSUM_SHA256, read_at::read_region_at, Archive, MonolithicaError, Result};
+use sha2::{Digest, Sha256};
+#[cfg(feature = "std-fs")]
+use std::path::Path;
+
+/// Result of [`Archive::verify`], listing every problem found rather than
+/// stopping at the first one.
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    /// Number of index entries checked, variants included.
+    pub entries_checked: usize,
+    /// Number of assets whose checksum was checked.
+    pub checksums_checked: usize,
+    /// Whether the whole archive matches its checksum, `None` if it has none,
+    /// see [`Archive::verify_checksum`].
+    pub archive_checksum: Option<bool>,
+    pub problems: Vec<VerifyProblem>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyProblem {
+    /// The entry extends past the end of the blob data.
+    OutOfBounds { path: String, offset: u64, len: u64 },
+    /// The entry partially overlaps another one. Entries sharing exactly the
+    /// same bytes are not reported.
+    Overlap { path: String, other: String },
+    /// Bytes of the blob no entry refers to.
+    Gap { offset: u64, len: u64 },
+    /// The decoded bytes don't match the recorded checksum.
+    ChecksumMismatch { path: String },
+    /// The asset couldn't be read or decoded.
+    Unreadable { path: String, error: String },
+    /// The single-file archive doesn't match its checksum.
+    ArchiveChecksumMismatch,
+}
+
+impl Archive {
+    /// Checks that the index and the blob agree: every entry lies within the
+    /// blob, entries don't overlap and together cover the blob, and assets
+    /// match their checksums where the index has them. Sharded archives can't
+    /// be verified yet.
+    pub fn verify(&self) -> Result<VerifyReport> {
+        if !self.shards.is_empty() {
+            return Err(MonolithicaError::Unsupported(
+                "can't verify a sharded blob".to_owned(),
+            ));
+        }
+        let mut report = VerifyReport::default();
+
+        let mut regions = Vec::new();
+        for (path, asset) in &self.assets {
+            regions.push((asset.offset, asset.len, path.as_str()));
+            for variant in &asset.variants {
+                regions.push((variant.offset, variant.len, path.as_str()));
+            }
+        }
+        regions.sort_unstable();
+        report.entries_checked = regions.len();
+
+        let data = &self.data_range;
+        let mut covered_to = data.start;
+        // The region reaching furthest so far, the one that set `covered_to`,
+        // which any later region starting before its end overlaps.
+        let mut furthest: Option<(u64, u64, &str)> = None;
+        for &(offset, len, path) in &regions {
+            let end = offset.saturating_add(len);
+            if offset < data.start || end > data.end {
+                report.problems.push(VerifyProblem::OutOfBounds {
+                    path: path.to_owned(),
+                    offset,
+                    len,
+                });
+                continue;
+            }
+
+            if let Some((other_offset, other_len, other_path)) = furthest {
+                let same_bytes = (other_offset, other_len) == (offset, len);
+                if offset < other_offset + other_len && !same_bytes {
+                    report.problems.push(VerifyProblem::Overlap {
+                        path: path.to_owned(),
+                        other: other_path.to_owned(),
+                    });
+                }
+            }
+            if offset > covered_to {
+                report.problems.push(VerifyProblem::Gap {
+                    offset: covered_to,
+                    len: offset - covered_to,
+                });
+            }
+            if furthest.is_none_or(|(other_offset, other_len, _)| end > other_offset + other_len) {
+                furthest = Some((offset, len, path));
+            }
+            covered_to = covered_to.max(end);
+        }
+        if covered_to < data.end {
+            report.problems.push(VerifyProblem::Gap {
+                offset: covered_to,
+                len: data.end - covered_to,
+            });
+        }
+
+        let mut paths: Vec<_> = self.assets.keys().collect();
+        paths.sort_unstable();
+        for path in paths {
+            let asset = &self.assets[path];
+            let Some(crc32) = asset.crc32 else {
+                continue;
+            };
+            if asset.offset.saturating_add(asset.len) > data.end {
+                continue;
+            }
+
+            report.checksums_checked += 1;
+            let bytes = self
+                .read_region(asset.shard, asset.offset, asset.len)
+                .and_then(|bytes| self.decrypt(path, asset, bytes))
+                .and_then(|bytes| asset.decode(bytes));
+            match bytes {
+                Ok(bytes) if crc32fast::hash(&bytes) == crc32 => {}
+                Ok(_) => report.problems.push(VerifyProblem::ChecksumMismatch {
+                    path: path.to_owned(),
+                }),
+                Err(e) => report.problems.push(VerifyProblem::Unreadable {
+                    path: path.to_owned(),
+                    error: format!("{e:#}"),
+                }),
+            }
+        }
+
+        report.archive_checksum = self.verify_checksum()?;
+        if report.archive_checksum == Some(false) {
+            report.problems.push(VerifyProblem::ArchiveChecksumMismatch);
+        }
+        Ok(report)
+    }
+
+    /// Hashes the whole single-file archive and compares it to the checksum
+    /// stored in it, a quick way to tell whether it was damaged in transit
+    /// without checking each asset. `Ok(None)` if there is nothing to compare
+    /// to: two-file archives and older single-file ones have no checksum.
+    /// Checksums of algorithms this version doesn't know are skipped too.
+    pub fn verify_checksum(&self) -> Result<Option<bool>> {
+        let Some((checksum, len)) = &self.checksum else {
+            return Ok(None);
+        };
+        if checksum.algorithm != CHECKSUM_SHA256 {
+            tracing::warn!("unknown checksum algorithm: {}", checksum.algorithm);
+            return Ok(None);
+        }
+
+        const CHUNK: u64 = 1 << 20;
+        let mut hasher = Sha256::new();
+        let mut offset = 0;
+        while offset < *len {
+            let chunk_len = CHUNK.min(len - offset);
+            hasher.update(read_region_at(&self.blob, offset, chunk_len)?);
+            offset += chunk_len;
+        }
+        Ok(Some(hasher.finalize()[..] == checksum.digest[..]))
+    }
+}
+
+#[cfg(feature = "std-fs")]
+impl AssetArchiver {
+    /// Opens the archive and runs [`Archive::verify`] on it.
+    pub fn verify_archive(
+        blob_path: impl AsRef<Path>,
+        blob_index_path: impl AsRef<Path>,
+    ) -> Result<VerifyReport> {
+        Archive::open(blob_path, blob_index_path)?.verify()
+    }
+}
+
+#[cfg(all(test, feature = "std-fs"))]
+mod tests {
+    use super::*;
+    use crate::{
+        format::{self, FOOTER_LEN},
+        test_util::{build_fixture, fixture_dir, write_file},
+        Asset, AssetIndexer, IndexFormat,
+    };
+    use std::fs;
+
+    #[test]
+    fn verify_intact_archive() {
+        let (blob, index) = build_fixture("verify_intact_archive");
+        let report = AssetArchiver::verify_archive(&blob, &index).unwrap();
+        assert!(report.is_ok(), "{:?}", report.problems);
+        assert_eq!(report.entries_checked, 3);
+        assert_eq!(report.checksums_checked, 3);
+    }
+
+    #[test]
+    fn single_archive_checksum() {
+        let root = fixture_dir("verify_single_archive_checksum");
+        write_file(&root.join("src/index.html"), "<html></html>");
+        let path = root.join("test.mono");
+        AssetArchiver::create_single_archive(root.join("src"), &path, false).unwrap();
+        let archive = Archive::open_single(&path).unwrap();
+        assert_eq!(archive.verify_checksum().unwrap(), Some(true));
+        assert_eq!(archive.verify().unwrap().archive_checksum, Some(true));
+
+        // Corrupt the index, which no per-asset check covers.
+        let mut bytes = fs::read(&path).unwrap();
+        let at = bytes.len() - FOOTER_LEN as usize - 40;
+        bytes[at] ^= 0x20;
+        fs::write(&path, &bytes).unwrap();
+        let archive = Archive::open_single(&path).unwrap();
+        assert_eq!(archive.verify_checksum().unwrap(), Some(false));
+        let report = archive.verify().unwrap();
+        assert_eq!(report.problems, [VerifyProblem::ArchiveChecksumMismatch]);
+
+        let (blob, index) = build_fixture("verify_single_archive_checksum_two_files");
+        let archive = Archive::open(&blob, &index).unwrap();
+        assert_eq!(archive.verify_checksum().unwrap(), None);
+    }
+
+    #[test]
+    fn verify_reports_all_problems() {
+        let (blob, index) = build_fixture("verify_reports_all_problems");
+        let content = fs::read_to_string(&index).unwrap();
+        let indexer = AssetIndexer::parse(&content).unwrap();
+        let mut entries: Vec<(String, Asset)> = indexer
+            .asset_map
+            .iter()
+            .map(|(path, asset)| (path.to_string(), asset.clone()))
+            .collect();
+        entries.sort_by_key(|(_, asset)| asset.offset);
+
+        // shrink the first entry, leaving a gap, and corrupt the second
+        entries[0].1.len -= 1;
+        let mut bytes = fs::read(&blob).unwrap();
+        bytes[entries[1].1.offset as usize] ^= 0xff;
+        fs::write(&blob, &bytes).unwrap();
+        let blob_len = bytes.len() as u64;
+        entries.push((
+            "past-end".to_owned(),
+            Asset {
+                offset: blob_len - 2,
+                len: 5,
+                ..Default::default()
+            },
+        ));
+
+        let mut content = Vec::new();
+        format::write_index(&mut content, &entries, IndexFormat::Text).unwrap();
+        fs::write(&index, content).unwrap();
+
+        let report = AssetArchiver::verify_archive(&blob, &index).unwrap();
+        let first = &entries[0].1;
+        assert!(report.problems.contains(&VerifyProblem::Gap {
+            offset: first.offset + first.len,
+            len: 1
+        }));
+        assert!(report.problems.contains(&VerifyProblem::ChecksumMismatch {
+            path: entries[0].0.clone()
+        }));
+        assert!(report.problems.contains(&VerifyProblem::ChecksumMismatch {
+            path: entries[1].0.clone()
+        }));
+        assert!(report.problems.contains(&VerifyProblem::OutOfBounds {
+            path: "past-end".to_owned(),
+            offset: blob_len - 2,
+            len: 5
+        }));
+        assert_eq!(report.problems.len(), 4, "{:?}", report.problems);
+    }
+
+    #[test]
+    fn nested_overlap() {
+        let entries = [("a.bin".to_owned(), vec![b'a'; 100])];
+        let (blob, index) = AssetArchiver::build_in_memory(entries, &Default::default()).unwrap();
+        let index = format!("{index}dup.bin//16//100//-\nb.bin//20//10//-\nc.bin//40//10//-\n");
+        let report = Archive::from_vec(blob, index).unwrap().verify().unwrap();
+        let overlap = |path: &str| VerifyProblem::Overlap {
+            path: path.to_owned(),
+            other: "a.bin".to_owned(),
+        };
+        assert_eq!(report.problems, [overlap("b.bin"), overlap("c.bin")]);
+    }
+}